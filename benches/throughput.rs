@@ -0,0 +1,68 @@
+//! Encode and decode throughput at a few input sizes and thread counts.
+//!
+//! cargo bench --bench throughput
+//!
+//! Criterion reports each result in MB/s of input. The 10 MB cases take
+//! minutes per sample; run `cargo bench --bench throughput -- 1KB` or
+//! `-- 1MB` to skip them while tuning.
+
+use babel_encoding::{BabelCodec, Threads};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const SIZES: [(&str, usize); 3] = [("1KB", 1024), ("1MB", 1024 * 1024), ("10MB", 10 * 1024 * 1024)];
+
+// One thread, one per physical core, then every logical CPU, so both the
+// parallel speedup and what hyperthreading adds on top of it are visible
+fn thread_counts() -> Vec<usize> {
+    let mut counts = vec![1, Threads::Physical.count(), Threads::Logical.count()];
+    counts.dedup();
+    counts
+}
+
+fn codec(jobs: usize) -> BabelCodec {
+    BabelCodec::new().with_progress(false).with_threads(Threads::Fixed(jobs))
+}
+
+fn input(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    StdRng::seed_from_u64(len as u64).fill(&mut bytes[..]);
+    bytes
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode_bytes");
+    group.sample_size(10);
+    for (name, len) in SIZES {
+        let data = input(len);
+        group.throughput(Throughput::Bytes(len as u64));
+        for jobs in thread_counts() {
+            let codec = codec(jobs);
+            group.bench_with_input(BenchmarkId::new(name, format!("jobs={}", jobs)), &data, |b, data| {
+                b.iter(|| codec.encode_bytes(data).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode_bytes");
+    group.sample_size(10);
+    for (name, len) in SIZES {
+        let data = input(len);
+        let addresses = codec(*thread_counts().last().unwrap()).encode_bytes(&data).unwrap();
+        group.throughput(Throughput::Bytes(len as u64));
+        for jobs in thread_counts() {
+            let codec = codec(jobs);
+            group.bench_with_input(BenchmarkId::new(name, format!("jobs={}", jobs)), &addresses, |b, addresses| {
+                b.iter(|| codec.decode_bytes(addresses, len).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode, decode);
+criterion_main!(benches);
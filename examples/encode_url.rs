@@ -0,0 +1,22 @@
+//! Encode a remote file without downloading it to disk first.
+//!
+//! cargo run --example encode_url --features network -- <url> <output.babel>
+
+use babel_encoding::BabelCodec;
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} <url> <output.babel>", args[0]);
+        return;
+    }
+
+    let output = File::create(&args[2]).expect("failed to create output file");
+    match BabelCodec::new().encode_url(&args[1], BufWriter::new(output)) {
+        Ok(size) => println!("Encoded {} bytes", size),
+        Err(e) => eprintln!("Error encoding {}: {}", args[1], e),
+    }
+}
@@ -0,0 +1,133 @@
+use num_bigint::BigInt;
+use num_traits::Num;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::BabelError;
+use crate::library::{format_address, int_to_base36, split_address};
+use crate::location::{LibraryGeometry, Location};
+
+/// Where a page sits in the library: a base-36 key and its place in a hexagon.
+///
+/// Parses from and displays as `key:wall:shelf:volume:page`, each coordinate
+/// zero-padded to the width of its largest value in the default geometry, so
+/// `9`, `09` and `009` parse the same. Archives of another geometry write
+/// their addresses with [`Address::to_string_in`]. `parse` only checks that
+/// the key is uppercase base 36; [`Address::parse_in`] also refuses a location
+/// outside a geometry. Whether the key fits a page is known once the page
+/// length is.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Address {
+    pub key: BigInt,
+    pub location: Location,
+}
+
+impl FromStr for Address {
+    type Err = BabelError;
+
+    fn from_str(address: &str) -> Result<Self, BabelError> {
+        let (key, location) = split_address(address)?;
+        // num-bigint would also take lowercase and a leading sign, which no key is written with
+        if key.is_empty() || !key.bytes().all(|b| b.is_ascii_digit() || b.is_ascii_uppercase()) {
+            return Err(BabelError::InvalidRadix(address.to_string()));
+        }
+        let key = BigInt::from_str_radix(key, 36).expect("checked to be base-36 digits");
+        Ok(Address { key, location })
+    }
+}
+
+impl Address {
+    /// Parse `address`, refusing a location outside `geometry`.
+    pub fn parse_in(address: &str, geometry: &LibraryGeometry) -> Result<Self, BabelError> {
+        let parsed: Address = address.parse()?;
+        if !parsed.location.is_within(geometry) {
+            return Err(BabelError::LocationOutOfRange { address: address.to_string(), geometry: *geometry });
+        }
+        Ok(parsed)
+    }
+
+    /// The address with each coordinate zero-padded to its width in `geometry`.
+    pub fn to_string_in(&self, geometry: &LibraryGeometry) -> String {
+        format_address(&int_to_base36(self.key.clone()), &self.location, geometry)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_in(&LibraryGeometry::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::BabelCodec;
+    use crate::library::{get_page, search_exact, PageSpace};
+    use crate::page_length::PageLength;
+
+    #[test]
+    fn single_digit_fields_read_as_numbers() {
+        let space = PageSpace::new(20, LibraryGeometry::default()).unwrap();
+        let page = "a page on page nine.";
+        let address = search_exact(page, &space, &Location { wall: 1, shelf: 2, volume: 3, page: 9 }).unwrap();
+        let written = address.to_string();
+        assert!(written.ends_with(":1:2:03:009"), "{}", written);
+
+        let short = written.replace(":03:009", ":3:9");
+        let parsed: Address = short.parse().unwrap();
+        assert_eq!(parsed, address);
+        assert_eq!(parsed.to_string(), written);
+        assert_eq!(get_page(&parsed, &space).unwrap(), page);
+
+        let outside: Address = written.replace(":009", ":410").parse().unwrap();
+        assert!(matches!(get_page(&outside, &space), Err(BabelError::LocationOutOfRange { .. })));
+    }
+
+    #[test]
+    fn addresses_are_padded_to_their_geometry() {
+        let geometry = LibraryGeometry::new(20, 20, 200, 2000).unwrap();
+        let space = PageSpace::new(20, geometry).unwrap();
+        let address = search_exact("a page far along....", &space, &Location { wall: 19, shelf: 3, volume: 12, page: 1992 }).unwrap();
+        let written = address.to_string_in(&geometry);
+        assert!(written.ends_with(":19:03:012:1992"), "{}", written);
+        assert_eq!(written.parse::<Address>().unwrap(), address);
+
+        let codec = BabelCodec::default()
+            .with_progress(false)
+            .with_page_length(PageLength::new(20).unwrap())
+            .with_geometry(geometry)
+            .with_seed(Some(235));
+        for address in codec.encode_bytes(&[0x5a; 200]).unwrap() {
+            let widths: Vec<usize> = address.split(':').skip(1).map(str::len).collect();
+            assert_eq!(widths, geometry.field_widths(), "{}", address);
+        }
+    }
+
+    #[test]
+    fn keys_must_be_uppercase_base_36() {
+        assert!("1A2Z:0:0:00:000".parse::<Address>().is_ok());
+        for address in ["1a2z:0:0:00:000", "1A_2Z:0:0:00:000", "-1A:0:0:00:000", "+1A:0:0:00:000", "1A 2:0:0:00:000"] {
+            match address.parse::<Address>() {
+                Err(BabelError::InvalidRadix(found)) => assert_eq!(found, address),
+                other => panic!("{} parsed as {:?}", address, other),
+            }
+        }
+    }
+
+    #[test]
+    fn parsing_in_a_geometry_checks_the_location() {
+        let geometry = LibraryGeometry::default();
+        assert!("K:9:9:99:999".parse::<Address>().is_ok());
+        for address in ["K:9:9:99:999", "K:4:0:00:000", "K:0:5:00:000", "K:0:0:32:000", "K:0:0:00:410"] {
+            match Address::parse_in(address, &geometry) {
+                Err(BabelError::LocationOutOfRange { address: found, .. }) => assert_eq!(found, address),
+                other => panic!("{} parsed as {:?}", address, other),
+            }
+        }
+        let parsed = Address::parse_in("K:3:4:31:409", &geometry).unwrap();
+        assert_eq!(parsed.location, Location { wall: 3, shelf: 4, volume: 31, page: 409 });
+        assert!(Address::parse_in("k:0:0:00:000", &geometry).is_err());
+        let lookup = BabelCodec::default().get_page_shared("K:9:9:99:999");
+        assert!(matches!(lookup, Err(BabelError::LocationOutOfRange { .. })));
+    }
+}
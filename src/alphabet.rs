@@ -0,0 +1,232 @@
+use crate::error::BabelError;
+use crate::library::{CHARSET, PAD_CHAR};
+
+/// The symbols used to spell bytes as base-N digits.
+///
+/// Most alphabets spell each byte as a pair: `b` becomes `symbols[b / N]`
+/// followed by `symbols[b % N]`, so they need at least 16 symbols for two
+/// digits to cover every byte. [`Alphabet::base29`] instead reads three bytes
+/// at a time as one number and writes it as five digits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alphabet {
+    id: String,
+    symbols: Vec<char>,
+    // Bytes read together as one number
+    block_bytes: usize,
+}
+
+/// How many bytes are spelled together, and how many symbols that takes.
+///
+/// A short last block takes the fewest symbols that can hold its bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Block {
+    bytes: u64,
+    chars: u64,
+    base: u64,
+}
+
+impl Block {
+    // Text mode: one character per byte
+    pub(crate) const TEXT: Block = Block { bytes: 1, chars: 1, base: 256 };
+
+    // Symbols needed for `bytes` bytes, at most a whole block
+    fn chars_for(self, bytes: u64) -> u64 {
+        let limit = 1u64 << (8 * bytes);
+        let mut chars = 0;
+        let mut reach = 1u64;
+        while reach < limit {
+            reach = reach.saturating_mul(self.base);
+            chars += 1;
+        }
+        chars
+    }
+
+    /// Characters `bytes` bytes are spelled with.
+    pub(crate) fn text_len(self, bytes: u64) -> u64 {
+        // Saturating, so a doctored size gives too many pages rather than a panic
+        (bytes / self.bytes).saturating_mul(self.chars).saturating_add(self.chars_for(bytes % self.bytes))
+    }
+
+    /// Bytes spelled by `chars` characters, or `None` if that many ends partway through a block.
+    pub(crate) fn bytes_in(self, chars: u64) -> Option<u64> {
+        let whole = chars / self.chars * self.bytes;
+        let rest = chars % self.chars;
+        (0..self.bytes).find(|&bytes| self.chars_for(bytes) == rest).map(|bytes| whole + bytes)
+    }
+
+    /// Bytes of every whole block whose last character is among the first `chars`.
+    pub(crate) fn bytes_before(self, chars: u64) -> u64 {
+        chars / self.chars * self.bytes
+    }
+
+    pub(crate) fn bytes(self) -> u64 {
+        self.bytes
+    }
+
+    pub(crate) fn chars(self) -> u64 {
+        self.chars
+    }
+}
+
+impl Alphabet {
+    /// The original scheme: `a`..`z`, base 26, so byte `b` is spelled
+    /// `('a' + b / 26, 'a' + b % 26)`. This is the default alphabet, and its
+    /// output must never change or existing archives would stop decoding.
+    pub fn letters26() -> Self {
+        Alphabet {
+            id: "letters26".to_string(),
+            symbols: ('a'..='z').collect(),
+            block_bytes: 1,
+        }
+    }
+
+    /// Hex spelled with the letters `a`..`p`.
+    pub fn hex16() -> Self {
+        Alphabet {
+            id: "hex16".to_string(),
+            symbols: ('a'..='p').collect(),
+            block_bytes: 1,
+        }
+    }
+
+    /// Every page character, base 29, with each three bytes read as one number
+    /// and written as five digits. That is 5 characters for 3 bytes where the
+    /// pair alphabets take 6, so an input fills about a sixth fewer pages; a
+    /// last block of one or two bytes takes two or four characters.
+    ///
+    /// The padding character is one of its digits, so trailing periods are
+    /// data and only the recorded size says where the bytes end.
+    pub fn base29() -> Self {
+        Alphabet {
+            id: "base29".to_string(),
+            symbols: CHARSET.chars().collect(),
+            block_bytes: 3,
+        }
+    }
+
+    /// The built-in alphabet recorded in a header as `id`, if there is one.
+    ///
+    /// Custom alphabets are only identified by a hash, so they cannot be rebuilt from it.
+    pub fn built_in(id: &str) -> Option<Self> {
+        [Alphabet::letters26(), Alphabet::hex16(), Alphabet::base29()].into_iter().find(|alphabet| alphabet.id == id)
+    }
+
+    /// Build an alphabet from a custom symbol set, identified by a hash of its symbols.
+    pub fn custom(symbols: &str) -> Result<Self, BabelError> {
+        let symbols: Vec<char> = symbols.chars().collect();
+        if symbols.len() < 16 {
+            return Err(BabelError::InvalidAlphabet(format!(
+                "need at least 16 symbols, got {}", symbols.len()
+            )));
+        }
+        for (i, &c) in symbols.iter().enumerate() {
+            if c == PAD_CHAR || !CHARSET.contains(c) {
+                return Err(BabelError::InvalidAlphabet(format!(
+                    "symbol {:?} is not a page character", c
+                )));
+            }
+            if symbols[..i].contains(&c) {
+                return Err(BabelError::InvalidAlphabet(format!("duplicate symbol {:?}", c)));
+            }
+        }
+
+        // FNV-1a keeps the id stable across builds and platforms
+        let mut hash: u32 = 0x811c9dc5;
+        for &c in &symbols {
+            hash ^= c as u32;
+            hash = hash.wrapping_mul(0x01000193);
+        }
+
+        Ok(Alphabet {
+            id: format!("custom-{:08x}", hash),
+            symbols,
+            block_bytes: 1,
+        })
+    }
+
+    /// Identifier recorded in the archive header.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn base(&self) -> usize {
+        self.symbols.len()
+    }
+
+    /// Characters this alphabet spells `bytes` bytes with.
+    pub fn text_len(&self, bytes: u64) -> u64 {
+        self.block().text_len(bytes)
+    }
+
+    /// Bytes of the whole blocks spelled within the first `chars` characters.
+    pub fn bytes_before(&self, chars: u64) -> u64 {
+        self.block().bytes_before(chars)
+    }
+
+    /// Characters each whole block of bytes is spelled with: two for the pair
+    /// alphabets, five for base29.
+    pub fn block_chars(&self) -> u64 {
+        self.block().chars()
+    }
+
+    pub(crate) fn symbol(&self, digit: usize) -> char {
+        self.symbols[digit]
+    }
+
+    pub(crate) fn digit(&self, c: char) -> Option<usize> {
+        self.symbols.iter().position(|&s| s == c)
+    }
+
+    pub(crate) fn block(&self) -> Block {
+        let bytes = self.block_bytes as u64;
+        let mut block = Block { bytes, chars: 0, base: self.base() as u64 };
+        block.chars = block.chars_for(bytes);
+        block
+    }
+
+    /// Whether trailing periods can only be padding, because no symbol is one.
+    pub(crate) fn pads_with_period(&self) -> bool {
+        !self.symbols.contains(&PAD_CHAR)
+    }
+
+    // Append the symbols for `bytes`, one block or the short last one
+    pub(crate) fn spell_block(&self, bytes: &[u8], text: &mut String) {
+        let base = self.base() as u64;
+        let mut value = bytes.iter().fold(0u64, |value, &b| value << 8 | u64::from(b));
+        let chars = self.block().chars_for(bytes.len() as u64) as usize;
+        // Least significant digit first, then written out the other way round
+        let mut digits = [0usize; 8];
+        for digit in &mut digits[..chars] {
+            *digit = (value % base) as usize;
+            value /= base;
+        }
+        text.extend(digits[..chars].iter().rev().map(|&digit| self.symbols[digit]));
+    }
+
+    // Append the bytes spelled by `chars`, a block or a short last block
+    // whose length `Block::bytes_in` accepts; `offset` is its position in the text
+    //
+    // Digits can spell numbers too big for the block's bytes, such as `zz` in
+    // letters26, and those never come from an encode, so they are rejected
+    // rather than wrapped.
+    pub(crate) fn read_block(&self, chars: &[char], offset: usize, bytes: &mut Vec<u8>) -> Result<(), BabelError> {
+        let invalid = || BabelError::InvalidBytePair { offset, pair: chars.iter().collect() };
+        let base = self.base() as u64;
+        let mut value = 0u64;
+        for &c in chars {
+            value = value * base + self.digit(c).ok_or_else(invalid)? as u64;
+        }
+        let count = self.block().bytes_in(chars.len() as u64).ok_or_else(invalid)? as usize;
+        if count < 8 && value >> (8 * count) != 0 {
+            return Err(invalid());
+        }
+        bytes.extend_from_slice(&value.to_be_bytes()[8 - count..]);
+        Ok(())
+    }
+}
+
+impl Default for Alphabet {
+    fn default() -> Self {
+        Alphabet::letters26()
+    }
+}
@@ -0,0 +1,1134 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use num_bigint::{BigInt, Sign};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::alphabet::Alphabet;
+use crate::compression::Compression;
+use crate::directory::ArchiveEntry;
+use crate::encryption::Encryption;
+use crate::error::BabelError;
+use crate::library::{format_address, int_to_base36, split_address};
+use crate::location::{LibraryGeometry, Location};
+use crate::metadata;
+use crate::spool::AddressSpool;
+use crate::temp_file::TempFile;
+use crate::text_mode::PayloadMode;
+
+/// Version written by this crate. Version 1 archives have no magic line and
+/// are otherwise read with the same layout.
+pub const FORMAT_VERSION: u32 = 2;
+
+// First line of every text or compressed-index archive since version 2, followed by the version
+const MAGIC: &str = "babel/";
+
+/// How an archive is laid out on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArchiveFormat {
+    /// Extension, size and `key=value` header lines followed by the addresses, one or more per line.
+    #[default]
+    Text,
+    /// A single JSON object holding the header fields and an `addresses` array,
+    /// easy to load from any language with a JSON parser.
+    Json,
+    /// A single JSON object holding the header and the base64 of the binary-packed index.
+    CompactJson,
+    /// The text header, ending in `index_compression=deflate` and `index_length=N`,
+    /// followed by N bytes of deflated address lines. The header stays readable
+    /// without inflating the index.
+    CompressedIndex,
+    /// Address lines only. The header, with its size and page checksums, is
+    /// spelled on the leading pages, so nothing is stored out of band.
+    SelfContained,
+}
+
+/// Metadata describing the encoded file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveHeader {
+    /// Format version the archive was read as; archives are always written as [`FORMAT_VERSION`].
+    #[serde(default = "legacy_version")]
+    pub(crate) version: u32,
+    pub(crate) extension: String,
+    /// File name of the original, dots and all, when it was encoded from a file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) name: Option<String>,
+    pub(crate) size: u64,
+    #[serde(default = "legacy_alphabet")]
+    pub(crate) alphabet: String,
+    /// Whether pages spell raw bytes or plain text.
+    #[serde(default, skip_serializing_if = "PayloadMode::is_bytes")]
+    pub(crate) payload: PayloadMode,
+    /// How the bytes on the pages were compressed; `size` counts them compressed.
+    #[serde(default, skip_serializing_if = "Compression::is_none")]
+    pub(crate) compression: Compression,
+    /// Salt and nonce the bytes on the pages were encrypted with, after compression.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) encryption: Option<Encryption>,
+    /// Modification time of the original file, in seconds since the unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) modified: Option<u64>,
+    /// Unix permission bits of the original file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) mode: Option<u32>,
+    /// Name and version of the tool that wrote the archive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) encoder: Option<String>,
+    /// When the archive was written, as an RFC 3339 UTC timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) created: Option<String>,
+    /// Free-text note supplied by whoever encoded the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) note: Option<String>,
+    /// File names, relative to the manifest, of the parts holding the addresses
+    /// of a split archive. Empty when the addresses follow the header.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) parts: Vec<String>,
+    /// Files of a directory archive, in the order their bytes follow one
+    /// another. Empty when the archive holds a single file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) files: Vec<ArchiveEntry>,
+    /// CRC-32 of the whole decoded file, checked once it is reassembled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) crc32: Option<u32>,
+    /// CRC-32 of each page's text, checked as the page is decoded. Empty when
+    /// the archive was written without page checksums.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) page_crcs: Vec<u32>,
+    /// How many of the addresses hold the file when filler pages follow them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pages: Option<u64>,
+    /// Characters per page, recorded only when it is not the default 3239.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) page_length: Option<usize>,
+    /// Shape of the library's hexagons, recorded only when it is not libraryofbabel.info's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) geometry: Option<LibraryGeometry>,
+    /// Set when a best-effort parse found no size line; `size` is then only
+    /// an upper bound filled in by the decoder.
+    #[serde(skip)]
+    pub(crate) size_unknown: bool,
+    /// Set for a self-contained archive until its header is read from its
+    /// leading pages; every other field is a placeholder until then.
+    #[serde(skip)]
+    pub(crate) self_contained: bool,
+}
+
+impl ArchiveHeader {
+    /// A header for `size` bytes with the given extension, spelled with `alphabet`.
+    ///
+    /// The encoder and creation time are filled in; everything else is unset.
+    pub fn new(extension: &str, size: u64, alphabet: &Alphabet) -> Self {
+        ArchiveHeader {
+            version: FORMAT_VERSION,
+            extension: extension.to_string(),
+            name: None,
+            size,
+            alphabet: alphabet.id().to_string(),
+            payload: PayloadMode::default(),
+            compression: Compression::None,
+            encryption: None,
+            modified: None,
+            mode: None,
+            encoder: Some(format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))),
+            created: Some(metadata::now_utc()),
+            note: None,
+            parts: Vec::new(),
+            files: Vec::new(),
+            crc32: None,
+            page_crcs: Vec::new(),
+            pages: None,
+            page_length: None,
+            geometry: None,
+            size_unknown: false,
+            self_contained: false,
+        }
+    }
+
+    /// Mark the pages as holding plain text rather than bytes.
+    pub fn with_payload(mut self, payload: PayloadMode) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Whether the header is still stored in the archive's leading pages.
+    ///
+    /// Only the addresses of such an archive are known after reading it; the
+    /// codec fills in the rest of the header when it decodes.
+    pub fn is_self_contained(&self) -> bool {
+        self.self_contained
+    }
+
+    /// Format version the archive was written in; 1 for archives without a magic line.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Extension of the original file, without the dot.
+    pub fn extension(&self) -> &str {
+        &self.extension
+    }
+
+    /// File name of the original, if it was encoded from a file.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Size of the original file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Id of the alphabet the pages were spelled with.
+    pub fn alphabet(&self) -> &str {
+        &self.alphabet
+    }
+
+    /// Whether pages spell raw bytes or plain text.
+    pub fn payload(&self) -> PayloadMode {
+        self.payload
+    }
+
+    /// How the bytes on the pages were compressed.
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Modification time of the original file, if it was preserved.
+    pub fn modified(&self) -> Option<u64> {
+        self.modified
+    }
+
+    /// Unix permission bits of the original file, if they were preserved.
+    pub fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+
+    /// Name and version of the tool that wrote the archive.
+    pub fn encoder(&self) -> Option<&str> {
+        self.encoder.as_deref()
+    }
+
+    /// When the archive was written, as an RFC 3339 UTC timestamp.
+    pub fn created(&self) -> Option<&str> {
+        self.created.as_deref()
+    }
+
+    /// Free-text note supplied by whoever encoded the file, if any.
+    pub fn note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+
+    /// CRC-32 of the whole decoded file, if the archive carries one.
+    pub fn crc32(&self) -> Option<u32> {
+        self.crc32
+    }
+
+    /// CRC-32 of each page's text, if the archive carries page checksums.
+    pub fn page_crcs(&self) -> &[u32] {
+        &self.page_crcs
+    }
+
+    /// Part files of a split archive, in order.
+    pub fn parts(&self) -> &[String] {
+        &self.parts
+    }
+
+    /// Whether the bytes on the pages are encrypted, so decoding needs a passphrase.
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    /// Files of a directory archive, in order; empty for a single file.
+    pub fn files(&self) -> &[ArchiveEntry] {
+        &self.files
+    }
+
+    /// How many addresses hold the file when filler pages follow them.
+    pub fn pages(&self) -> Option<u64> {
+        self.pages
+    }
+
+    /// Characters per page the archive was written with, if not the default.
+    pub fn page_length(&self) -> Option<usize> {
+        self.page_length
+    }
+
+    /// The library geometry the archive was encoded with, when it is not the default.
+    pub fn geometry(&self) -> Option<LibraryGeometry> {
+        self.geometry
+    }
+}
+
+// Header values live on one line, so backslashes and line breaks are escaped
+fn escape_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape_value(value: &str) -> Result<String, BabelError> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            other => {
+                return Err(BabelError::InvalidHeader(format!(
+                    "Invalid escape sequence \\{}", other.map(String::from).unwrap_or_default()
+                )));
+            }
+        }
+    }
+    Ok(unescaped)
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonArchive {
+    #[serde(flatten)]
+    header: ArchiveHeader,
+    addresses: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactJson {
+    header: ArchiveHeader,
+    index_base64: String,
+}
+
+// Separates addresses packed onto one line; never part of an address
+pub(crate) const ADDRESS_DELIMITER: char = ' ';
+
+// Starts a line of notes in the address section
+const COMMENT_PREFIX: char = '#';
+
+// Lines ended by `\n`, `\r\n` or a lone `\r`, so archives stitched together
+// on different platforms still parse
+fn split_lines(contents: &str) -> impl Iterator<Item = &str> {
+    let mut rest = contents;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let (line, tail) = match rest.find(['\n', '\r']) {
+            Some(end) if rest[end..].starts_with("\r\n") => (&rest[..end], &rest[end + 2..]),
+            Some(end) => (&rest[..end], &rest[end + 1..]),
+            None => (rest, ""),
+        };
+        rest = tail;
+        Some(line)
+    })
+}
+
+fn utf8(contents: &[u8]) -> Result<&str, BabelError> {
+    std::str::from_utf8(contents)
+        .map_err(|_| BabelError::InvalidHeader("Archive is not valid UTF-8".to_string()))
+}
+
+// Where a compressed index starts and how long it is, if the archive has one
+//
+// `index_length` is always the last header line, and no address contains '='.
+fn compressed_index(contents: &[u8]) -> Result<Option<(usize, usize)>, BabelError> {
+    const KEY: &[u8] = b"\nindex_length=";
+    let Some(start) = contents.windows(KEY.len()).position(|w| w == KEY) else {
+        return Ok(None);
+    };
+    let value_start = start + KEY.len();
+    let value_end = contents[value_start..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map(|n| value_start + n)
+        .ok_or_else(|| BabelError::InvalidHeader("Unterminated index_length".to_string()))?;
+    let value = utf8(&contents[value_start..value_end])?;
+    let length = value.trim_end_matches('\r').parse().map_err(|_| {
+        BabelError::InvalidHeader(format!("Invalid index_length: {}", value))
+    })?;
+    Ok(Some((value_end + 1, length)))
+}
+
+// Whether the first line that is not blank or a comment holds an address, as in a
+// self-contained archive; a header's extension line never parses as one
+fn starts_with_address(contents: &str) -> bool {
+    split_lines(contents)
+        .map(str::trim_start)
+        .find(|line| !line.is_empty() && !line.starts_with(COMMENT_PREFIX))
+        .and_then(|line| line.split(ADDRESS_DELIMITER).next())
+        .is_some_and(|address| split_address(address).is_ok())
+}
+
+// A `file=` value: offset and size in the decoded stream, then the escaped path
+fn parse_entry(value: &str) -> Result<ArchiveEntry, BabelError> {
+    let invalid = || BabelError::InvalidHeader(format!("Invalid file entry: {}", value));
+    let mut fields = value.splitn(3, ' ');
+    let offset = fields.next().and_then(|n| n.parse().ok()).ok_or_else(invalid)?;
+    let size = fields.next().and_then(|n| n.parse().ok()).ok_or_else(invalid)?;
+    let path = unescape_value(fields.next().ok_or_else(invalid)?)?;
+    Ok(ArchiveEntry { path, offset, size })
+}
+
+// Why the line after the extension is not a size, in the terms of the likely mistake
+fn missing_size(has_magic: bool, extension: &str, line: Option<&str>) -> BabelError {
+    let line = line.map(str::trim);
+    let address = line.filter(|line| starts_with_address(line));
+    let field = line.and_then(|line| line.split_once('=')).is_some_and(|(key, _)| {
+        !key.is_empty() && key.bytes().all(|b| b.is_ascii_lowercase() || b == b'_')
+    });
+    // Without the magic line, or a field or address where the size should be,
+    // nothing says this was ever an archive
+    if !has_magic && address.is_none() && !field {
+        let first = extension.chars().take(40).collect::<String>();
+        return BabelError::NotAnArchive(format!(
+            "it starts with {:?} where an archive starts with {}{}; was it the original file?",
+            first, MAGIC, FORMAT_VERSION
+        ));
+    }
+    match line {
+        None => BabelError::InvalidHeader("Archive ends after its extension line; the size line is missing".to_string()),
+        Some(line) if address.is_some() || field => BabelError::InvalidHeader(format!(
+            "Expected the file size after the extension line but found {:?}; \
+             --best-effort decodes an archive without one",
+            line
+        )),
+        Some(line) => BabelError::InvalidHeader(format!("Invalid file size: {}", line)),
+    }
+}
+
+fn legacy_version() -> u32 {
+    1
+}
+
+// Archives written before alphabets were recorded always used letters26
+fn legacy_alphabet() -> String {
+    Alphabet::letters26().id().to_string()
+}
+
+// Addresses in a bare list, one or more per line
+pub(crate) fn parse_addresses(contents: &str) -> Vec<String> {
+    collect_addresses(split_lines(contents))
+}
+
+// Addresses on `lines`, skipping blank lines and `#` comments; an address
+// always starts with a base-36 digit, so a comment can never be mistaken for one
+fn collect_addresses<'a>(lines: impl Iterator<Item = &'a str>) -> Vec<String> {
+    lines
+        .filter(|line| !line.trim_start().starts_with(COMMENT_PREFIX))
+        .flat_map(|line| line.split(ADDRESS_DELIMITER))
+        .filter(|address| !address.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// 1-based line of `contents` whose addresses include `address`
+pub(crate) fn address_line(contents: &str, address: &str) -> Option<usize> {
+    split_lines(contents)
+        .position(|line| !line.trim_start().starts_with(COMMENT_PREFIX)
+            && line.split(ADDRESS_DELIMITER).any(|a| a == address))
+        .map(|index| index + 1)
+}
+
+/// A parsed archive: its header and the page addresses in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Archive {
+    pub(crate) header: ArchiveHeader,
+    pub(crate) addresses: Vec<String>,
+    /// Addresses of the filler pages written after the real ones; never decoded.
+    pub(crate) filler: Vec<String>,
+    /// Addresses per line in the text format.
+    pub(crate) pages_per_line: usize,
+}
+
+impl Archive {
+    /// Read and parse an archive in any supported format.
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, BabelError> {
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents)?;
+        Self::parse(&contents)
+    }
+
+    /// Read only the header of an archive; a compressed index is skipped, not inflated.
+    pub fn read_header<R: Read>(mut reader: R) -> Result<ArchiveHeader, BabelError> {
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents)?;
+        match compressed_index(&contents)? {
+            Some((header_end, _)) => Ok(Self::parse_text(utf8(&contents[..header_end])?, false)?.header),
+            None => Ok(Self::parse(&contents)?.header),
+        }
+    }
+
+    pub fn header(&self) -> &ArchiveHeader {
+        &self.header
+    }
+
+    /// Page addresses in file order.
+    pub fn addresses(&self) -> &[String] {
+        &self.addresses
+    }
+
+    /// An archive of `header` and the addresses in `list`, separated by spaces or newlines.
+    ///
+    /// This pairs a bare address list with a header kept elsewhere; anything
+    /// in `list` that looks like a header is taken as an address.
+    pub fn from_addresses(header: ArchiveHeader, list: &str) -> Self {
+        let mut archive = Archive {
+            header,
+            addresses: parse_addresses(list),
+            filler: Vec::new(),
+            pages_per_line: 1,
+        };
+        archive.separate_filler();
+        archive
+    }
+
+    pub(crate) fn write<W: Write>(&self, format: ArchiveFormat, mut writer: W) -> Result<(), BabelError> {
+        match format {
+            ArchiveFormat::Text => {
+                self.write_text_header(&mut writer)?;
+                for line in self.address_lines() {
+                    writeln!(writer, "{}", line)?;
+                }
+            }
+            ArchiveFormat::CompressedIndex => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+                for line in self.address_lines() {
+                    writeln!(encoder, "{}", line)?;
+                }
+                let index = encoder.finish()?;
+
+                self.write_text_header(&mut writer)?;
+                writeln!(writer, "index_compression=deflate")?;
+                writeln!(writer, "index_length={}", index.len())?;
+                writer.write_all(&index)?;
+            }
+            ArchiveFormat::SelfContained => {
+                for line in self.address_lines() {
+                    writeln!(writer, "{}", line)?;
+                }
+            }
+            ArchiveFormat::Json => {
+                let json = JsonArchive {
+                    header: ArchiveHeader { version: FORMAT_VERSION, ..self.header.clone() },
+                    addresses: self.all_addresses(),
+                };
+                serde_json::to_writer(&mut writer, &json)
+                    .map_err(|e| BabelError::InvalidHeader(e.to_string()))?;
+                writeln!(writer)?;
+            }
+            ArchiveFormat::CompactJson => {
+                let json = CompactJson {
+                    header: ArchiveHeader { version: FORMAT_VERSION, ..self.header.clone() },
+                    index_base64: BASE64.encode(pack_index(&self.all_addresses(), &self.header.geometry.unwrap_or_default())?),
+                };
+                serde_json::to_writer(&mut writer, &json)
+                    .map_err(|e| BabelError::InvalidHeader(e.to_string()))?;
+                writeln!(writer)?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    // The text format with the address lines taken from `spool` rather than `addresses`
+    pub(crate) fn write_spooled<W: Write>(&self, spool: AddressSpool, mut writer: W) -> Result<(), BabelError> {
+        self.write_text_header(&mut writer)?;
+        spool.copy_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    // Extension, size and `key=value` lines shared by the text and compressed-index formats
+    fn write_text_header<W: Write>(&self, writer: &mut W) -> Result<(), BabelError> {
+        writeln!(writer, "{}{}", MAGIC, FORMAT_VERSION)?;
+        writeln!(writer, "{}", self.header.extension)?;
+        writeln!(writer, "{}", self.header.size)?;
+        writeln!(writer, "alphabet={}", self.header.alphabet)?;
+        if let Some(name) = &self.header.name {
+            writeln!(writer, "name={}", escape_value(name))?;
+        }
+        if self.header.payload == PayloadMode::Text {
+            writeln!(writer, "payload=text")?;
+        }
+        if !self.header.compression.is_none() {
+            writeln!(writer, "compression={}", self.header.compression.name())?;
+        }
+        if let Some(encryption) = &self.header.encryption {
+            writeln!(writer, "encryption={}", encryption.to_header_value())?;
+        }
+        if let Some(encoder) = &self.header.encoder {
+            writeln!(writer, "encoder={}", escape_value(encoder))?;
+        }
+        if let Some(created) = &self.header.created {
+            writeln!(writer, "created={}", escape_value(created))?;
+        }
+        if let Some(note) = &self.header.note {
+            writeln!(writer, "note={}", escape_value(note))?;
+        }
+        if let Some(modified) = self.header.modified {
+            writeln!(writer, "mtime={}", modified)?;
+        }
+        if let Some(mode) = self.header.mode {
+            writeln!(writer, "mode={:o}", mode)?;
+        }
+        if self.pages_per_line > 1 {
+            writeln!(writer, "pages_per_line={}", self.pages_per_line)?;
+        }
+        if let Some(length) = self.header.page_length {
+            writeln!(writer, "page_length={}", length)?;
+        }
+        if let Some(geometry) = self.header.geometry {
+            writeln!(writer, "geometry={}", geometry)?;
+        }
+        if let Some(crc) = self.header.crc32 {
+            writeln!(writer, "crc32={:08x}", crc)?;
+        }
+        if !self.header.page_crcs.is_empty() {
+            let crcs: Vec<String> = self.header.page_crcs.iter().map(|crc| format!("{:08x}", crc)).collect();
+            writeln!(writer, "page_crcs={}", crcs.join(","))?;
+        }
+        if let Some(pages) = self.header.pages {
+            writeln!(writer, "pages={}", pages)?;
+        }
+        for part in &self.header.parts {
+            writeln!(writer, "part={}", escape_value(part))?;
+        }
+        for file in &self.header.files {
+            writeln!(writer, "file={} {} {}", file.offset, file.size, escape_value(&file.path))?;
+        }
+        Ok(())
+    }
+
+    fn address_lines(&self) -> impl Iterator<Item = String> {
+        let all = self.all_addresses();
+        all.chunks(self.pages_per_line.max(1))
+            .map(|line| line.join(&ADDRESS_DELIMITER.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    // The real addresses followed by any filler, as written to disk
+    fn all_addresses(&self) -> Vec<String> {
+        [self.addresses.as_slice(), self.filler.as_slice()].concat()
+    }
+
+    // Move the addresses past the header's real page count into `filler`
+    pub(crate) fn separate_filler(&mut self) {
+        if let Some(pages) = self.header.pages {
+            if (self.addresses.len() as u64) > pages {
+                let mut filler = self.addresses.split_off(pages as usize);
+                filler.append(&mut self.filler);
+                self.filler = filler;
+            }
+        }
+    }
+
+    /// Write the header to `manifest_path` and the addresses to part files
+    /// beside it, none larger than `max_part_bytes`.
+    ///
+    /// Address lines are never broken across parts, so a part is closed as
+    /// soon as the next line would take it over the limit. Parts are named
+    /// after the manifest, e.g. `out.part001.babel` for `out.babel`.
+    pub(crate) fn write_split(&self, format: ArchiveFormat, manifest_path: &Path, max_part_bytes: u64) -> Result<Vec<PathBuf>, BabelError> {
+        let mut parts: Vec<Vec<String>> = Vec::new();
+        let mut part_size = 0;
+        for line in self.address_lines() {
+            let line_size = line.len() as u64 + 1;
+            if line_size > max_part_bytes {
+                return Err(BabelError::SplitSizeTooSmall { limit: max_part_bytes, line: line_size });
+            }
+            match parts.last_mut() {
+                Some(part) if part_size + line_size <= max_part_bytes => part.push(line),
+                _ => {
+                    parts.push(vec![line]);
+                    part_size = 0;
+                }
+            }
+            part_size += line_size;
+        }
+
+        let dir = manifest_path.parent().unwrap_or(Path::new(""));
+        let stem = manifest_path.file_stem().and_then(|s| s.to_str()).unwrap_or("archive");
+        let mut manifest = Archive {
+            header: self.header.clone(),
+            addresses: Vec::new(),
+            filler: Vec::new(),
+            pages_per_line: self.pages_per_line,
+        };
+        // Nothing is renamed into place until every part and the manifest are written
+        let mut paths = Vec::new();
+        let mut outputs = Vec::new();
+        for (i, lines) in parts.iter().enumerate() {
+            let name = format!("{}.part{:03}.babel", stem, i + 1);
+            let path = dir.join(&name);
+            let mut output = TempFile::create(&path)?;
+            let mut writer = BufWriter::new(&mut output);
+            for line in lines {
+                writeln!(writer, "{}", line)?;
+            }
+            writer.flush()?;
+            drop(writer);
+            outputs.push(output);
+            manifest.header.parts.push(name);
+            paths.push(path);
+        }
+
+        let mut output = TempFile::create(manifest_path)?;
+        manifest.write(format, BufWriter::new(&mut output))?;
+        outputs.push(output);
+        for output in outputs {
+            output.commit()?;
+        }
+        Ok(paths)
+    }
+
+    /// Read an archive from disk, gathering the addresses of a split archive from its parts.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, BabelError> {
+        Self::open_with(path.as_ref(), false)
+    }
+
+    // `best_effort` accepts a text header whose size line is missing
+    pub(crate) fn open_with(path: &Path, best_effort: bool) -> Result<Self, BabelError> {
+        let mut archive = Self::parse_with(&fs::read(path)?, best_effort)?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for part in &archive.header.parts {
+            let contents = fs::read_to_string(part_path(dir, part)?)?;
+            archive.addresses.extend(parse_addresses(&contents));
+        }
+        archive.separate_filler();
+        Ok(archive)
+    }
+
+    /// Parse an archive in any supported format.
+    pub(crate) fn parse(contents: &[u8]) -> Result<Self, BabelError> {
+        Self::parse_with(contents, false)
+    }
+
+    pub(crate) fn parse_with(contents: &[u8], best_effort: bool) -> Result<Self, BabelError> {
+        let mut archive = if let Some((header_end, length)) = compressed_index(contents)? {
+            let mut archive = Self::parse_text(utf8(&contents[..header_end])?, best_effort)?;
+            let index = contents.get(header_end..header_end + length).ok_or_else(|| {
+                BabelError::InvalidHeader("Truncated address index".to_string())
+            })?;
+            let mut lines = String::new();
+            DeflateDecoder::new(index).read_to_string(&mut lines)?;
+            archive.addresses = parse_addresses(&lines);
+            archive
+        } else {
+            let contents = std::str::from_utf8(contents).map_err(|_| {
+                BabelError::NotAnArchive("it is binary rather than text; was it the original file?".to_string())
+            })?;
+            if contents.trim_start().starts_with('{') {
+                Self::parse_json(contents)?
+            } else if starts_with_address(contents) {
+                Self::parse_self_contained(contents)
+            } else {
+                Self::parse_text(contents, best_effort)?
+            }
+        };
+        archive.separate_filler();
+        Ok(archive)
+    }
+
+    fn parse_text(contents: &str, best_effort: bool) -> Result<Self, BabelError> {
+        let mut lines = split_lines(contents).peekable();
+
+        // Version 1 archives start straight at the extension line; a bare extension never contains '/'
+        let version = match lines.peek().and_then(|line| line.strip_prefix(MAGIC)) {
+            Some(value) => {
+                let version = value.parse().ok().filter(|v| (1..=FORMAT_VERSION).contains(v)).ok_or_else(|| {
+                    BabelError::InvalidHeader(format!("Unsupported archive version: {}", value))
+                })?;
+                lines.next();
+                version
+            }
+            None => legacy_version(),
+        };
+
+        let has_magic = contents.starts_with(MAGIC);
+        let extension = lines.next().ok_or_else(|| match has_magic {
+            true => BabelError::InvalidHeader(format!("Archive ends after its {}{} line", MAGIC, version)),
+            false => BabelError::InvalidHeader("File is empty".to_string()),
+        })?;
+
+        // Without the size line the next line is a header field or an address, so leave it
+        let size = match lines.peek().and_then(|s| s.parse::<u64>().ok()) {
+            Some(size) => {
+                lines.next();
+                Some(size)
+            }
+            None if best_effort => None,
+            None => return Err(missing_size(has_magic, extension, lines.peek().copied())),
+        };
+
+        let mut alphabet = legacy_alphabet();
+        let mut name = None;
+        let mut payload = PayloadMode::default();
+        let mut compression = Compression::None;
+        let mut encryption = None;
+        let mut modified = None;
+        let mut mode = None;
+        let mut encoder = None;
+        let mut created = None;
+        let mut note = None;
+        let mut pages_per_line = 1;
+        let mut parts = Vec::new();
+        let mut files = Vec::new();
+        let mut crc32 = None;
+        let mut page_crcs = Vec::new();
+        let mut pages = None;
+        let mut page_length = None;
+        let mut geometry = None;
+        while let Some((key, value)) = lines.peek().copied().and_then(|line| line.split_once('=')) {
+            match key {
+                "alphabet" => alphabet = value.to_string(),
+                "name" => name = Some(unescape_value(value)?),
+                "payload" => {
+                    payload = match value {
+                        "bytes" => PayloadMode::Bytes,
+                        "text" => PayloadMode::Text,
+                        _ => return Err(BabelError::InvalidHeader(format!("Invalid payload: {}", value))),
+                    };
+                }
+                "compression" => compression = Compression::from_name(value)?,
+                "encryption" => encryption = Some(Encryption::from_header_value(value)?),
+                "encoder" => encoder = Some(unescape_value(value)?),
+                "created" => created = Some(unescape_value(value)?),
+                "note" => note = Some(unescape_value(value)?),
+                "part" => parts.push(unescape_value(value)?),
+                "file" => files.push(parse_entry(value)?),
+                "index_compression" if value != "deflate" => {
+                    return Err(BabelError::InvalidHeader(format!("Unsupported index compression: {}", value)));
+                }
+                "pages" => {
+                    pages = Some(value.parse().map_err(|_| {
+                        BabelError::InvalidHeader(format!("Invalid pages: {}", value))
+                    })?);
+                }
+                "page_length" => {
+                    page_length = Some(value.parse().map_err(|_| {
+                        BabelError::InvalidHeader(format!("Invalid page_length: {}", value))
+                    })?);
+                }
+                "geometry" => geometry = Some(value.parse()?),
+                "crc32" => {
+                    crc32 = Some(u32::from_str_radix(value, 16).map_err(|_| {
+                        BabelError::InvalidHeader(format!("Invalid crc32: {}", value))
+                    })?);
+                }
+                "page_crcs" => {
+                    page_crcs = value
+                        .split(',')
+                        .map(|crc| u32::from_str_radix(crc, 16))
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| BabelError::InvalidHeader(format!("Invalid page_crcs: {}", value)))?;
+                }
+                "mtime" => {
+                    modified = Some(value.parse().map_err(|_| {
+                        BabelError::InvalidHeader(format!("Invalid mtime: {}", value))
+                    })?);
+                }
+                "mode" => {
+                    mode = Some(u32::from_str_radix(value, 8).map_err(|_| {
+                        BabelError::InvalidHeader(format!("Invalid mode: {}", value))
+                    })?);
+                }
+                "pages_per_line" => {
+                    pages_per_line = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                        BabelError::InvalidHeader(format!("Invalid pages_per_line: {}", value))
+                    })?;
+                }
+                _ => {}
+            }
+            lines.next();
+        }
+
+        Ok(Archive {
+            header: ArchiveHeader {
+                version,
+                extension: extension.to_string(),
+                name,
+                size: size.unwrap_or(0),
+                alphabet,
+                payload,
+                compression,
+                encryption,
+                modified,
+                mode,
+                encoder,
+                created,
+                note,
+                parts,
+                files,
+                crc32,
+                page_crcs,
+                pages,
+                page_length,
+                geometry,
+                size_unknown: size.is_none(),
+                self_contained: false,
+            },
+            addresses: collect_addresses(lines),
+            filler: Vec::new(),
+            pages_per_line,
+        })
+    }
+
+    // Only the addresses are on disk; the header is read from the pages on decode
+    fn parse_self_contained(contents: &str) -> Self {
+        let mut header = ArchiveHeader::new("", 0, &Alphabet::letters26());
+        header.self_contained = true;
+        Archive {
+            header,
+            addresses: parse_addresses(contents),
+            filler: Vec::new(),
+            pages_per_line: 1,
+        }
+    }
+
+    // Either JSON layout, told apart by whether the addresses are listed or packed
+    fn parse_json(contents: &str) -> Result<Self, BabelError> {
+        let value: serde_json::Value = serde_json::from_str(contents)
+            .map_err(|e| BabelError::InvalidHeader(e.to_string()))?;
+        if value.get("addresses").is_none() {
+            return Self::parse_compact_json(value);
+        }
+        let json: JsonArchive = serde_json::from_value(value)
+            .map_err(|e| BabelError::InvalidHeader(e.to_string()))?;
+
+        Ok(Archive {
+            header: json.header,
+            addresses: json.addresses,
+            filler: Vec::new(),
+            pages_per_line: 1,
+        })
+    }
+
+    fn parse_compact_json(value: serde_json::Value) -> Result<Self, BabelError> {
+        let json: CompactJson = serde_json::from_value(value)
+            .map_err(|e| BabelError::InvalidHeader(e.to_string()))?;
+        let index = BASE64.decode(json.index_base64.trim())
+            .map_err(|e| BabelError::InvalidHeader(format!("index_base64: {}", e)))?;
+
+        let addresses = unpack_index(&index, &json.header.geometry.unwrap_or_default())?;
+
+        Ok(Archive {
+            header: json.header,
+            addresses,
+            filler: Vec::new(),
+            pages_per_line: 1,
+        })
+    }
+}
+
+// A part named by the manifest in `dir`, which must not lead out of it
+fn part_path(dir: &Path, part: &str) -> Result<PathBuf, BabelError> {
+    let relative = Path::new(part);
+    let safe = !part.is_empty() && relative.components().all(|component| matches!(component, Component::Normal(_)));
+    if !safe {
+        return Err(BabelError::InvalidHeader(format!("Part escapes the manifest's directory: {}", part)));
+    }
+    Ok(dir.join(relative))
+}
+
+/// Pack addresses into a compact binary index.
+///
+/// Layout (little-endian): a `u32` address count, then per address a `u32`
+/// key length, the key's big-endian magnitude bytes, and the wall, shelf,
+/// volume and page, each in as many bytes as [`coordinate_bytes`] gives for
+/// `geometry`. The default geometry takes one byte each for the wall, shelf
+/// and volume and two for the page.
+pub(crate) fn pack_index(addresses: &[String], geometry: &LibraryGeometry) -> Result<Vec<u8>, BabelError> {
+    let widths = coordinate_bytes(geometry);
+    let mut index = Vec::new();
+    index.extend_from_slice(&(addresses.len() as u32).to_le_bytes());
+
+    for address in addresses {
+        let malformed = || BabelError::MalformedAddress(address.clone());
+        let (key, location) = split_address(address)?;
+
+        let key = BigInt::parse_bytes(key.as_bytes(), 36).ok_or_else(malformed)?;
+        let (_, key_bytes) = key.to_bytes_be();
+        index.extend_from_slice(&(key_bytes.len() as u32).to_le_bytes());
+        index.extend_from_slice(&key_bytes);
+
+        let coords = [location.wall, location.shelf, location.volume, location.page];
+        for (coord, width) in coords.into_iter().zip(widths) {
+            let bytes = coord.to_le_bytes();
+            // A coordinate past its geometry would lose its high bytes
+            if bytes[width..].iter().any(|&b| b != 0) {
+                return Err(malformed());
+            }
+            index.extend_from_slice(&bytes[..width]);
+        }
+    }
+
+    Ok(index)
+}
+
+/// Bytes [`pack_index`] gives each coordinate, as `[wall, shelf, volume, page]`:
+/// enough for the largest index below its count in `geometry`.
+pub(crate) fn coordinate_bytes(geometry: &LibraryGeometry) -> [usize; 4] {
+    [geometry.walls, geometry.shelves, geometry.volumes, geometry.pages]
+        .map(|count| (count.saturating_sub(1).max(1).ilog2() as usize) / 8 + 1)
+}
+
+/// Reverse of [`pack_index`], producing addresses in the form `search` emits.
+pub(crate) fn unpack_index(mut index: &[u8], geometry: &LibraryGeometry) -> Result<Vec<String>, BabelError> {
+    fn take<'a>(index: &mut &'a [u8], n: usize) -> Result<&'a [u8], BabelError> {
+        if index.len() < n {
+            return Err(BabelError::InvalidHeader("Truncated address index".to_string()));
+        }
+        let (head, tail) = index.split_at(n);
+        *index = tail;
+        Ok(head)
+    }
+    fn take_u32(index: &mut &[u8]) -> Result<u32, BabelError> {
+        Ok(u32::from_le_bytes(take(index, 4)?.try_into().unwrap()))
+    }
+    fn take_coord(index: &mut &[u8], width: usize) -> Result<u32, BabelError> {
+        let mut bytes = [0u8; 4];
+        bytes[..width].copy_from_slice(take(index, width)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    let widths = coordinate_bytes(geometry);
+    let count = take_u32(&mut index)?;
+    let mut addresses = Vec::new();
+    for _ in 0..count {
+        let key_len = take_u32(&mut index)? as usize;
+        let key = BigInt::from_bytes_be(Sign::Plus, take(&mut index, key_len)?);
+        let location = Location {
+            wall: take_coord(&mut index, widths[0])?,
+            shelf: take_coord(&mut index, widths[1])?,
+            volume: take_coord(&mut index, widths[2])?,
+            page: take_coord(&mut index, widths[3])?,
+        };
+        addresses.push(format_address(&int_to_base36(key), &location, geometry));
+    }
+
+    if !index.is_empty() {
+        return Err(BabelError::InvalidHeader("Trailing bytes after address index".to_string()));
+    }
+
+    Ok(addresses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::BabelCodec;
+    use crate::page_length::PageLength;
+
+    #[test]
+    fn lines_end_at_any_mix_of_endings() {
+        let lines: Vec<&str> = split_lines("unix\nwindows\r\nmac\rlast").collect();
+        assert_eq!(lines, ["unix", "windows", "mac", "last"]);
+        let lines: Vec<&str> = split_lines("\r\n\n\r\rtrailing\r\n").collect();
+        assert_eq!(lines, ["", "", "", "", "trailing"]);
+        assert_eq!(split_lines("").count(), 0);
+    }
+
+    #[test]
+    fn archives_with_mixed_line_endings_parse() {
+        let mut header = ArchiveHeader::new("txt", 12, &Alphabet::default());
+        header.note = Some("mixed".to_string());
+        let addresses = "A:0:0:00:000\nB:1:0:00:000\nC:2:0:00:000\n";
+        let mut text = Vec::new();
+        Archive::from_addresses(header.clone(), addresses).write(ArchiveFormat::Text, &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+
+        let mut endings = ["\r\n", "\r", "\n"].iter().cycle();
+        let mixed: String = text.lines().map(|line| format!("{}{}", line, endings.next().unwrap())).collect();
+        let archive = Archive::parse(mixed.as_bytes()).unwrap();
+        assert_eq!(archive.header.note(), Some("mixed"));
+        assert_eq!(archive.header.size(), 12);
+        assert_eq!(archive.addresses, ["A:0:0:00:000", "B:1:0:00:000", "C:2:0:00:000"]);
+    }
+
+    #[test]
+    fn comment_lines_among_the_addresses_are_skipped() {
+        let codec = BabelCodec::default().with_progress(false).with_page_length(PageLength::new(20).unwrap());
+        let bytes = b"annotated by hand, page by page";
+        let mut encoded = Vec::new();
+        codec.encode_reader(&bytes[..], "txt", &mut encoded).unwrap();
+        let encoded = String::from_utf8(encoded).unwrap();
+
+        let mut annotated = String::new();
+        for line in encoded.lines() {
+            if line.contains(':') && !line.contains('=') {
+                annotated.push_str("# the next page\n  #indented, with: a colon\n");
+            }
+            annotated.push_str(line);
+            annotated.push('\n');
+        }
+        annotated.push_str("# trailing note\n");
+
+        let archive = Archive::parse(annotated.as_bytes()).unwrap();
+        assert_eq!(archive.addresses, Archive::parse(encoded.as_bytes()).unwrap().addresses);
+        assert_eq!(archive.addresses.len(), 4);
+        let mut decoded = Vec::new();
+        codec.decode_archive(&archive, &mut decoded).unwrap();
+        assert_eq!(decoded, bytes);
+    }
+
+    #[test]
+    fn parts_outside_the_manifest_directory_are_refused() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let manifest = dir.path().join("nested").join("out.babel");
+        fs::create_dir(manifest.parent().unwrap()).unwrap();
+        fs::write(dir.path().join("secret.txt"), "not an address\n").unwrap();
+
+        for part in ["../secret.txt", "/etc/passwd", "", "./out.part001.babel"] {
+            let mut header = ArchiveHeader::new("txt", 1, &Alphabet::default());
+            header.parts.push(part.to_string());
+            Archive::from_addresses(header, "").write(ArchiveFormat::Text, fs::File::create(&manifest).unwrap()).unwrap();
+            match Archive::open(&manifest) {
+                Err(BabelError::InvalidHeader(message)) => assert!(message.contains("escapes"), "{}", message),
+                other => panic!("opened part {:?}: {:?}", part, other.map(|archive| archive.addresses)),
+            }
+        }
+    }
+
+    // Both formats hand back the addresses the encoder wrote, padded to the archive's geometry
+    #[test]
+    fn custom_geometry_addresses_survive_text_and_packed_indexes() {
+        let geometry = LibraryGeometry::new(20, 20, 200, 2000).unwrap();
+        let codec = BabelCodec::default()
+            .with_progress(false)
+            .with_page_length(PageLength::new(20).unwrap())
+            .with_geometry(geometry)
+            .with_seed(Some(294));
+        let bytes = b"written in a library of another shape";
+        let addresses = codec.encode_bytes(bytes).unwrap();
+        assert!(addresses.iter().all(|address| address.split(':').skip(1).map(str::len).eq(geometry.field_widths())));
+
+        for format in [ArchiveFormat::Text, ArchiveFormat::CompressedIndex] {
+            let mut header = ArchiveHeader::new("txt", bytes.len() as u64, &Alphabet::default());
+            header.page_length = Some(20);
+            header.geometry = Some(geometry);
+            let mut written = Vec::new();
+            Archive::from_addresses(header, &addresses.join("\n")).write(format, &mut written).unwrap();
+            let archive = Archive::parse(&written).unwrap();
+            assert_eq!(archive.addresses, addresses, "{:?}", format);
+            let mut decoded = Vec::new();
+            BabelCodec::default().with_progress(false).decode_archive(&archive, &mut decoded).unwrap();
+            assert_eq!(decoded, bytes, "{:?}", format);
+        }
+    }
+}
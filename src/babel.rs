@@ -0,0 +1,84 @@
+//! Free functions over a default [`BabelCodec`], for callers that just want
+//! bytes turned into addresses and back.
+//!
+//! Every function here shares one codec with the default alphabet, page
+//! length and padding, so the multi-kilobyte location multiplier is computed
+//! once per process. Build a [`BabelCodec`] directly for anything else.
+
+use num_bigint::BigInt;
+use std::sync::OnceLock;
+
+use crate::codec::BabelCodec;
+use crate::error::BabelError;
+use crate::library;
+use crate::location::LocationBias;
+
+pub use crate::address::Address;
+pub use crate::library::{LENGTH_OF_PAGE, PAD_CHAR};
+
+fn codec() -> &'static BabelCodec {
+    static CODEC: OnceLock<BabelCodec> = OnceLock::new();
+    CODEC.get_or_init(|| BabelCodec::new().with_progress(false))
+}
+
+/// Addresses of pages spelling `bytes`, one per page and in order.
+///
+/// Each page is placed at a random location and verified before it is returned.
+pub fn encode_bytes(bytes: &[u8]) -> Result<Vec<Address>, BabelError> {
+    codec().encode_parsed(bytes)
+}
+
+/// The first `original_size` bytes spelled by `addresses`; the inverse of [`encode_bytes`].
+pub fn decode_addresses(addresses: &[Address], original_size: usize) -> Result<Vec<u8>, BabelError> {
+    codec().decode_parsed(addresses, original_size)
+}
+
+/// The full text of the page at `address`, [`LENGTH_OF_PAGE`] characters long.
+pub fn get_page(address: &Address) -> Result<String, BabelError> {
+    library::get_page(address, codec().space()?)
+}
+
+/// `bytes` spelled as page text, two letters per byte, before any padding.
+pub fn bytes_to_babel_text(bytes: &[u8]) -> String {
+    codec().bytes_to_babel_text(bytes)
+}
+
+/// The number a page spelling `text` holds: its characters read as base-29
+/// digits in page-character order, `a` being 0 and `.` 28.
+pub fn string_to_number(text: &str) -> Result<BigInt, BabelError> {
+    library::string_to_number(text)
+}
+
+/// The page spelling `number`, left-padded with `a`; the inverse of
+/// [`string_to_number`] for text of [`LENGTH_OF_PAGE`] characters.
+///
+/// A negative number, or one no page of that length can spell, is an error.
+pub fn to_text(number: &BigInt) -> Result<String, BabelError> {
+    library::number_to_text(number, codec().space()?)
+}
+
+/// An address at a random location holding `page`, which must be exactly
+/// [`LENGTH_OF_PAGE`] page characters.
+pub fn search(page: &str) -> Result<Address, BabelError> {
+    library::search(page, codec().space()?, LocationBias::Uniform, &mut rand::thread_rng())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_text_numbers_and_addresses_agree() {
+        let page = bytes_to_babel_text(b"free functions").repeat(LENGTH_OF_PAGE).chars().take(LENGTH_OF_PAGE).collect::<String>();
+        let number = string_to_number(&page).unwrap();
+        assert_eq!(to_text(&number).unwrap(), page);
+
+        let address = search(&page).unwrap();
+        assert_eq!(get_page(&address).unwrap(), page);
+        assert_eq!(decode_addresses(&[address], 14).unwrap(), b"free functions");
+
+        assert!(matches!(to_text(&BigInt::from(-1)), Err(BabelError::PageNumberOutOfRange { negative: true })));
+        let past_last = BigInt::from(29u32).pow(LENGTH_OF_PAGE as u32);
+        assert!(matches!(to_text(&past_last), Err(BabelError::PageNumberOutOfRange { negative: false })));
+    }
+}
@@ -0,0 +1,59 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::archive::Archive;
+use crate::codec::BabelCodec;
+use crate::error::BabelError;
+
+impl BabelCodec {
+    /// Encode `data` into an archive returned as a `Bytes` buffer.
+    ///
+    /// The input is read straight out of `data` and the archive is written
+    /// into a single growing `BytesMut`, so neither side goes through a `Vec`.
+    pub fn encode_bytes_buf(&self, data: Bytes, extension: &str) -> Result<Bytes, BabelError> {
+        let mut writer = BytesMut::new().writer();
+        self.encode_reader(data.reader(), extension, &mut writer)?;
+        Ok(writer.into_inner().freeze())
+    }
+
+    /// Decode an archive held in memory, returning the original bytes.
+    ///
+    /// The output buffer is sized from the header up front, so pages are
+    /// decoded into it without reallocating. The size is only trusted as far
+    /// as the archive has pages to hold it.
+    pub fn decode_to_bytes(&self, archive: &[u8]) -> Result<Bytes, BabelError> {
+        let archive = Archive::read(archive)?;
+        let capacity = self.decode_chunks(&archive)?.capacity();
+        let mut writer = BytesMut::with_capacity(capacity).writer();
+        self.decode_archive(&archive, &mut writer)?;
+        Ok(writer.into_inner().freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_round_trip_through_an_archive() {
+        let codec = BabelCodec::default().with_progress(false);
+        let data = Bytes::from((0..=255u8).cycle().take(4000).collect::<Vec<u8>>());
+        let archive = codec.encode_bytes_buf(data.clone(), "bin").unwrap();
+        assert_eq!(codec.decode_to_bytes(&archive).unwrap(), data);
+    }
+
+    // A size no page list could hold is an error once the pages run out, not
+    // an allocation; `reader` bounds its `Vec` by the same capacity
+    #[test]
+    fn a_doctored_size_is_not_preallocated() {
+        let codec = BabelCodec::default().with_progress(false);
+        let archive = codec.encode_bytes_buf(Bytes::from_static(b"one page"), "bin").unwrap();
+        let archive = String::from_utf8(archive.to_vec()).unwrap();
+        let doctored = archive.replacen("\n8\n", "\n18000000000000000000\n", 1);
+        assert_ne!(doctored, archive);
+        let parsed = Archive::read(doctored.as_bytes()).unwrap();
+        assert!(codec.decode_chunks(&parsed).unwrap().capacity() <= codec.page_length().get());
+        assert!(codec.decode_to_bytes(doctored.as_bytes()).is_err());
+        let decoded: Result<Vec<u8>, _> = codec.reader(&parsed).unwrap().try_into();
+        assert!(decoded.is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::VecDeque;
+
+use crate::codec::BabelCodec;
+use crate::error::BabelError;
+
+impl BabelCodec {
+    /// Lazily encode the bytes `bytes` yields, producing page addresses in order.
+    ///
+    /// Bytes are pulled a batch of pages at a time, so the source is never
+    /// held in memory as a whole, and the final partial page is padded once
+    /// it runs dry. Pages always spell bytes. With the default padding the
+    /// addresses decode back with [`BabelCodec::decode_addresses`] once parsed;
+    /// an error ends the iteration.
+    pub fn encode_byte_iter<'a, I>(&'a self, bytes: I) -> impl Iterator<Item = Result<String, BabelError>> + 'a
+    where
+        I: IntoIterator<Item = u8>,
+        I::IntoIter: 'a,
+    {
+        let mut bytes = bytes.into_iter();
+        let mut pending = String::new();
+        let mut ready = VecDeque::new();
+        let mut pages = 0;
+        let mut done = false;
+
+        std::iter::from_fn(move || {
+            while ready.is_empty() && !done {
+                match self.next_byte_batch(&mut bytes, &mut pending, pages) {
+                    Ok((batch, exhausted)) => {
+                        pages += batch.len();
+                        ready.extend(batch);
+                        done = exhausted;
+                    }
+                    Err(e) => {
+                        done = true;
+                        return Some(Err(e));
+                    }
+                }
+            }
+            ready.pop_front().map(Ok)
+        })
+    }
+
+    // Addresses for the next batch of pages, and whether `bytes` ran out.
+    // Characters left over after the last full page carry over in `pending`.
+    fn next_byte_batch(
+        &self,
+        bytes: &mut impl Iterator<Item = u8>,
+        pending: &mut String,
+        first_index: usize,
+    ) -> Result<(Vec<String>, bool), BabelError> {
+        if !self.pad.is_valid() {
+            return Err(BabelError::InvalidPadding(self.pad));
+        }
+        let page_length = self.page_length().get();
+        let batch_bytes = self.batch_bytes(page_length);
+        let buf: Vec<u8> = bytes.by_ref().take(batch_bytes).collect();
+        let exhausted = buf.len() < batch_bytes;
+        pending.push_str(&self.bytes_to_babel_text(&buf));
+
+        let full = pending.len() / page_length * page_length;
+        let mut chunks: Vec<String> = pending.as_bytes()[..full]
+            .chunks(page_length)
+            .map(|c| String::from_utf8_lossy(c).into_owned())
+            .collect();
+        pending.drain(..full);
+        if exhausted && !pending.is_empty() {
+            chunks.push(self.pad_page(pending, page_length, first_index + chunks.len()));
+            pending.clear();
+        }
+        if chunks.is_empty() {
+            return Ok((Vec::new(), exhausted));
+        }
+
+        let (addresses, stats) = self.locate_pages(&chunks, first_index)?;
+        if !stats.all_passed() {
+            return Err(BabelError::VerificationFailed(stats));
+        }
+        Ok((addresses, exhausted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Address;
+    use crate::library::LENGTH_OF_PAGE;
+
+    #[test]
+    fn cycled_bytes_round_trip_through_the_iterator() {
+        let codec = BabelCodec::default().with_progress(false);
+        let bytes: Vec<u8> = (0u8..=255).cycle().take(10000).collect();
+        let addresses = codec.encode_byte_iter((0u8..=255).cycle().take(10000)).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(addresses.len(), 20000usize.div_ceil(LENGTH_OF_PAGE));
+        let parsed: Vec<Address> = addresses.iter().map(|address| address.parse().unwrap()).collect();
+        assert_eq!(codec.decode_addresses(&parsed).unwrap(), bytes);
+        assert_eq!(codec.decode_bytes(&addresses, bytes.len()).unwrap(), bytes);
+    }
+}
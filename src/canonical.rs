@@ -0,0 +1,76 @@
+use rayon::prelude::*;
+
+use crate::address::Address;
+use crate::codec::BabelCodec;
+use crate::error::BabelError;
+use crate::library::{get_page, search_exact, PAD_CHAR};
+use crate::location::Location;
+use crate::padding::PadStrategy;
+
+// Every canonical address sits at the first page of the first volume
+const CANONICAL_LOCATION: Location = Location { wall: 0, shelf: 0, volume: 0, page: 0 };
+
+impl BabelCodec {
+    /// Addresses for `bytes` with every page placed at location `0:0:00:000`.
+    ///
+    /// No random location is drawn, so the same bytes always produce the same
+    /// addresses. That makes the output usable as a content address for
+    /// deduplication, at the cost of every page sharing one location.
+    pub fn encode_canonical(&self, bytes: &[u8]) -> Result<Vec<Address>, BabelError> {
+        let space = self.space()?;
+        let text = self.bytes_to_babel_text(bytes);
+        let chunks: Vec<String> = text
+            .as_bytes()
+            .chunks(space.length)
+            // Always the default padding: decode_addresses has no size to stop at
+            .map(|chunk| PadStrategy::default().pad(std::str::from_utf8(chunk).unwrap(), space.length, self.alphabet()))
+            .collect();
+
+        let locate = |chunk: &String| search_exact(chunk, space, &CANONICAL_LOCATION);
+        if chunks.len() >= self.min_parallel_pages {
+            self.in_pool(|| chunks.par_iter().map(locate).collect())
+        } else {
+            chunks.iter().map(locate).collect()
+        }
+    }
+
+    /// Bytes spelled by `addresses`, in order; the inverse of [`BabelCodec::encode_canonical`].
+    ///
+    /// Works for any list of addresses from this codec, not only canonical ones.
+    /// The periods padding the last page mark where the bytes end, so base29,
+    /// which spells with periods, has to decode with the size through
+    /// [`BabelCodec::decode_bytes`] instead.
+    pub fn decode_addresses(&self, addresses: &[Address]) -> Result<Vec<u8>, BabelError> {
+        if !self.alphabet().pads_with_period() {
+            return Err(BabelError::InvalidAlphabet(format!(
+                "{} spells with periods, so its pages need the size to decode", self.alphabet().id()
+            )));
+        }
+        let space = self.space()?;
+        let mut text = String::new();
+        for address in addresses {
+            text.push_str(get_page(address, space)?.trim_end_matches(PAD_CHAR));
+        }
+        self.babel_text_to_bytes(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page_length::PageLength;
+
+    #[test]
+    fn canonical_addresses_depend_only_on_the_bytes() {
+        let bytes = b"The same content always lands on the same pages.";
+        let codec = || BabelCodec::default().with_page_length(PageLength::new(40).unwrap());
+        let first = codec().with_seed(Some(1)).encode_canonical(bytes).unwrap();
+        let second = codec().with_seed(Some(2)).encode_canonical(bytes).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.len(), 3);
+        assert!(first.iter().all(|address| address.location == CANONICAL_LOCATION));
+
+        assert_eq!(codec().decode_addresses(&first).unwrap(), bytes);
+        assert_ne!(codec().encode_canonical(b"Other content").unwrap()[0], first[0]);
+    }
+}
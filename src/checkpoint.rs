@@ -0,0 +1,133 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::address::Address;
+use crate::error::BabelError;
+
+/// Addresses of the pages an encode has finished, appended to `<output>.partial`
+/// after every batch so a killed encode can pick up where it stopped.
+///
+/// The file holds one address per line. Each batch is synced before the next
+/// is searched, so at most the batch in progress is lost; a line torn by the
+/// kill has no newline and is cut off when the checkpoint is opened again.
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    file: fs::File,
+    // Addresses found in the file when it was opened, in page order
+    resumed: Vec<Address>,
+    // Pages handed to `push` so far, and of those the ones already on disk
+    seen: usize,
+    written: usize,
+}
+
+impl Checkpoint {
+    /// The checkpoint beside `output_path`, started afresh if there is none.
+    pub(crate) fn open(output_path: &str) -> Result<Self, BabelError> {
+        let path = PathBuf::from(format!("{}.partial", output_path));
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let complete = text.rfind('\n').map_or(0, |end| end + 1);
+        let resumed = text[..complete]
+            .lines()
+            .enumerate()
+            .map(|(index, line)| {
+                line.parse::<Address>()
+                    .map_err(|e| BabelError::BadAddress { index, line: Some(index + 1), source: Box::new(e) })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut file = OpenOptions::new().create(true).write(true).truncate(false).open(&path)?;
+        file.set_len(complete as u64)?;
+        file.seek(SeekFrom::End(0))?;
+        let written = resumed.len();
+        Ok(Checkpoint { path, file, resumed, seen: 0, written })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Take the addresses the checkpoint already held, for the encode to reuse.
+    pub(crate) fn take_resumed(&mut self) -> Vec<Address> {
+        std::mem::take(&mut self.resumed)
+    }
+
+    /// Record the next batch of addresses, skipping pages already on disk.
+    pub(crate) fn push(&mut self, batch: &[String]) -> io::Result<()> {
+        let skip = self.written.saturating_sub(self.seen).min(batch.len());
+        self.seen += batch.len();
+        if skip == batch.len() {
+            return Ok(());
+        }
+        let mut lines = String::new();
+        for address in &batch[skip..] {
+            lines.push_str(address);
+            lines.push('\n');
+        }
+        self.file.write_all(lines.as_bytes())?;
+        self.file.sync_data()?;
+        self.written = self.seen;
+        Ok(())
+    }
+
+    /// Delete the checkpoint once the archive it was building is written.
+    pub(crate) fn remove(self) -> io::Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archive::Archive;
+    use crate::codec::BabelCodec;
+    use crate::error::BabelError;
+    use crate::page_length::PageLength;
+    use std::fs;
+
+    fn codec() -> BabelCodec {
+        BabelCodec::default().with_progress(false).with_page_length(PageLength::new(20).unwrap()).with_resume(true)
+    }
+
+    // A kill after two pages leaves them and a torn third line behind
+    #[test]
+    fn a_truncated_checkpoint_resumes_after_its_last_full_line() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("input.babel");
+        let bytes = b"forty bytes of input take four full pages";
+        fs::write(&input, bytes).unwrap();
+        let earlier = codec().encode_bytes(bytes).unwrap();
+        let partial = format!("{}\n{}\n{}", earlier[0], earlier[1], &earlier[2][..5]);
+        fs::write(dir.path().join("input.babel.partial"), partial).unwrap();
+
+        codec().encode_file(input.to_str().unwrap(), output.to_str()).unwrap();
+        assert!(!dir.path().join("input.babel.partial").exists());
+        let archive = Archive::open(&output).unwrap();
+        assert_eq!(archive.addresses()[..2], earlier[..2]);
+        assert_eq!(archive.addresses().len(), earlier.len());
+        let mut decoded = Vec::new();
+        codec().decode_archive(&archive, &mut decoded).unwrap();
+        assert_eq!(decoded, bytes);
+    }
+
+    #[test]
+    fn a_changed_input_does_not_match_its_checkpoint() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("input.babel");
+        let earlier = codec().encode_bytes(b"forty bytes of input take four full pages").unwrap();
+        fs::write(dir.path().join("input.babel.partial"), format!("{}\n{}\n", earlier[0], earlier[1])).unwrap();
+        fs::write(&input, b"forty bytes of INPUT take four full pages").unwrap();
+
+        match codec().encode_file(input.to_str().unwrap(), output.to_str()) {
+            Err(BabelError::CheckpointMismatch { index }) => assert_eq!(index, 1),
+            other => panic!("resumed over a changed input: {:?}", other),
+        }
+        assert!(!output.exists());
+    }
+}
@@ -0,0 +1,2419 @@
+use log::{log, Level};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufReader, BufWriter};
+use std::io::{ErrorKind, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::address::Address;
+use crate::alphabet::{Alphabet, Block};
+use crate::checkpoint::Checkpoint;
+use crate::archive::{address_line, Archive, ArchiveFormat, ArchiveHeader, FORMAT_VERSION};
+use crate::compression::Compression;
+use crate::crc::{crc32, Crc32, CrcReader};
+use crate::directory;
+use crate::encryption::{Encryption, Passphrase};
+use crate::error::BabelError;
+use crate::library::{get_page, search, search_exact, PageSpace, PAD_CHAR};
+use crate::layout::PageLayout;
+use crate::location::{LibraryGeometry, LocationBias};
+use crate::metadata;
+use crate::padding::PadStrategy;
+use crate::page_cache::PageCache;
+use crate::page_length::PageLength;
+use crate::parallel_read::ParallelReader;
+use crate::spool::AddressSpool;
+use crate::temp_file::TempFile;
+use crate::text_mode::{self, OnInvalidChar, PayloadMode};
+use crate::verify::{verify_pages, VerifyMode, VerifyStats};
+
+// Pages searched per parallel batch while streaming
+const PAGES_PER_BATCH: usize = 64;
+
+/// Size of the thread pool used for page search and decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Threads {
+    /// One per logical CPU (rayon's global pool).
+    #[default]
+    Logical,
+    /// One per physical core. The BigInt arithmetic in search is CPU-bound,
+    /// so hyperthread siblings mostly contend for the same core's caches.
+    Physical,
+    /// Exactly this many. `Fixed(1)` runs every search, check and
+    /// conversion in turn on one thread, for reproducible timing.
+    Fixed(usize),
+}
+
+impl Threads {
+    /// How many threads this setting searches and decodes on.
+    pub fn count(self) -> usize {
+        match self {
+            Threads::Logical => rayon::current_num_threads(),
+            Threads::Physical => num_cpus::get_physical(),
+            Threads::Fixed(n) => n.max(1),
+        }
+    }
+}
+
+// Rough resident bytes per in-flight page, as a multiple of the page length:
+// the input bytes, the spelled text, pending and chunk copies, and the
+// BigInts and address string `search` builds
+const BYTES_PER_PAGE_CHAR: usize = 8;
+
+// Page counts below this are searched and decoded sequentially
+const DEFAULT_MIN_PARALLEL_PAGES: usize = 2;
+
+// Byte counts above this are spelled and read back on the pool. The count is
+// of bytes in both directions, so a round trip takes the same path each way.
+const PARALLEL_CONVERSION_BYTES: usize = 1024;
+
+// Blocks of bytes or symbols each parallel conversion task works through
+const CONVERSION_BLOCKS: usize = 4096;
+
+/// Converts files to and from lists of Library of Babel page addresses.
+#[derive(Debug, Clone)]
+pub struct BabelCodec {
+    alphabet: Alphabet,
+    format: ArchiveFormat,
+    pages_per_line: usize,
+    preserve_metadata: bool,
+    pub(crate) note: Option<String>,
+    verify: VerifyMode,
+    pub(crate) min_parallel_pages: usize,
+    page_length: PageLength,
+    geometry: LibraryGeometry,
+    byte_aligned: bool,
+    pub(crate) payload: PayloadMode,
+    pub(crate) on_invalid_char: OnInvalidChar,
+    split_size: Option<u64>,
+    pub(crate) pad: PadStrategy,
+    pub(crate) page_checksums: bool,
+    pub(crate) checksum: bool,
+    strict_checksums: bool,
+    memory_budget: Option<usize>,
+    parallel_read: Option<usize>,
+    pub(crate) pad_to_pages: Option<usize>,
+    strict_ascii: bool,
+    pub(crate) location_bias: LocationBias,
+    compression: Compression,
+    pub(crate) passphrase: Option<Passphrase>,
+    seed: Option<u64>,
+    resume: bool,
+    pub(crate) page_cache: Arc<Mutex<PageCache>>,
+    best_effort: bool,
+    progress: bool,
+    on_page: Option<PageCallback>,
+    threads: Threads,
+    pool: OnceLock<Arc<ThreadPool>>,
+    space: OnceLock<Arc<PageSpace>>,
+}
+
+// A caller's page callback and the count it is given, shared by the codec's clones
+#[derive(Clone)]
+struct PageCallback {
+    done: Arc<AtomicUsize>,
+    on_page: Arc<dyn Fn(usize) + Send + Sync>,
+}
+
+impl fmt::Debug for PageCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PageCallback({} pages done)", self.done.load(Ordering::Relaxed))
+    }
+}
+
+impl Default for BabelCodec {
+    fn default() -> Self {
+        BabelCodec {
+            alphabet: Alphabet::default(),
+            format: ArchiveFormat::default(),
+            pages_per_line: 1,
+            preserve_metadata: false,
+            note: None,
+            verify: VerifyMode::default(),
+            min_parallel_pages: DEFAULT_MIN_PARALLEL_PAGES,
+            page_length: PageLength::default(),
+            geometry: LibraryGeometry::default(),
+            byte_aligned: false,
+            payload: PayloadMode::default(),
+            on_invalid_char: OnInvalidChar::default(),
+            split_size: None,
+            pad: PadStrategy::default(),
+            page_checksums: false,
+            checksum: false,
+            strict_checksums: true,
+            memory_budget: None,
+            parallel_read: None,
+            pad_to_pages: None,
+            strict_ascii: false,
+            location_bias: LocationBias::default(),
+            compression: Compression::None,
+            passphrase: None,
+            seed: None,
+            resume: false,
+            page_cache: Arc::default(),
+            best_effort: false,
+            progress: true,
+            on_page: None,
+            threads: Threads::default(),
+            pool: OnceLock::new(),
+            space: OnceLock::new(),
+        }
+    }
+}
+
+impl BabelCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `alphabet` to spell bytes as page text.
+    pub fn with_alphabet(mut self, alphabet: Alphabet) -> Self {
+        self.alphabet = alphabet;
+        // Byte-aligned pages are aligned to the alphabet's blocks
+        if self.byte_aligned {
+            self.space = OnceLock::new();
+            self.pool = OnceLock::new();
+            self.reset_page_cache();
+        }
+        self
+    }
+
+    /// Layout used when writing archives. Decoding detects the format itself.
+    pub fn with_format(mut self, format: ArchiveFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Pack `n` space-separated addresses onto each line of a text archive.
+    pub fn with_pages_per_line(mut self, n: usize) -> Self {
+        self.pages_per_line = n.max(1);
+        self
+    }
+
+    /// Record the input's modification time and unix mode so decode can restore them.
+    pub fn with_preserve_metadata(mut self, preserve: bool) -> Self {
+        self.preserve_metadata = preserve;
+        self
+    }
+
+    /// Attach a free-text note to archives this codec writes.
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+
+    /// How thoroughly encoding checks that addresses resolve back to their pages.
+    pub fn with_verify_mode(mut self, verify: VerifyMode) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Use pages of `length` characters instead of the default 3239.
+    pub fn with_page_length(mut self, length: PageLength) -> Self {
+        self.page_length = length;
+        self.space = OnceLock::new();
+        self.pool = OnceLock::new();
+        self.reset_page_cache();
+        self
+    }
+
+    /// Round the page length down to whole blocks of the alphabet, so every
+    /// page holds whole bytes.
+    pub fn with_byte_aligned_pages(mut self, aligned: bool) -> Self {
+        self.byte_aligned = aligned;
+        self.space = OnceLock::new();
+        self.pool = OnceLock::new();
+        self.reset_page_cache();
+        self
+    }
+
+    /// Characters per page after applying byte alignment.
+    pub fn page_length(&self) -> PageLength {
+        if self.byte_aligned && !self.page_length.holds_whole_blocks(&self.alphabet) {
+            // A length shorter than one block has no aligned length below it
+            PageLength::aligned(self.page_length.get(), &self.alphabet).unwrap_or(self.page_length)
+        } else {
+            self.page_length
+        }
+    }
+
+    /// Lay locations out in hexagons of this shape instead of libraryofbabel.info's.
+    ///
+    /// The geometry is recorded in the header when it is not the default, and
+    /// decoding follows it.
+    pub fn with_geometry(mut self, geometry: LibraryGeometry) -> Self {
+        self.geometry = geometry;
+        self.space = OnceLock::new();
+        self.reset_page_cache();
+        self
+    }
+
+    pub fn geometry(&self) -> LibraryGeometry {
+        self.geometry
+    }
+
+    /// Whether encoding spells the input's bytes or writes it onto pages as text.
+    /// Decoding follows the mode recorded in the archive.
+    pub fn with_payload_mode(mut self, payload: PayloadMode) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// What text mode does with input characters that cannot appear on a page.
+    pub fn with_on_invalid_char(mut self, policy: OnInvalidChar) -> Self {
+        self.on_invalid_char = policy;
+        self
+    }
+
+    /// Have `encode_file` write a manifest plus part files of at most `max_bytes` each.
+    pub fn with_split_size(mut self, max_bytes: Option<u64>) -> Self {
+        self.split_size = max_bytes;
+        self
+    }
+
+    /// Byte range of each page of an archive with `header` written by this codec.
+    ///
+    /// A page length recorded in the header takes the place of the codec's.
+    pub fn layout(&self, header: &ArchiveHeader) -> PageLayout {
+        let length = header.page_length.and_then(|length| PageLength::recorded(length).ok());
+        PageLayout::new(header, length.unwrap_or(self.page_length()))
+    }
+
+    // This codec, or a copy of it using the alphabet and page length `header` records
+    //
+    // A codec left at the default length follows the archive; one given some
+    // other length must agree with it, as pages of one length never decode
+    // with another. The alphabet is followed the same way when it is built in.
+    pub(crate) fn for_header(&self, header: &ArchiveHeader) -> Result<Cow<'_, BabelCodec>, BabelError> {
+        // A built-in alphabet is known from its id; a custom one has to be given to the codec
+        let mut codec = Cow::Borrowed(self);
+        if header.alphabet() != self.alphabet.id() && self.alphabet == Alphabet::default() {
+            if let Some(alphabet) = Alphabet::built_in(header.alphabet()) {
+                codec = Cow::Owned(self.clone().with_alphabet(alphabet));
+            }
+        }
+        // Like the page length, an archive's geometry is followed unless the codec was given its own
+        if let Some(geometry) = header.geometry.filter(|&geometry| geometry != self.geometry) {
+            if self.geometry != LibraryGeometry::default() {
+                return Err(BabelError::GeometryMismatch { codec: self.geometry, archive: geometry });
+            }
+            codec = Cow::Owned(codec.into_owned().with_geometry(geometry));
+        }
+        let Some(length) = header.page_length else {
+            return Ok(codec);
+        };
+        if length == self.page_length().get() {
+            return Ok(codec);
+        }
+        if self.page_length() != PageLength::DEFAULT {
+            return Err(BabelError::PageLengthMismatch { codec: self.page_length().get(), archive: length });
+        }
+        let codec = codec.into_owned().with_byte_aligned_pages(false).with_page_length(PageLength::recorded(length)?);
+        Ok(Cow::Owned(codec))
+    }
+
+    /// What fills the rest of the final page.
+    pub fn with_pad_strategy(mut self, pad: PadStrategy) -> Self {
+        self.pad = pad;
+        self
+    }
+
+    /// Store a CRC-32 of every page in the header so decoding can spot corrupt addresses.
+    pub fn with_page_checksums(mut self, enabled: bool) -> Self {
+        self.page_checksums = enabled;
+        self
+    }
+
+    /// Record a CRC-32 of the whole file in the header, checked once decode
+    /// has reassembled it, so a corrupt address cannot pass unnoticed.
+    pub fn with_checksum(mut self, enabled: bool) -> Self {
+        self.checksum = enabled;
+        self
+    }
+
+    /// Stop decoding at the first page whose checksum does not match, or at a
+    /// file that does not match its own (the default), or just warn and keep going.
+    pub fn with_strict_checksums(mut self, strict: bool) -> Self {
+        self.strict_checksums = strict;
+        self
+    }
+
+    /// Keep roughly at most `bytes` of input, page text and search state in
+    /// flight while encoding.
+    ///
+    /// The budget sets how many pages are read and searched together, at
+    /// least one, and no more threads search them than a batch has pages; see
+    /// [`BabelCodec::threads`]. An archive's addresses must go to disk as they
+    /// are found, so only unsplit text archives without byte verification can
+    /// keep to a budget, and encoding any other way, or encrypting, is refused.
+    pub fn with_memory_budget(mut self, bytes: Option<usize>) -> Self {
+        self.memory_budget = bytes;
+        self.pool = OnceLock::new();
+        self
+    }
+
+    // Refuse an encode that would hold more than its memory budget: every
+    // address, or the whole input to encrypt
+    fn check_memory_budget(&self) -> Result<(), BabelError> {
+        if self.memory_budget.is_none() {
+            return Ok(());
+        }
+        if !self.streams() {
+            return Err(BabelError::UnsupportedOptions(
+                "A memory budget needs an unsplit text archive without byte verification".to_string(),
+            ));
+        }
+        if self.passphrase.is_some() {
+            return Err(BabelError::UnsupportedOptions("An encrypted encode cannot keep to a memory budget".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Have `encode_file` read its input through this many file handles at
+    /// once, each covering its own region of every batch. Helps on storage
+    /// that only reaches full speed with several reads queued; `None` reads
+    /// sequentially.
+    pub fn with_parallel_read(mut self, handles: Option<usize>) -> Self {
+        self.parallel_read = handles;
+        self
+    }
+
+    /// Follow the real pages with filler pages of random text until the archive
+    /// holds exactly `pages` addresses. The header records how many pages are
+    /// real, so decoding skips the filler. Encoding fails if the input alone
+    /// needs more pages.
+    pub fn with_pad_to_pages(mut self, pages: Option<usize>) -> Self {
+        self.pad_to_pages = pages;
+        self
+    }
+
+    /// How random page locations are drawn. Purely cosmetic: any location
+    /// decodes the same.
+    pub fn with_location_bias(mut self, bias: LocationBias) -> Self {
+        self.location_bias = bias;
+        self
+    }
+
+    /// Compress the input before spelling it onto pages. The archive records
+    /// the compression, so decoding undoes it without being told. Text mode
+    /// writes its input as it is and ignores this.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Encrypt what is encoded under this passphrase, and decrypt archives with it.
+    ///
+    /// The bytes are encrypted after any compression with ChaCha20-Poly1305,
+    /// under a key derived with Argon2id from the passphrase and a salt drawn
+    /// for each encode; the header records the salt and nonce but never the key.
+    /// Text mode writes its input as page text and cannot be encrypted. Needs
+    /// the `encryption` feature.
+    pub fn with_passphrase(mut self, passphrase: Option<String>) -> Self {
+        self.passphrase = passphrase.map(Passphrase);
+        self
+    }
+
+    // Decrypt the bytes on an archive's pages, if it is encrypted
+    pub(crate) fn decrypt(&self, header: &ArchiveHeader, bytes: Vec<u8>) -> Result<Vec<u8>, BabelError> {
+        match &header.encryption {
+            Some(encryption) => {
+                let passphrase = self.passphrase.as_ref().ok_or(BabelError::PassphraseRequired)?;
+                self.progress(format_args!("Decrypting..."));
+                encryption.open(passphrase, &bytes)
+            }
+            None => Ok(bytes),
+        }
+    }
+
+    // The compression an encode applies, which text mode never does
+    fn compression(&self) -> Compression {
+        match self.payload {
+            PayloadMode::Bytes => self.compression,
+            PayloadMode::Text => Compression::None,
+        }
+    }
+
+    /// Draw page locations and random padding from `seed`, so encoding the
+    /// same input twice gives the same addresses. `None` draws fresh ones.
+    ///
+    /// A seeded encode also leaves the creation time out of the header, so
+    /// the whole archive comes out byte for byte the same.
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Checkpoint the addresses of finished pages to `<output>.partial` as an
+    /// encode to a file goes, and pick up from that checkpoint if one is there.
+    ///
+    /// A resumed encode reads the input again from the start but only checks
+    /// each checkpointed page against its address, searching just the pages
+    /// that are left; an input that changed since is an error. The checkpoint
+    /// is deleted once the archive is written. Encryption draws a fresh salt
+    /// each time, so encrypted encodes cannot resume.
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Reject decoded output holding anything but printable ASCII, tabs and
+    /// line breaks. Catches corruption that still decodes to valid UTF-8, such
+    /// as a byte flipped into the control range.
+    pub fn with_strict_ascii(mut self, strict_ascii: bool) -> Self {
+        self.strict_ascii = strict_ascii;
+        self
+    }
+
+    // With strict ASCII on, fail at the first unexpected byte; `offset` is where `bytes` starts in the output
+    fn check_ascii(&self, bytes: &[u8], offset: u64) -> Result<(), BabelError> {
+        if !self.strict_ascii {
+            return Ok(());
+        }
+        match bytes.iter().position(|&b| !(b.is_ascii_graphic() || matches!(b, b' ' | b'\t' | b'\n' | b'\r'))) {
+            Some(i) => Err(BabelError::NonPrintableByte { offset: offset + i as u64, byte: bytes[i] }),
+            None => Ok(()),
+        }
+    }
+
+    /// Let `decode_file` recover archives whose size line is missing by
+    /// decoding every page in full. Only the padding character is trimmed from
+    /// the end, so up to a page of extra trailing bytes may remain.
+    pub fn with_best_effort(mut self, best_effort: bool) -> Self {
+        self.best_effort = best_effort;
+        self
+    }
+
+    /// Log what each encode and decode step is doing, at info level through
+    /// the `log` facade; on by default. Warnings are logged either way.
+    pub fn with_progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    // A record for the `log` facade
+    pub(crate) fn log(&self, level: Level, message: fmt::Arguments) {
+        log!(level, "{}", message);
+    }
+
+    pub(crate) fn warn(&self, message: fmt::Arguments) {
+        self.log(Level::Warn, message);
+    }
+
+    pub(crate) fn progress(&self, message: fmt::Arguments) {
+        if self.progress {
+            self.log(Level::Info, message);
+        }
+    }
+
+    /// Call `on_page` with the number of pages searched or decoded so far,
+    /// each time more are done, for a progress display of the caller's own.
+    ///
+    /// The count starts from zero with every encode or decode. Pages are done
+    /// on the codec's worker threads, so the callback may run on any of them,
+    /// and counts from different threads can arrive out of order.
+    pub fn with_on_page(mut self, on_page: impl Fn(usize) + Send + Sync + 'static) -> Self {
+        self.on_page = Some(PageCallback { done: Arc::new(AtomicUsize::new(0)), on_page: Arc::new(on_page) });
+        self
+    }
+
+    // Count pages for the callback from zero
+    fn start_page_count(&self) {
+        if let Some(callback) = &self.on_page {
+            callback.done.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn pages_done(&self, pages: u64) {
+        if let Some(callback) = &self.on_page {
+            let done = callback.done.fetch_add(pages as usize, Ordering::Relaxed) + pages as usize;
+            (callback.on_page)(done);
+        }
+    }
+
+    /// How many threads search and decode pages in parallel.
+    pub fn with_threads(mut self, threads: Threads) -> Self {
+        self.threads = threads;
+        self.pool = OnceLock::new();
+        self
+    }
+
+    /// The threads pages are searched and decoded on: those asked for, or
+    /// fewer when a memory budget leaves room for fewer pages at once.
+    pub fn threads(&self) -> Threads {
+        if self.memory_budget.is_none() {
+            return self.threads;
+        }
+        let pages = self.pages_per_batch(self.page_length().get());
+        if self.threads.count() > pages {
+            Threads::Fixed(pages)
+        } else {
+            self.threads
+        }
+    }
+
+    // Random source for page `index`: stream 0 draws its location, 1 its
+    // padding, and 2 the permutation of a whole archive's locations
+    //
+    // Under a seed every page gets a stream of its own, so what a page draws
+    // does not depend on which thread searches it or how pages are batched.
+    pub(crate) fn rng_for(&self, index: usize, stream: u8) -> StdRng {
+        match self.seed {
+            Some(seed) => {
+                let mut key = [0u8; 32];
+                key[..8].copy_from_slice(&seed.to_le_bytes());
+                key[8..16].copy_from_slice(&(index as u64).to_le_bytes());
+                key[16] = stream;
+                StdRng::from_seed(key)
+            }
+            None => StdRng::from_rng(rand::thread_rng()).expect("thread_rng never fails"),
+        }
+    }
+
+    pub(crate) fn page_rng(&self, index: usize) -> StdRng {
+        self.rng_for(index, 0)
+    }
+
+    // Pad `chunk`, page `index` of the file, to `length` with the codec's strategy
+    pub(crate) fn pad_page(&self, chunk: &str, length: usize, index: usize) -> String {
+        self.pad.pad_with(chunk, length, &self.alphabet, &mut self.rng_for(index, 1))
+    }
+
+    // Run `op` on this codec's thread pool, or rayon's global pool by default
+    pub(crate) fn in_pool<T: Send>(&self, op: impl FnOnce() -> T + Send) -> T {
+        let threads = self.threads();
+        if threads == Threads::Logical {
+            return op();
+        }
+        let num_threads = threads.count();
+        let pool = self.pool.get_or_init(|| {
+            Arc::new(ThreadPoolBuilder::new().num_threads(num_threads).build()
+                .expect("failed to start the page search threads"))
+        });
+        pool.install(op)
+    }
+
+    // Input bytes spelling about a batch of pages, in whole blocks so that
+    // only the last read of an input can end partway through one
+    pub(crate) fn batch_bytes(&self, page_length: usize) -> usize {
+        let block = self.alphabet.block();
+        let chars = (self.pages_per_batch(page_length) * page_length) as u64;
+        block.bytes_before(chars).max(block.bytes()) as usize
+    }
+
+    // Pages read and searched together, bounded by the memory budget
+    pub(crate) fn pages_per_batch(&self, page_length: usize) -> usize {
+        match self.memory_budget {
+            Some(budget) => (budget / (page_length * BYTES_PER_PAGE_CHAR)).clamp(1, PAGES_PER_BATCH),
+            None => PAGES_PER_BATCH,
+        }
+    }
+
+    /// Search or decode fewer than `n` pages on the calling thread instead of
+    /// handing them to rayon. `0` always parallelizes; `usize::MAX` never does.
+    pub fn with_min_parallel_pages(mut self, n: usize) -> Self {
+        self.min_parallel_pages = n;
+        self
+    }
+
+    pub fn alphabet(&self) -> &Alphabet {
+        &self.alphabet
+    }
+
+    // Computed on first use and shared by every page this codec touches
+    pub(crate) fn space(&self) -> Result<&PageSpace, BabelError> {
+        if let Some(space) = self.space.get() {
+            return Ok(space);
+        }
+        let space = PageSpace::shared(self.page_length().get(), self.geometry)?;
+        Ok(self.space.get_or_init(|| space))
+    }
+
+    /// `bytes` spelled in this codec's alphabet, as they would appear on pages
+    /// before any padding: two symbols per byte, or five per three bytes in base29.
+    pub fn bytes_to_babel_text(&self, bytes: &[u8]) -> String {
+        self.spell_bytes(bytes, bytes.len() > PARALLEL_CONVERSION_BYTES)
+    }
+
+    fn spell_bytes(&self, bytes: &[u8], parallel: bool) -> String {
+        let block_bytes = self.alphabet.block().bytes() as usize;
+        let spell = |part: &[u8]| {
+            let mut text = String::new();
+            for block in part.chunks(block_bytes) {
+                self.alphabet.spell_block(block, &mut text);
+            }
+            text
+        };
+        if parallel {
+            self.in_pool(|| bytes.par_chunks(block_bytes * CONVERSION_BLOCKS).map(spell).collect())
+        } else {
+            spell(bytes)
+        }
+    }
+
+    /// The bytes spelled by `text`; the inverse of [`BabelCodec::bytes_to_babel_text`].
+    ///
+    /// Trailing periods are padding and ignored, except for alphabets such as
+    /// base29 that spell with them, where `text` must end with the bytes. Text
+    /// that ends partway through a byte, or a block of bytes, is an error.
+    pub fn babel_text_to_bytes(&self, text: &str) -> Result<Vec<u8>, BabelError> {
+        let text = if self.alphabet.pads_with_period() { text.trim_end_matches(PAD_CHAR) } else { text };
+        self.read_text(text)
+    }
+
+    // The bytes spelled by exactly `text`, padding and all
+    fn read_text(&self, text: &str) -> Result<Vec<u8>, BabelError> {
+        let chars: Vec<char> = text.chars().collect();
+        let block = self.alphabet.block();
+        let Some(bytes) = block.bytes_in(chars.len() as u64) else {
+            return Err(BabelError::UnpairedSymbol { offset: chars.len() - 1 });
+        };
+
+        self.read_blocks(&chars, bytes as usize > PARALLEL_CONVERSION_BYTES)
+    }
+
+    // Bytes spelled by whole blocks of symbols and maybe a short last one;
+    // both paths stop at the first bad block
+    fn read_blocks(&self, chars: &[char], parallel: bool) -> Result<Vec<u8>, BabelError> {
+        let block_chars = self.alphabet.block().chars() as usize;
+        let read = |(i, part): (usize, &[char])| {
+            let mut bytes = Vec::with_capacity(part.len());
+            for (j, block) in part.chunks(block_chars).enumerate() {
+                self.alphabet.read_block(block, i * block_chars * CONVERSION_BLOCKS + j * block_chars, &mut bytes)?;
+            }
+            Ok::<_, BabelError>(bytes)
+        };
+        if parallel {
+            let parts: Vec<Vec<u8>> =
+                self.in_pool(|| chars.par_chunks(block_chars * CONVERSION_BLOCKS).enumerate().map(read).collect::<Result<_, _>>())?;
+            Ok(parts.concat())
+        } else {
+            read((0, chars))
+        }
+    }
+
+    // Search a batch of pages whose first page is `first_index` in the file
+    pub(crate) fn locate_pages(&self, chunks: &[String], first_index: usize) -> Result<(Vec<String>, VerifyStats), BabelError> {
+        let (locations, stats) = self.locate_addresses(chunks, first_index)?;
+        Ok((locations.iter().map(|address| address.to_string_in(&self.geometry)).collect(), stats))
+    }
+
+    // `locate_pages`, keeping the addresses parsed
+    fn locate_addresses(&self, chunks: &[String], first_index: usize) -> Result<(Vec<Address>, VerifyStats), BabelError> {
+        let space = self.space()?;
+        let locate = |(i, chunk): (usize, &String)| {
+            let address = search(chunk, space, self.location_bias, &mut self.page_rng(first_index + i));
+            self.pages_done(1);
+            address
+        };
+
+        // Only parallelize when there are enough pages to pay for it
+        let parallel = chunks.len() >= self.min_parallel_pages;
+        self.in_pool(|| {
+            let locations: Vec<Address> = if parallel {
+                chunks.par_iter().enumerate().map(locate).collect::<Result<_, BabelError>>()?
+            } else {
+                chunks.iter().enumerate().map(locate).collect::<Result<_, BabelError>>()?
+            };
+
+            let stats = match self.verify {
+                VerifyMode::Full => verify_pages(chunks, &locations, space, first_index, parallel),
+                VerifyMode::None | VerifyMode::FullBytes => VerifyStats::default(),
+            };
+            // The error only carries page numbers, so name each address here
+            for &index in &stats.failed_indices {
+                let address = locations[index - first_index].to_string_in(&space.geometry);
+                self.log(Level::Error, format_args!("page {} at {} does not read back as its text", index, address));
+            }
+            Ok((locations, stats))
+        })
+    }
+
+    // Search every page of the stream
+    //
+    // Every page is verified before failing, so the error lists all bad pages.
+    fn encode_pages<R: Read>(
+        &self,
+        reader: R,
+        checkpoint: Option<&mut Checkpoint>,
+    ) -> Result<EncodedPages, BabelError> {
+        let mut addresses = Vec::new();
+        let mut encoded = self.encode_pages_with(reader, checkpoint, |batch| {
+            addresses.extend(batch);
+            Ok(())
+        })?;
+        encoded.addresses = addresses;
+        Ok(encoded)
+    }
+
+    // `encode_pages`, handing each batch of addresses to `sink` instead of keeping them
+    //
+    // Pages already in `checkpoint` are reused, and every batch is added to it.
+    fn encode_pages_with<R: Read>(
+        &self,
+        reader: R,
+        mut checkpoint: Option<&mut Checkpoint>,
+        mut sink: impl FnMut(Vec<Address>) -> Result<(), BabelError>,
+    ) -> Result<EncodedPages, BabelError> {
+        let mut encoder = PageEncoder::new(self, reader, self.compression(), true)?;
+        if let Some(checkpoint) = checkpoint.as_deref_mut() {
+            encoder.resumed = checkpoint.take_resumed();
+            if !encoder.resumed.is_empty() {
+                self.progress(format_args!("Resuming after {} checkpointed pages", encoder.resumed.len()));
+            }
+        }
+        let mut stats = VerifyStats::default();
+        while let Some((batch, batch_stats)) = encoder.next_batch()? {
+            if let Some(checkpoint) = checkpoint.as_deref_mut() {
+                checkpoint.push(&self.address_strings(&batch))?;
+            }
+            sink(batch)?;
+            stats.merge(batch_stats);
+        }
+        // A checkpoint with pages to spare was taken of a longer input
+        if encoder.pages < encoder.resumed.len() {
+            return Err(BabelError::CheckpointMismatch { index: encoder.pages });
+        }
+
+        if !stats.all_passed() {
+            return Err(BabelError::VerificationFailed(stats));
+        }
+
+        let pages = encoder.pages;
+        let filler = match self.pad_to_pages {
+            Some(limit) if pages > limit => {
+                return Err(BabelError::TooManyPages { pages, limit });
+            }
+            Some(limit) => self.filler_pages(pages, limit - pages)?,
+            None => Vec::new(),
+        };
+
+        let checksum = self.checksum.then(|| match self.payload {
+            PayloadMode::Bytes => encoder.input_crc.get().finish(),
+            PayloadMode::Text => encoder.text_crc.finish(),
+        });
+        Ok(EncodedPages {
+            addresses: Vec::new(),
+            pages,
+            filler,
+            size: encoder.total,
+            stats,
+            page_crcs: encoder.page_crcs,
+            checksum,
+            encryption: encoder.encryption,
+        })
+    }
+
+    /// Addresses of the pages spelling what `reader` holds, searched a batch at a time as they are asked for.
+    ///
+    /// Only one batch of pages is in memory at once, so the addresses can be
+    /// written out as they come however long the input is. They arrive in page
+    /// order, each verified like [`BabelCodec::encode_bytes`]; a batch that
+    /// fails verification is an error before any of its addresses are handed
+    /// out. As with `encode_bytes` there is no header, so compression and filler
+    /// pages are left out; [`EncodedAddresses::size`] gives the length to
+    /// decode with once the iterator is exhausted.
+    pub fn encode_iter<'a, R: Read + 'a>(&'a self, reader: R) -> Result<EncodedAddresses<'a>, BabelError> {
+        Ok(EncodedAddresses {
+            encoder: PageEncoder::new(self, reader, Compression::None, false)?,
+            batch: Vec::new().into_iter(),
+            done: false,
+        })
+    }
+
+    // Addresses of `count` pages of random text, each at a random location,
+    // following the `first_index` real pages
+    fn filler_pages(&self, first_index: usize, count: usize) -> Result<Vec<String>, BabelError> {
+        let space = self.space()?;
+        let filler = |index: usize| {
+            let mut rng = self.page_rng(index);
+            let page = PadStrategy::RandomAlphabet.pad_with("", space.length, &self.alphabet, &mut rng);
+            search(&page, space, self.location_bias, &mut rng)
+        };
+        (first_index..first_index + count).map(|index| filler(index).map(|address| address.to_string_in(&space.geometry))).collect()
+    }
+
+    // `addresses` as an archive of this codec's geometry writes them
+    fn address_strings(&self, addresses: &[Address]) -> Vec<String> {
+        addresses.iter().map(|address| address.to_string_in(&self.geometry)).collect()
+    }
+
+    pub(crate) fn new_header(&self, extension: &str, encoded: &mut EncodedPages) -> ArchiveHeader {
+        let mut header = ArchiveHeader::new(extension, encoded.size, &self.alphabet);
+        header.payload = self.payload;
+        header.page_crcs = std::mem::take(&mut encoded.page_crcs);
+        header.crc32 = encoded.checksum;
+        if self.pad_to_pages.is_some() {
+            header.pages = Some(encoded.pages as u64);
+        }
+        header.note = self.note.clone();
+        if self.page_length() != PageLength::DEFAULT {
+            header.page_length = Some(self.page_length().get());
+        }
+        if self.geometry != LibraryGeometry::default() {
+            header.geometry = Some(self.geometry);
+        }
+        if self.seed.is_some() {
+            header.created = None;
+        }
+        header.compression = self.compression();
+        header.encryption = encoded.encryption.take();
+        header
+    }
+
+    fn new_archive(&self, header: ArchiveHeader, encoded: &mut EncodedPages) -> Result<Archive, BabelError> {
+        let mut archive = Archive {
+            header,
+            addresses: self.address_strings(&std::mem::take(&mut encoded.addresses)),
+            filler: std::mem::take(&mut encoded.filler),
+            pages_per_line: self.pages_per_line,
+        };
+        if self.format == ArchiveFormat::SelfContained {
+            self.embed_header(&mut archive)?;
+        }
+        Ok(archive)
+    }
+
+    // Whether an encode can spool its addresses to disk rather than hold them
+    //
+    // Only the plain text format writes addresses straight after the header;
+    // the others pack, split or prefix them, and byte verification decodes
+    // the whole archive from memory.
+    fn streams(&self) -> bool {
+        self.format == ArchiveFormat::Text && self.split_size.is_none() && self.verify != VerifyMode::FullBytes
+    }
+
+    // Encode `reader` as a text archive, spooling address lines at `spool_path`
+    //
+    // Memory stays at one batch of pages however long the input is, and the
+    // output is byte-for-byte what writing a collected archive would give.
+    fn encode_spooled<R: Read, W: Write>(
+        &self,
+        reader: R,
+        spool_path: &Path,
+        checkpoint: Option<&mut Checkpoint>,
+        new_header: impl FnOnce(&mut EncodedPages) -> Result<ArchiveHeader, BabelError>,
+        writer: W,
+    ) -> Result<EncodedPages, BabelError> {
+        let mut spool = AddressSpool::create(spool_path, self.pages_per_line)?;
+        let mut encoded = self.encode_pages_with(reader, checkpoint, |batch| Ok(spool.push(self.address_strings(&batch))?))?;
+        let header = new_header(&mut encoded)?;
+        spool.push(std::mem::take(&mut encoded.filler))?;
+
+        let archive = Archive { header, addresses: Vec::new(), filler: Vec::new(), pages_per_line: self.pages_per_line };
+        archive.write_spooled(spool, writer)?;
+        Ok(encoded)
+    }
+
+    /// Encode everything `reader` yields into an archive written to `writer`.
+    ///
+    /// The input is consumed in page-sized batches, so its length need not be
+    /// known up front; the header is written once the stream is exhausted.
+    /// In the text format the addresses wait in a file under the system
+    /// temporary directory until then, so memory use does not grow with the
+    /// input. Returns the number of bytes encoded.
+    pub fn encode_reader<R: Read, W: Write>(&self, mut reader: R, extension: &str, writer: W) -> Result<u64, BabelError> {
+        self.check_memory_budget()?;
+        if self.streams() {
+            static SPOOLS: AtomicUsize = AtomicUsize::new(0);
+            let spool_path = std::env::temp_dir().join(format!(
+                "babel-{}-{}.addresses", std::process::id(), SPOOLS.fetch_add(1, Ordering::Relaxed)
+            ));
+            let encoded = self.encode_spooled(reader, &spool_path, None, |encoded| Ok(self.new_header(extension, encoded)), writer)?;
+            return Ok(encoded.size);
+        }
+
+        // A stream cannot be read twice, so byte verification keeps a copy of it
+        let mut input = Vec::new();
+        let mut encoded = if self.verify == VerifyMode::FullBytes {
+            reader.read_to_end(&mut input)?;
+            self.encode_pages(&input[..], None)?
+        } else {
+            self.encode_pages(reader, None)?
+        };
+        let header = self.new_header(extension, &mut encoded);
+        let archive = self.new_archive(header, &mut encoded)?;
+        if self.verify == VerifyMode::FullBytes {
+            self.check_bytes(&archive, &input[..])?;
+        }
+        archive.write(self.format, writer)?;
+        Ok(encoded.size)
+    }
+
+    /// Addresses of the pages spelling `data`, one per page and in order.
+    ///
+    /// Nothing touches the filesystem, so callers keep the addresses however
+    /// they like. Chunking, padding and verification are the same as for
+    /// [`BabelCodec::encode_file`]; filler pages are left out, and so is
+    /// compression, since only `data.len()` is kept to decode with. Pass the
+    /// addresses and `data.len()` to [`BabelCodec::decode_bytes`] to get the
+    /// data back.
+    pub fn encode_bytes(&self, data: &[u8]) -> Result<Vec<String>, BabelError> {
+        Ok(self.address_strings(&self.encode_parsed(data)?))
+    }
+
+    // `encode_bytes`, keeping the addresses parsed
+    pub(crate) fn encode_parsed(&self, data: &[u8]) -> Result<Vec<Address>, BabelError> {
+        if !self.compression.is_none() || self.passphrase.is_some() {
+            return self.clone().with_compression(Compression::None).with_passphrase(None).encode_parsed(data);
+        }
+        let encoded = self.encode_pages(data, None)?;
+        if self.verify == VerifyMode::FullBytes {
+            let archive = self.bare_archive(self.address_strings(&encoded.addresses), encoded.size);
+            self.check_bytes(&archive, data)?;
+        }
+        Ok(encoded.addresses)
+    }
+
+    /// The `original_size` bytes spelled by `addresses` from [`BabelCodec::encode_bytes`].
+    ///
+    /// Padding past `original_size` is dropped whatever the pad strategy was,
+    /// and too few addresses for that size is an error.
+    pub fn decode_bytes(&self, addresses: &[String], original_size: usize) -> Result<Vec<u8>, BabelError> {
+        let parsed = addresses
+            .iter()
+            .map(|address| Address::parse_in(address, &self.geometry))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.decode_parsed(&parsed, original_size)
+    }
+
+    // `decode_bytes` of addresses already parsed
+    pub(crate) fn decode_parsed(&self, addresses: &[Address], original_size: usize) -> Result<Vec<u8>, BabelError> {
+        let layout = self.layout(&self.bare_archive(Vec::new(), original_size as u64).header);
+        let needed = layout.page_count();
+        if addresses.len() < needed {
+            return Err(BabelError::TooFewPages { pages: addresses.len(), needed });
+        }
+        let space = self.space()?;
+        self.start_page_count();
+        let decode = |address: &Address| {
+            let page = get_page(address, space)?;
+            self.pages_done(1);
+            Ok(page)
+        };
+        let pages: Vec<String> = if needed >= self.min_parallel_pages {
+            self.in_pool(|| addresses[..needed].par_iter().map(decode).collect::<Result<_, BabelError>>())?
+        } else {
+            addresses[..needed].iter().map(decode).collect::<Result<_, BabelError>>()?
+        };
+
+        // Padding past the size need not spell bytes, so it is never converted
+        let mut text = pages.concat();
+        text.truncate(layout.text_len() as usize);
+        let mut data = match self.payload {
+            PayloadMode::Bytes => self.read_text(&text)?,
+            PayloadMode::Text => text.into_bytes(),
+        };
+        data.truncate(original_size);
+        self.check_ascii(&data, 0)?;
+        Ok(data)
+    }
+
+    // An archive of `addresses` with only what decoding needs in its header
+    fn bare_archive(&self, addresses: Vec<String>, size: u64) -> Archive {
+        let mut header = ArchiveHeader::new("", size, &self.alphabet).with_payload(self.payload);
+        if self.page_length() != PageLength::DEFAULT {
+            header.page_length = Some(self.page_length().get());
+        }
+        if self.geometry != LibraryGeometry::default() {
+            header.geometry = Some(self.geometry);
+        }
+        Archive { header, addresses, filler: Vec::new(), pages_per_line: 1 }
+    }
+
+    /// Encode several readers as one logical byte stream under a single header.
+    ///
+    /// Readers are drained in order and never buffered whole, so this is
+    /// equivalent to `encode_reader` over their concatenation.
+    pub fn encode_readers<W: Write>(&self, readers: Vec<Box<dyn Read>>, extension: &str, writer: W) -> Result<u64, BabelError> {
+        self.encode_reader(ChainedReaders::new(readers), extension, writer)
+    }
+
+    /// Encode the file at `input_path`, returning the page verification summary.
+    pub fn encode_file(&self, input_path: &str, output_path: Option<&str>) -> Result<VerifyStats, BabelError> {
+        self.progress(format_args!("Reading input file..."));
+
+        let extension = Path::new(input_path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("");
+
+        let output_path = match output_path {
+            Some(path) => path.to_string(),
+            None => {
+                let mut path = Path::new(input_path).to_path_buf();
+                path.set_extension("babel");
+                path.to_string_lossy().to_string()
+            }
+        };
+
+        let open = || -> Result<Box<dyn Read>, BabelError> {
+            Ok(match self.parallel_read {
+                Some(handles) => Box::new(ParallelReader::open(input_path, handles)?),
+                None => Box::new(BufReader::new(fs::File::open(input_path)?)),
+            })
+        };
+        self.encode_to_path(open, &output_path, |encoded| {
+            let mut header = self.new_header(extension, encoded);
+            header.name = Path::new(input_path).file_name().map(|name| name.to_string_lossy().into_owned());
+            if self.preserve_metadata {
+                metadata::capture(Path::new(input_path), &mut header)?;
+            }
+            Ok(header)
+        })
+    }
+
+    // Encode what `open` reads to an archive at `output_path`, under the header `new_header` builds
+    //
+    // `open` is called again to check the bytes when the verify mode asks for it.
+    pub(crate) fn encode_to_path<'a>(
+        &self,
+        open: impl Fn() -> Result<Box<dyn Read + 'a>, BabelError>,
+        output_path: &str,
+        new_header: impl FnOnce(&mut EncodedPages) -> Result<ArchiveHeader, BabelError>,
+    ) -> Result<VerifyStats, BabelError> {
+        if self.resume && self.passphrase.is_some() {
+            return Err(BabelError::UnsupportedOptions("An encrypted encode cannot be resumed".to_string()));
+        }
+        if self.split_size.is_some() && self.format == ArchiveFormat::SelfContained {
+            return Err(BabelError::UnsupportedOptions("A self-contained archive cannot be split".to_string()));
+        }
+        self.check_memory_budget()?;
+        let mut checkpoint = if self.resume { Some(Checkpoint::open(output_path)?) } else { None };
+
+        if self.streams() {
+            // The spool sits beside the output, clear of its own `.tmp` file
+            let mut output = TempFile::create(output_path)?;
+            let spool_path = format!("{}.addresses", output_path);
+            let encoded = self.encode_spooled(
+                open()?, Path::new(&spool_path), checkpoint.as_mut(), new_header, BufWriter::new(&mut output),
+            )?;
+            output.commit()?;
+            self.finish_checkpoint(checkpoint)?;
+            self.progress(format_args!("Wrote {}", output_path));
+            self.progress(format_args!("Encoding complete!"));
+            return Ok(encoded.stats);
+        }
+
+        let mut encoded = self.encode_pages(open()?, checkpoint.as_mut())?;
+        let header = new_header(&mut encoded)?;
+
+        let archive = self.new_archive(header, &mut encoded)?;
+        if self.verify == VerifyMode::FullBytes {
+            self.progress(format_args!("Verifying decoded bytes..."));
+            encoded.stats = self.check_bytes(&archive, open()?)?;
+        }
+
+        self.progress(format_args!("Writing to {}...", output_path));
+        match self.split_size {
+            Some(max_bytes) => {
+                let parts = archive.write_split(self.format, Path::new(output_path), max_bytes)?;
+                self.progress(format_args!("Split addresses across {} parts", parts.len()));
+            }
+            None => {
+                let mut output = TempFile::create(output_path)?;
+                archive.write(self.format, BufWriter::new(&mut output))?;
+                output.commit()?;
+            }
+        }
+        self.finish_checkpoint(checkpoint)?;
+
+        self.progress(format_args!("Encoding complete!"));
+        Ok(encoded.stats)
+    }
+
+    // Delete the checkpoint of an encode whose archive is now written
+    fn finish_checkpoint(&self, checkpoint: Option<Checkpoint>) -> Result<(), BabelError> {
+        if let Some(checkpoint) = checkpoint {
+            self.progress(format_args!("Removing checkpoint {}", checkpoint.path().display()));
+            checkpoint.remove()?;
+        }
+        Ok(())
+    }
+
+    // Compare a generated page with the checksum recorded for it, if any
+    fn check_page(&self, header: &ArchiveHeader, index: usize, page: &str) -> Result<(), BabelError> {
+        let Some(&expected) = header.page_crcs.get(index) else {
+            return Ok(());
+        };
+        if crc32(page.as_bytes()) == expected {
+            return Ok(());
+        }
+        if self.strict_checksums {
+            return Err(BabelError::PageChecksumMismatch { index });
+        }
+        self.warn(format_args!("page {} does not match its checksum", index));
+        Ok(())
+    }
+
+    // Compare the reassembled file's CRC-32 with the one the header records
+    pub(crate) fn check_crc32(&self, expected: u32, found: u32) -> Result<(), BabelError> {
+        if found == expected {
+            return Ok(());
+        }
+        if self.strict_checksums {
+            return Err(BabelError::ChecksumMismatch { expected, found });
+        }
+        self.warn(format_args!("{}", BabelError::ChecksumMismatch { expected, found }));
+        Ok(())
+    }
+
+    pub(crate) fn check_alphabet(&self, header: &ArchiveHeader) -> Result<(), BabelError> {
+        if header.alphabet() != self.alphabet.id() {
+            return Err(BabelError::AlphabetMismatch {
+                expected: self.alphabet.id().to_string(),
+                found: header.alphabet().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Decode `archive` one page at a time, yielding each page's bytes in order.
+    pub(crate) fn decode_chunks<'a>(&'a self, archive: &'a Archive) -> Result<DecodedChunks<'a>, BabelError> {
+        let (header, addresses) = match self.embedded_header(archive)? {
+            Some((header, pages)) => {
+                let end = header.pages.map_or(archive.addresses.len(), |real| pages + real as usize);
+                let addresses = archive.addresses.get(pages..end).unwrap_or_default();
+                (Cow::Owned(header), addresses)
+            }
+            None => (Cow::Borrowed(&archive.header), archive.addresses.as_slice()),
+        };
+        let codec = self.for_header(&header)?;
+        codec.check_alphabet(&header)?;
+        codec.space()?;
+        Ok(DecodedChunks {
+            payload: header.payload(),
+            layout: codec.layout(&header),
+            codec,
+            header,
+            addresses: addresses.iter(),
+            index: 0,
+            carry: String::new(),
+        })
+    }
+
+    /// Decode `archive` page by page into `writer`, returning the number of bytes written.
+    ///
+    /// A directory archive holds several files and is refused; decode it with
+    /// [`BabelCodec::decode_file`].
+    pub fn decode_archive<W: Write>(&self, archive: &Archive, mut writer: W) -> Result<u64, BabelError> {
+        let chunks = self.decode_chunks(archive)?;
+        check_streamable(chunks.header())?;
+        self.start_page_count();
+        let mut reader = self.chunk_reader(chunks)?;
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut written = 0;
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            self.check_ascii(&buf[..n], written)?;
+            writer.write_all(&buf[..n])?;
+            written += n as u64;
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// Decode only bytes `range` of `archive` into `writer`, returning the number of bytes written.
+    ///
+    /// Each page holds a fixed slice of the file, so only the pages the range
+    /// touches are generated. A compressed or encrypted archive has no such map
+    /// and is decoded from the start, with the bytes before the range thrown away.
+    /// The range is cut short at the end of the file, and a directory archive
+    /// is refused like it is by [`BabelCodec::decode_archive`].
+    pub fn decode_range<W: Write>(&self, archive: &Archive, range: Range<u64>, mut writer: W) -> Result<u64, BabelError> {
+        let mut chunks = self.decode_chunks(archive)?;
+        let header = chunks.header().clone();
+        check_streamable(&header)?;
+        let size = header.size();
+        let range = range.start.min(size)..range.end.min(size);
+        if !header.compression().is_none() || header.is_encrypted() {
+            let mut reader = self.chunk_reader(chunks)?;
+            io::copy(&mut (&mut reader).take(range.start), &mut io::sink())?;
+            let mut slice = Vec::new();
+            reader.take(range.end.saturating_sub(range.start)).read_to_end(&mut slice)?;
+            self.check_ascii(&slice, range.start)?;
+            writer.write_all(&slice)?;
+            writer.flush()?;
+            return Ok(slice.len() as u64);
+        }
+
+        let Some(first) = chunks.layout.page_of(range.start).filter(|_| !range.is_empty()) else {
+            writer.flush()?;
+            return Ok(0);
+        };
+        let last = chunks.layout.page_of(range.end - 1).unwrap_or(first);
+        self.start_page_count();
+        chunks.skip_to(first)?;
+        let mut written = 0;
+        for index in first..=last {
+            let (offset, _) = chunks.layout.page(index).unwrap_or_default();
+            let bytes = chunks.next().unwrap_or(Ok(Vec::new()))?;
+            // Keep the part of the page's bytes inside the range
+            let start = range.start.saturating_sub(offset).min(bytes.len() as u64) as usize;
+            let end = (range.end - offset).min(bytes.len() as u64) as usize;
+            self.check_ascii(&bytes[start..end], offset + start as u64)?;
+            writer.write_all(&bytes[start..end])?;
+            written += (end - start) as u64;
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// Rewrite the archive at `input_path` in the current format version, returning the version it had.
+    ///
+    /// Only the framing changes: the header and addresses are copied as they
+    /// are and no page is generated. The addresses of a split archive are
+    /// gathered into the one output file.
+    pub fn migrate_archive(&self, input_path: &str, output_path: &str) -> Result<u32, BabelError> {
+        let mut archive = self.resolve_embedded(Archive::open(input_path)?)?;
+        let version = archive.header.version;
+        archive.header.version = FORMAT_VERSION;
+        archive.header.parts.clear();
+        if self.format == ArchiveFormat::SelfContained {
+            self.embed_header(&mut archive)?;
+        }
+
+        let mut output = TempFile::create(output_path)?;
+        archive.write(self.format, BufWriter::new(&mut output))?;
+        output.commit()?;
+        Ok(version)
+    }
+
+    pub fn decode_file(&self, input_path: &str, output_path: Option<&str>) -> Result<(), BabelError> {
+        self.progress(format_args!("Reading babel file..."));
+        let archive = self.prepare_decode(Archive::open_with(Path::new(input_path), self.best_effort)?)?;
+        self.write_decoded(&archive, input_path, output_path)
+    }
+
+    /// Decode an archive read whole from `reader`, such as stdin, to `writer`.
+    ///
+    /// The header and addresses come from the one stream and nothing touches
+    /// the filesystem, so no metadata is restored, and a directory archive is
+    /// refused. Returns the bytes written.
+    pub fn decode_reader<R: Read, W: Write>(&self, mut reader: R, writer: W) -> Result<u64, BabelError> {
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents)?;
+        let archive = self.prepare_decode(Archive::parse_with(&contents, self.best_effort)?)?;
+        self.decode_archive(&archive, writer)
+    }
+
+    // `text` without the padding that ends an archive with no size line
+    //
+    // Periods are text in text mode, and digits in base29, so those lose any
+    // that ended the data too; base29 also drops a block left incomplete.
+    fn unpadded<'t>(&self, payload: PayloadMode, text: &'t str) -> &'t str {
+        let mut text = text.trim_end_matches(PAD_CHAR);
+        if payload == PayloadMode::Bytes {
+            let block = self.alphabet.block();
+            while block.bytes_in(text.len() as u64).is_none() {
+                text = &text[..text.len() - 1];
+            }
+        }
+        text
+    }
+
+    // Read the header of a self-contained archive and size one that lost its size line
+    fn prepare_decode(&self, archive: Archive) -> Result<Archive, BabelError> {
+        let mut archive = self.resolve_embedded(archive)?;
+        if archive.header.size_unknown {
+            // Every page holds as many bytes as it can; the trailing padding is trimmed on decode
+            let codec = self.for_header(&archive.header)?;
+            let block = match archive.header.payload() {
+                PayloadMode::Bytes => codec.alphabet.block(),
+                PayloadMode::Text => Block::TEXT,
+            };
+            let chars = archive.addresses.len() as u64 * codec.page_length().get() as u64;
+            archive.header.size = block.bytes_before(chars);
+            self.warn(format_args!(
+                "archive has no size line, so the exact length is unknown; only trailing padding will be trimmed"
+            ));
+        }
+        Ok(archive)
+    }
+
+    /// Decode a bare address list at `index_path` using a header supplied separately.
+    ///
+    /// This recovers files whose archive header was lost or kept elsewhere:
+    /// build the header with [`ArchiveHeader::new`] from the known size and
+    /// extension, or take it from another archive. Addresses may be separated
+    /// by spaces or newlines; anything in `index_path` that looks like a header
+    /// is not recognised and will be treated as an address.
+    pub fn decode_with_header(&self, header: &ArchiveHeader, index_path: &str, output_path: Option<&str>) -> Result<(), BabelError> {
+        self.progress(format_args!("Reading address list..."));
+        let archive = Archive::from_addresses(header.clone(), &fs::read_to_string(index_path)?);
+        self.write_decoded(&archive, index_path, output_path)
+    }
+
+    // Decode every page of `archive` to `output_path`, or beside `input_path` by default
+    fn write_decoded(&self, archive: &Archive, input_path: &str, output_path: Option<&str>) -> Result<(), BabelError> {
+        let header = &archive.header;
+        if let Cow::Owned(codec) = self.for_header(header)? {
+            return codec.write_decoded(archive, input_path, output_path);
+        }
+        self.check_alphabet(header)?;
+
+        // Sizes are u64 everywhere else; only the decoded buffer needs a usize
+        let original_size = usize::try_from(header.size()).map_err(|_| BabelError::SizeTooLarge(header.size()))?;
+        self.progress(format_args!("Decoding {} bytes...", original_size));
+
+        let locations = &archive.addresses;
+        self.progress(format_args!("Found {} pages to decode", locations.len()));
+        let needed = self.layout(header).page_count();
+        if locations.len() < needed {
+            return Err(BabelError::TooFewPages { pages: locations.len(), needed });
+        }
+
+        // Name the line of a text archive an unusable address came from, reading it at most once
+        let contents = OnceLock::new();
+        let bad_address = |index: usize, source: BabelError| BabelError::BadAddress {
+            index,
+            line: contents
+                .get_or_init(|| fs::read_to_string(input_path).ok())
+                .as_deref()
+                .and_then(|contents| address_line(contents, &locations[index])),
+            source: Box::new(source),
+        };
+        // Catch malformed addresses before any page is generated
+        let space = self.space()?;
+        let parsed = locations
+            .iter()
+            .enumerate()
+            .map(|(index, location)| Address::parse_in(location, &space.geometry).map_err(|e| bad_address(index, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let payload = header.payload();
+        self.start_page_count();
+        let decode = |(index, location): (usize, &Address)| {
+            let page_content = get_page(location, space).map_err(|e| bad_address(index, e))?;
+            self.pages_done(1);
+            self.check_page(header, index, &page_content)?;
+            Ok(page_content)
+        };
+
+        // Collecting into a Result stops at the first bad page
+        let decoded_chunks: Vec<String> = if locations.len() >= self.min_parallel_pages {
+            self.progress(format_args!("Decoding pages in parallel..."));
+            self.in_pool(|| parsed.par_iter().enumerate().map(decode).collect::<Result<_, BabelError>>())?
+        } else {
+            self.progress(format_args!("Decoding pages..."));
+            parsed.iter().enumerate().map(decode).collect::<Result<_, BabelError>>()?
+        };
+
+        let mut decoded_text = decoded_chunks.join("");
+        // Padding past the recorded size need not spell bytes, so it is never converted
+        decoded_text.truncate(self.layout(header).text_len() as usize);
+        if header.size_unknown {
+            decoded_text.truncate(self.unpadded(payload, &decoded_text).len());
+        }
+
+        self.progress(format_args!("Converting to bytes..."));
+        let mut bytes = match payload {
+            PayloadMode::Bytes => self.read_text(&decoded_text)?,
+            PayloadMode::Text => decoded_text.into_bytes(),
+        };
+
+        self.progress(format_args!("Original size: {}, Decoded size: {}", original_size, bytes.len()));
+        bytes.truncate(original_size);
+        bytes = self.decrypt(header, bytes)?;
+        if !header.compression.is_none() {
+            self.progress(format_args!("Decompressing {}...", header.compression.name()));
+            let mut decompressed = Vec::new();
+            header.compression.decompress(&bytes[..])?.read_to_end(&mut decompressed)?;
+            bytes = decompressed;
+        }
+        if let Some(expected) = header.crc32.filter(|_| !header.size_unknown) {
+            self.check_crc32(expected, crc32(&bytes))?;
+        }
+        self.check_ascii(&bytes, 0)?;
+
+        let output_path = match output_path {
+            Some(path) => path.to_string(),
+            None => default_output_path(Path::new(input_path), header).to_string_lossy().to_string(),
+        };
+
+        if !header.files.is_empty() {
+            self.progress(format_args!("Writing {} files under {}", header.files.len(), output_path));
+            directory::write_files(&bytes, &header.files, Path::new(&output_path))?;
+            self.progress(format_args!("Decoding complete!"));
+            return Ok(());
+        }
+
+        self.progress(format_args!("Writing to {}", output_path));
+        let mut output = TempFile::create(&output_path)?;
+        output.write_all(&bytes)?;
+        output.commit()?;
+        metadata::apply(Path::new(&output_path), header)?;
+
+        self.progress(format_args!("Decoding complete!"));
+        Ok(())
+    }
+}
+
+// Where an archive at `input_path` decodes to when no output is given
+//
+// Beside the archive, under the original file name when the header has one.
+// A recorded name is only trusted as a bare file name, so a doctored header
+// cannot send the output into another directory; anything else falls back to
+// the archive's own name with the recorded extension.
+fn default_output_path(input_path: &Path, header: &ArchiveHeader) -> PathBuf {
+    let recorded = header.name().filter(|name| {
+        Path::new(name).file_name() == Some(std::ffi::OsStr::new(name))
+    });
+    match recorded {
+        Some(name) if input_path.with_file_name(name) != input_path => input_path.with_file_name(name),
+        _ => input_path.with_extension(header.extension()),
+    }
+}
+
+// Refuse to stream a directory archive, whose manifest would be lost on the way
+fn check_streamable(header: &ArchiveHeader) -> Result<(), BabelError> {
+    if header.files.is_empty() {
+        return Ok(());
+    }
+    Err(BabelError::UnsupportedOptions("A directory archive decodes to a directory, not a stream".to_string()))
+}
+
+// Reads each inner reader to exhaustion before moving on to the next
+struct ChainedReaders {
+    readers: std::vec::IntoIter<Box<dyn Read>>,
+    current: Option<Box<dyn Read>>,
+}
+
+impl ChainedReaders {
+    fn new(readers: Vec<Box<dyn Read>>) -> Self {
+        let mut readers = readers.into_iter();
+        let current = readers.next();
+        ChainedReaders { readers, current }
+    }
+}
+
+// An encode between batches: the input still to read and the text of a page not yet full
+struct PageEncoder<'a> {
+    codec: &'a BabelCodec,
+    reader: Box<dyn Read + 'a>,
+    // Checksummed as decode will produce it: the input before compression, or the page text
+    input_crc: Rc<Cell<Crc32>>,
+    text_crc: Crc32,
+    buf: Vec<u8>,
+    pending: String,
+    // Pages located so far
+    pages: usize,
+    // Input bytes read, and bytes or characters put on pages
+    read: u64,
+    total: u64,
+    page_crcs: Vec<u32>,
+    encryption: Option<Encryption>,
+    // Addresses a checkpoint holds for the first pages, checked rather than searched
+    resumed: Vec<Address>,
+    eof: bool,
+}
+
+impl<'a> PageEncoder<'a> {
+    fn new<R: Read + 'a>(
+        codec: &'a BabelCodec,
+        reader: R,
+        compression: Compression,
+        encrypt: bool,
+    ) -> Result<Self, BabelError> {
+        if !codec.pad.is_valid() {
+            return Err(BabelError::InvalidPadding(codec.pad));
+        }
+        let input_crc = Rc::new(Cell::new(Crc32::new()));
+        let mut reader = compression.compress(CrcReader { inner: reader, crc: input_crc.clone() })?;
+        // A tag covers the whole ciphertext, so the input is sealed in one piece
+        let mut encryption = None;
+        if let Some(passphrase) = codec.passphrase.as_ref().filter(|_| encrypt) {
+            if codec.payload == PayloadMode::Text {
+                return Err(BabelError::UnsupportedOptions("Text mode archives cannot be encrypted".to_string()));
+            }
+            let mut plain = Vec::new();
+            reader.read_to_end(&mut plain)?;
+            let (sealed, ciphertext) = Encryption::seal(passphrase, &plain)?;
+            encryption = Some(sealed);
+            reader = Box::new(io::Cursor::new(ciphertext));
+        }
+        codec.start_page_count();
+        Ok(PageEncoder {
+            codec,
+            reader,
+            input_crc,
+            text_crc: Crc32::new(),
+            buf: vec![0u8; codec.batch_bytes(codec.page_length().get())],
+            pending: String::new(),
+            pages: 0,
+            read: 0,
+            total: 0,
+            page_crcs: Vec::new(),
+            encryption,
+            resumed: Vec::new(),
+            eof: false,
+        })
+    }
+
+    // Read and locate the next batch of pages, or `None` once the input is used up
+    fn next_batch(&mut self) -> Result<Option<(Vec<Address>, VerifyStats)>, BabelError> {
+        let codec = self.codec;
+        let page_length = codec.page_length().get();
+        while !self.eof {
+            // Fill the buffer completely unless the stream ends first
+            let mut filled = 0;
+            while filled < self.buf.len() {
+                match self.reader.read(&mut self.buf[filled..]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            self.eof = filled < self.buf.len();
+            let bytes = &self.buf[..filled];
+
+            // Text mode may drop characters, so the size counts what reaches the pages
+            let babel_text = match codec.payload {
+                PayloadMode::Bytes => {
+                    let babel_text = codec.bytes_to_babel_text(bytes);
+                    if codec.read_text(&babel_text)? != bytes {
+                        return Err(BabelError::SpellingMismatch { offset: self.read });
+                    }
+                    self.total += filled as u64;
+                    babel_text
+                }
+                PayloadMode::Text => {
+                    let text = text_mode::to_page_text(bytes, self.read, codec.on_invalid_char)?;
+                    self.text_crc.update(text.as_bytes());
+                    self.total += text.len() as u64;
+                    text
+                }
+            };
+            self.read += filled as u64;
+            self.pending.push_str(&babel_text);
+
+            let full = self.pending.len() / page_length * page_length;
+            let mut chunks: Vec<String> = self.pending.as_bytes()[..full]
+                .chunks(page_length)
+                .map(|c| String::from_utf8_lossy(c).into_owned())
+                .collect();
+            self.pending.drain(..full);
+            let padded = self.eof && !self.pending.is_empty();
+            if padded {
+                chunks.push(codec.pad_page(&self.pending, page_length, self.pages + chunks.len()));
+                self.pending.clear();
+            }
+
+            // A self-contained archive has no other check on its content
+            if codec.page_checksums || codec.format == ArchiveFormat::SelfContained {
+                self.page_crcs.extend(chunks.iter().map(|chunk| crc32(chunk.as_bytes())));
+            }
+
+            if !chunks.is_empty() {
+                let (mut batch, mut stats) = self.resume_pages(&chunks, padded)?;
+                if batch.len() < chunks.len() {
+                    codec.progress(format_args!("Finding locations for {} pages...", chunks.len() - batch.len()));
+                    let (located, located_stats) = codec.locate_addresses(&chunks[batch.len()..], self.pages + batch.len())?;
+                    batch.extend(located);
+                    stats.merge(located_stats);
+                }
+                self.pages += batch.len();
+                return Ok(Some((batch, stats)));
+            }
+        }
+        Ok(None)
+    }
+
+    // The checkpointed addresses of the leading pages of `chunks`, each
+    // checked to still hold its page
+    //
+    // A padded last page may differ from the checkpoint only in its random
+    // padding, so that one is searched again rather than failing.
+    fn resume_pages(&self, chunks: &[String], padded: bool) -> Result<(Vec<Address>, VerifyStats), BabelError> {
+        let codec = self.codec;
+        let space = codec.space()?;
+        let resumed = self.resumed.get(self.pages..).unwrap_or_default();
+        let mut batch = Vec::new();
+        for (chunk, stored) in chunks.iter().zip(resumed) {
+            let index = self.pages + batch.len();
+            if search_exact(chunk, space, &stored.location)? != *stored {
+                if padded && batch.len() + 1 == chunks.len() {
+                    break;
+                }
+                return Err(BabelError::CheckpointMismatch { index });
+            }
+            batch.push(stored.clone());
+        }
+        if !batch.is_empty() {
+            codec.pages_done(batch.len() as u64);
+        }
+        // The key was worked out from the page text, which is all a read back checks
+        let stats = match codec.verify {
+            VerifyMode::Full => VerifyStats { total: batch.len(), passed: batch.len(), failed_indices: Vec::new() },
+            VerifyMode::None | VerifyMode::FullBytes => VerifyStats::default(),
+        };
+        Ok((batch, stats))
+    }
+}
+
+/// Addresses of an input's pages, searched a batch at a time; see [`BabelCodec::encode_iter`].
+///
+/// After an error the iterator ends.
+pub struct EncodedAddresses<'a> {
+    encoder: PageEncoder<'a>,
+    batch: std::vec::IntoIter<Address>,
+    done: bool,
+}
+
+impl EncodedAddresses<'_> {
+    /// Bytes put on pages so far: the input's length once the iterator is exhausted,
+    /// or in text mode the characters kept.
+    pub fn size(&self) -> u64 {
+        self.encoder.total
+    }
+}
+
+impl Iterator for EncodedAddresses<'_> {
+    type Item = Result<Address, BabelError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(address) = self.batch.next() {
+                return Some(Ok(address));
+            }
+            if self.done {
+                return None;
+            }
+            match self.encoder.next_batch() {
+                Ok(Some((_, stats))) if !stats.all_passed() => {
+                    self.done = true;
+                    return Some(Err(BabelError::VerificationFailed(stats)));
+                }
+                Ok(Some((batch, _))) => self.batch = batch.into_iter(),
+                Ok(None) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+// What encode_pages produces for the header and index
+pub(crate) struct EncodedPages {
+    addresses: Vec<Address>,
+    // Real pages encoded, whether or not `addresses` holds them
+    pages: usize,
+    filler: Vec<String>,
+    size: u64,
+    stats: VerifyStats,
+    page_crcs: Vec<u32>,
+    checksum: Option<u32>,
+    encryption: Option<Encryption>,
+}
+
+impl Read for ChainedReaders {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while let Some(reader) = self.current.as_mut() {
+            match reader.read(buf)? {
+                0 if !buf.is_empty() => self.current = self.readers.next(),
+                n => return Ok(n),
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Sequential page-by-page decode of an archive.
+///
+/// A byte's two characters, or a base29 block's five, can straddle a page
+/// boundary, so the characters of an unfinished one are carried over to the
+/// next page.
+pub(crate) struct DecodedChunks<'a> {
+    codec: Cow<'a, BabelCodec>,
+    header: Cow<'a, ArchiveHeader>,
+    addresses: std::slice::Iter<'a, String>,
+    pub(crate) payload: PayloadMode,
+    layout: PageLayout,
+    index: usize,
+    carry: String,
+}
+
+impl DecodedChunks<'_> {
+    // The header being decoded, read from the leading pages of a self-contained archive
+    pub(crate) fn header(&self) -> &ArchiveHeader {
+        &self.header
+    }
+
+    // Bytes the remaining pages can hold, at most the recorded size
+    //
+    // A bound for preallocating output: the size alone comes from the header,
+    // which a corrupt or doctored archive can set to anything.
+    pub(crate) fn capacity(&self) -> usize {
+        let pages = (self.index + self.addresses.len()).min(self.layout.page_count());
+        let end = pages.checked_sub(1).and_then(|last| self.layout.page(last)).map_or(0, |(offset, len)| offset + len);
+        let start = self.layout.page(self.index).map_or(end, |(offset, _)| offset);
+        usize::try_from(end.saturating_sub(start)).unwrap_or(usize::MAX)
+    }
+
+    // Go straight to page `page`, generating only the pages before it that
+    // hold the start of its first byte or block
+    fn skip_to(&mut self, page: usize) -> Result<(), BabelError> {
+        if page <= self.index {
+            return Ok(());
+        }
+        let page_length = self.codec.page_length().get();
+        // Past the last page there is nothing left to carry
+        let start = self.layout.page(page).map_or(page * page_length, |(offset, _)| self.layout.chars_before(offset) as usize);
+        let first = (start / page_length).max(self.index);
+        if first > self.index {
+            self.addresses.nth(first - self.index - 1);
+        }
+        self.carry.clear();
+        for _ in first..page {
+            let Some(address) = self.addresses.next() else { break };
+            self.carry.push_str(&self.codec.get_page_shared(address)?);
+        }
+        self.carry.drain(..start.saturating_sub(first * page_length).min(self.carry.len()));
+        self.index = page;
+        Ok(())
+    }
+}
+
+impl Iterator for DecodedChunks<'_> {
+    type Item = Result<Vec<u8>, BabelError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (offset, len) = self.layout.page(self.index)?;
+        let Some(address) = self.addresses.next() else {
+            // Stop after reporting it once
+            let needed = self.layout.page_count();
+            let pages = std::mem::replace(&mut self.index, needed);
+            return Some(Err(BabelError::TooFewPages { pages, needed }));
+        };
+        self.index += 1;
+
+        let page_content = match self.codec.get_page_shared(address) {
+            Ok(page) => page,
+            Err(e) => return Some(Err(e)),
+        };
+        self.codec.pages_done(1);
+        if let Err(e) = self.codec.check_page(&self.header, self.index - 1, &page_content) {
+            return Some(Err(e));
+        }
+        let mut bytes = match self.payload {
+            PayloadMode::Text => page_content.as_bytes().to_vec(),
+            PayloadMode::Bytes => {
+                self.carry.push_str(&page_content);
+                // Stop at the page's share of the size, leaving any padding unconverted
+                let chars = (self.layout.chars_before(offset + len) - self.layout.chars_before(offset)) as usize;
+                let mut text = &self.carry[..chars.min(self.carry.len())];
+                if self.header.size_unknown && self.index == self.layout.page_count() {
+                    text = self.codec.unpadded(self.payload, text);
+                }
+                let bytes = self.codec.read_text(text);
+                self.carry.drain(..chars.min(self.carry.len()));
+                match bytes {
+                    Ok(bytes) => bytes,
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+        };
+
+        bytes.truncate(len as usize);
+        Some(Ok(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::{search_exact, string_to_number, CHARSET, GET_PAGE_CALLS, LENGTH_OF_PAGE};
+    use crate::location::Location;
+    use num_bigint::BigInt;
+    use rand::Rng;
+
+    // Output of the byte spelling from before alphabets were configurable:
+    // each byte as `b / 26` then `b % 26`, both written as `a`..`z`
+    const GOLDEN_BYTES: &[u8] = b"Babel\x00\x01\x19\x1a\x7f\xc8\xff";
+    const GOLDEN_TEXT: &str = "codtdudxeeaaabazbaexhsjv";
+
+    #[test]
+    fn default_codec_spells_bytes_as_letters26() {
+        let codec = BabelCodec::default();
+        assert_eq!(codec.alphabet(), &Alphabet::letters26());
+        assert_eq!(codec.bytes_to_babel_text(GOLDEN_BYTES), GOLDEN_TEXT);
+        assert_eq!(codec.babel_text_to_bytes(GOLDEN_TEXT).unwrap(), GOLDEN_BYTES);
+    }
+
+    fn random_bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; len];
+        StdRng::seed_from_u64(seed).fill(&mut bytes[..]);
+        bytes
+    }
+
+    #[test]
+    fn every_byte_round_trips_alone() {
+        let codec = BabelCodec::default();
+        for b in 0..=u8::MAX {
+            let text = codec.bytes_to_babel_text(&[b]);
+            assert_eq!(text.len(), 2, "byte {:#04x}", b);
+            assert_eq!(codec.babel_text_to_bytes(&text).unwrap(), [b], "byte {:#04x}", b);
+        }
+    }
+
+    #[test]
+    fn every_byte_spells_differently() {
+        let codec = BabelCodec::default();
+        let spellings: std::collections::HashSet<String> =
+            (0..=u8::MAX).map(|b| codec.bytes_to_babel_text(&[b])).collect();
+        assert_eq!(spellings.len(), 256);
+    }
+
+    #[test]
+    fn random_sequences_round_trip() {
+        let codec = BabelCodec::default();
+        for seed in 0..32 {
+            let bytes = random_bytes(seed, seed as usize * 37);
+            let text = codec.bytes_to_babel_text(&bytes);
+            assert_eq!(codec.babel_text_to_bytes(&text).unwrap(), bytes, "seed {}", seed);
+        }
+    }
+
+    // Bytes through every stage at once: chunking, padding, search, page
+    // generation and reassembly, checked against both decode paths
+    fn assert_round_trips(bytes: &[u8], seed: u64) {
+        let codec = BabelCodec::default().with_seed(Some(seed));
+        let addresses = codec.encode_bytes(bytes).unwrap();
+        assert_eq!(addresses.len(), (bytes.len() * 2).div_ceil(LENGTH_OF_PAGE), "{} bytes", bytes.len());
+        assert_eq!(codec.decode_bytes(&addresses, bytes.len()).unwrap(), bytes, "{} bytes", bytes.len());
+        let mut decoded = Vec::new();
+        codec.decode_archive(&codec.bare_archive(addresses.clone(), bytes.len() as u64), &mut decoded).unwrap();
+        assert_eq!(decoded, bytes, "{} bytes", bytes.len());
+        let parsed: Vec<Address> = addresses.iter().map(|address| address.parse().unwrap()).collect();
+        assert_eq!(crate::babel::decode_addresses(&parsed, bytes.len()).unwrap(), bytes, "{} bytes", bytes.len());
+    }
+
+    // Lengths either side of page boundaries, where a byte straddles two
+    // pages of the odd default length
+    #[test]
+    fn bytes_at_page_boundaries_round_trip_through_addresses() {
+        let half_page = LENGTH_OF_PAGE / 2;
+        let lengths = [0, 1, 2, half_page - 1, half_page, half_page + 1, LENGTH_OF_PAGE, 2 * LENGTH_OF_PAGE + 1];
+        for (seed, len) in lengths.into_iter().enumerate() {
+            assert_round_trips(&random_bytes(seed as u64, len), seed as u64);
+        }
+    }
+
+    // A fixed seed keeps the generated cases, and so any failure, reproducible
+    proptest::proptest! {
+        #![proptest_config(proptest::test_runner::Config {
+            cases: 24,
+            rng_seed: proptest::test_runner::RngSeed::Fixed(298),
+            failure_persistence: None,
+            ..Default::default()
+        })]
+
+        #[test]
+        fn random_bytes_round_trip_through_addresses(bytes in proptest::collection::vec(proptest::num::u8::ANY, 0..4096), seed: u64) {
+            assert_round_trips(&bytes, seed);
+        }
+    }
+
+    // Conversion switches to rayon above PARALLEL_CONVERSION_BYTES; either side
+    // of it, and of twice it, the result must match byte-at-a-time conversion,
+    // which always takes the sequential path
+    #[test]
+    fn parallel_and_sequential_conversions_agree() {
+        let codec = BabelCodec::default();
+        let threshold = PARALLEL_CONVERSION_BYTES;
+        for len in [threshold - 1, threshold, threshold + 1, 2 * threshold - 1, 2 * threshold, 2 * threshold + 1] {
+            let bytes = random_bytes(len as u64, len);
+            let text = codec.bytes_to_babel_text(&bytes);
+            let sequential: String = bytes.iter().map(|&b| codec.bytes_to_babel_text(&[b])).collect();
+            assert_eq!(text, sequential, "{} bytes", len);
+            assert_eq!(codec.babel_text_to_bytes(&text).unwrap(), bytes, "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn both_strategies_give_the_same_result() {
+        let codec = BabelCodec::default();
+        for len in [0, 1, 2, 255, 4096] {
+            let bytes = random_bytes(len as u64, len);
+            let text = codec.spell_bytes(&bytes, false);
+            assert_eq!(codec.spell_bytes(&bytes, true), text, "{} bytes", len);
+            let chars: Vec<char> = text.chars().collect();
+            assert_eq!(codec.read_blocks(&chars, false).unwrap(), bytes, "{} bytes", len);
+            assert_eq!(codec.read_blocks(&chars, true).unwrap(), bytes, "{} bytes", len);
+        }
+        // A pair past 255 is the same error either way
+        let bad: Vec<char> = "aazz".chars().collect();
+        assert_eq!(
+            codec.read_blocks(&bad, false).unwrap_err().to_string(),
+            codec.read_blocks(&bad, true).unwrap_err().to_string()
+        );
+    }
+
+    // `zz` is 25 * 26 + 25 = 675, which a byte cannot hold
+    #[test]
+    fn pairs_past_255_are_rejected_not_wrapped() {
+        let codec = BabelCodec::default();
+        assert_eq!(codec.babel_text_to_bytes("jv").unwrap(), [255]);
+        assert!(matches!(codec.babel_text_to_bytes("zz"), Err(BabelError::InvalidBytePair { offset: 0, ref pair }) if pair == "zz"));
+        assert!(matches!(codec.babel_text_to_bytes("aajw"), Err(BabelError::InvalidBytePair { offset: 2, ref pair }) if pair == "jw"));
+    }
+
+    // Padding may follow the last pair, but a lone symbol before it is half a lost byte
+    #[test]
+    fn odd_symbol_counts_are_an_error() {
+        let codec = BabelCodec::default();
+        assert_eq!(codec.babel_text_to_bytes("codt...").unwrap(), b"Ba");
+        for (text, lone) in [("cod", 2), ("cod...", 2), ("c", 0)] {
+            match codec.babel_text_to_bytes(text) {
+                Err(BabelError::UnpairedSymbol { offset }) => assert_eq!(offset, lone, "{}", text),
+                other => panic!("{} gave {:?}", text, other),
+            }
+        }
+    }
+
+    // The split conversions must agree with base 29 and 36 worked one digit at a time:
+    // page text against the symbols' positions, keys against `from_str_radix`
+    #[test]
+    fn full_pages_and_their_keys_convert_exactly() {
+        let space = PageSpace::new(LENGTH_OF_PAGE, LibraryGeometry::default()).unwrap();
+        let charset: Vec<char> = CHARSET.chars().collect();
+        let mut rng = StdRng::seed_from_u64(29);
+        for _ in 0..8 {
+            let page: String = (0..LENGTH_OF_PAGE).map(|_| charset[rng.gen_range(0..charset.len())]).collect();
+            let number = page.chars().fold(BigInt::from(0), |n, c| n * 29 + charset.iter().position(|&x| x == c).unwrap());
+            assert_eq!(string_to_number(&page).unwrap(), number);
+
+            let location = Location::random(&mut rng, &LibraryGeometry::default(), LocationBias::Uniform);
+            let address = search_exact(&page, &space, &location).unwrap();
+            assert_eq!(get_page(&address, &space).unwrap(), page);
+            let text = address.to_string();
+            let key = text.split(':').next().unwrap();
+            assert_eq!(BigInt::parse_bytes(key.as_bytes(), 36).unwrap(), address.key);
+            assert!(!key.starts_with('0'));
+        }
+    }
+
+    // Every short last block, and blocks straddling pages of lengths that do
+    // not divide five, including pages shorter than a block
+    #[test]
+    fn base29_round_trips_through_pages() {
+        let codec = BabelCodec::default().with_alphabet(Alphabet::base29());
+        for len in 0..=7 {
+            let bytes = random_bytes(len as u64, len);
+            let text = codec.bytes_to_babel_text(&bytes);
+            assert_eq!(text.len(), len / 3 * 5 + [0, 2, 4][len % 3], "{} bytes", len);
+            assert_eq!(codec.babel_text_to_bytes(&text).unwrap(), bytes, "{} bytes", len);
+        }
+        // Periods are digits, so a trailing one is kept
+        assert_eq!(codec.babel_text_to_bytes(&codec.bytes_to_babel_text(&[0, 0, 28])).unwrap(), [0, 0, 28]);
+        assert!(codec.bytes_to_babel_text(&[0, 0, 28]).ends_with(PAD_CHAR));
+
+        for (seed, length) in [3, 4, 7, 12, LENGTH_OF_PAGE].into_iter().enumerate() {
+            let codec = codec.clone().with_page_length(PageLength::new(length).unwrap()).with_seed(Some(seed as u64));
+            for len in [1, 2, 3, 4, 5, 100, 1001] {
+                let bytes = random_bytes(len as u64, len);
+                let addresses = codec.encode_bytes(&bytes).unwrap();
+                assert_eq!(codec.decode_bytes(&addresses, len).unwrap(), bytes, "{} bytes on pages of {}", len, length);
+                let archive = codec.bare_archive(addresses, len as u64);
+                for range in [0..len as u64, 1..len as u64, len as u64 / 2..len as u64] {
+                    let mut part = Vec::new();
+                    codec.decode_range(&archive, range.clone(), &mut part).unwrap();
+                    assert_eq!(part, &bytes[range.start as usize..], "{:?} of {} bytes on pages of {}", range, len, length);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn base29_is_recorded_and_needs_fewer_pages() {
+        let bytes = random_bytes(285, 30000);
+        let mut archive = Vec::new();
+        let codec = BabelCodec::default().with_alphabet(Alphabet::base29()).with_progress(false);
+        codec.encode_reader(&bytes[..], "bin", &mut archive).unwrap();
+        let header = Archive::read_header(&archive[..]).unwrap();
+        assert_eq!(header.alphabet(), "base29");
+
+        // 50000 characters where pairs take 60000
+        let pages = Archive::parse_with(&archive, false).unwrap().addresses.len();
+        assert_eq!(pages, 50000usize.div_ceil(LENGTH_OF_PAGE));
+        assert_eq!(pages, codec.estimate(30000).unwrap().pages);
+        assert!(pages < BabelCodec::default().estimate(30000).unwrap().pages);
+
+        // A default codec follows the header
+        let mut decoded = Vec::new();
+        BabelCodec::default().with_progress(false).decode_reader(&archive[..], &mut decoded).unwrap();
+        assert_eq!(decoded, bytes);
+    }
+
+    fn archive_with(codec: &BabelCodec, bytes: &[u8]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        codec.clone().with_progress(false).encode_reader(bytes, "bin", &mut archive).unwrap();
+        archive
+    }
+
+    // A custom alphabet is only known by its hash, so neither side can adopt the other's
+    #[test]
+    fn decoding_with_another_alphabet_is_a_mismatch() {
+        let custom = BabelCodec::default().with_alphabet(Alphabet::custom("zyxwvutsrqponmlkjihgfedcba").unwrap());
+        let archive = archive_with(&custom, b"spelled backwards");
+        match BabelCodec::default().decode_reader(&archive[..], io::sink()) {
+            Err(BabelError::AlphabetMismatch { expected, found }) => {
+                assert_eq!(expected, "letters26");
+                assert_eq!(found, custom.alphabet().id());
+            }
+            other => panic!("decoded with letters26: {:?}", other),
+        }
+
+        let archive = archive_with(&BabelCodec::default(), b"spelled forwards");
+        assert!(matches!(custom.decode_reader(&archive[..], io::sink()), Err(BabelError::AlphabetMismatch { .. })));
+        let mut decoded = Vec::new();
+        custom.decode_reader(&archive_with(&custom, b"round trip")[..], &mut decoded).unwrap();
+        assert_eq!(decoded, b"round trip");
+    }
+
+    #[test]
+    fn four_pages_per_line_round_trip() {
+        let codec = BabelCodec::default().with_page_length(PageLength::new(100).unwrap()).with_pages_per_line(4);
+        let bytes = random_bytes(205, 430);
+        let archive = String::from_utf8(archive_with(&codec, &bytes)).unwrap();
+        let lines: Vec<usize> = archive
+            .lines()
+            .filter(|line| line.contains(':') && !line.contains('='))
+            .map(|line| line.split(crate::archive::ADDRESS_DELIMITER).count())
+            .collect();
+        assert_eq!(lines, [4, 4, 1]);
+
+        let mut decoded = Vec::new();
+        codec.decode_reader(archive.as_bytes(), &mut decoded).unwrap();
+        assert_eq!(decoded, bytes);
+    }
+
+    // The threads `on_page` was called from while `bytes` were encoded
+    fn page_threads(codec: BabelCodec, bytes: &[u8]) -> Vec<std::thread::ThreadId> {
+        let threads = Arc::new(Mutex::new(Vec::new()));
+        let record = Arc::clone(&threads);
+        let codec = codec.with_on_page(move |_| record.lock().unwrap().push(std::thread::current().id()));
+        archive_with(&codec, bytes);
+        let threads = threads.lock().unwrap().clone();
+        threads
+    }
+
+    #[test]
+    fn small_encodes_stay_on_the_calling_thread() {
+        let codec = BabelCodec::default().with_min_parallel_pages(2);
+        let threads = page_threads(codec, b"one page");
+        assert_eq!(threads, [std::thread::current().id()]);
+    }
+
+    #[test]
+    fn encodes_of_min_parallel_pages_fan_out() {
+        let codec = BabelCodec::default().with_page_length(PageLength::new(20).unwrap()).with_min_parallel_pages(2);
+        let threads = page_threads(codec, &random_bytes(209, 400));
+        assert_eq!(threads.len(), 40);
+        assert!(threads.iter().all(|&thread| thread != std::thread::current().id()));
+    }
+
+    #[test]
+    fn notes_with_newlines_and_unicode_round_trip() {
+        let note = "first line\nsecond line\r\n\tcafé — 図書館 📚\\n=value";
+        for format in [ArchiveFormat::Text, ArchiveFormat::Json, ArchiveFormat::CompressedIndex] {
+            let codec = BabelCodec::default().with_note(note).with_format(format);
+            let archive = archive_with(&codec, b"noted");
+            let header = Archive::read_header(&archive[..]).unwrap();
+            assert_eq!(header.note(), Some(note), "{:?}", format);
+            let mut decoded = Vec::new();
+            codec.decode_reader(&archive[..], &mut decoded).unwrap();
+            assert_eq!(decoded, b"noted");
+        }
+    }
+
+    // The page boundaries fall inside the readers, not between them
+    #[test]
+    fn chained_readers_decode_as_their_concatenation() {
+        let codec = BabelCodec::default().with_progress(false).with_page_length(PageLength::new(20).unwrap());
+        let parts = [random_bytes(1, 7), random_bytes(2, 23), random_bytes(3, 15)];
+        let readers: Vec<Box<dyn Read>> = parts.iter().map(|part| Box::new(io::Cursor::new(part.clone())) as Box<dyn Read>).collect();
+        let mut archive = Vec::new();
+        assert_eq!(codec.encode_readers(readers, "bin", &mut archive).unwrap(), 45);
+
+        let mut decoded = Vec::new();
+        codec.decode_reader(&archive[..], &mut decoded).unwrap();
+        assert_eq!(decoded, parts.concat());
+    }
+
+    #[test]
+    fn padded_archives_hold_exactly_the_pages_asked_for() {
+        let codec = BabelCodec::default().with_page_length(PageLength::new(20).unwrap()).with_pad_to_pages(Some(10));
+        let bytes = b"two pages of input";
+        let archive = Archive::read(&archive_with(&codec, bytes)[..]).unwrap();
+        assert_eq!((archive.addresses.len(), archive.filler.len()), (2, 8));
+        assert_eq!(archive.header().pages(), Some(2));
+        let mut decoded = Vec::new();
+        codec.decode_archive(&archive, &mut decoded).unwrap();
+        assert_eq!(decoded, bytes);
+
+        let mut archive = Vec::new();
+        match codec.with_progress(false).encode_reader(&random_bytes(241, 101)[..], "bin", &mut archive) {
+            Err(BabelError::TooManyPages { pages, limit }) => assert_eq!((pages, limit), (11, 10)),
+            other => panic!("padded 11 pages to 10: {:?}", other),
+        }
+    }
+
+    // Page 1 readdressed to text spelling a bell where the `f` of `of` was
+    #[test]
+    fn strict_ascii_catches_a_byte_flipped_into_the_control_range() {
+        let codec = BabelCodec::default().with_progress(false).with_page_length(PageLength::new(20).unwrap());
+        let bytes = b"plain text of a kind strict ASCII allows";
+        let mut archive = String::from_utf8(archive_with(&codec, bytes)).unwrap();
+        let original: Address = Archive::parse(archive.as_bytes()).unwrap().addresses[1].parse().unwrap();
+
+        let mut flipped = bytes.to_vec();
+        flipped[12] = 0x07;
+        let page = codec.bytes_to_babel_text(&flipped[10..20]);
+        let corrupt = search_exact(&page, codec.space().unwrap(), &original.location).unwrap();
+        archive = archive.replacen(&original.to_string(), &corrupt.to_string(), 1);
+        assert_ne!(corrupt, original);
+
+        let mut decoded = Vec::new();
+        codec.decode_reader(archive.as_bytes(), &mut decoded).unwrap();
+        assert_eq!(decoded, flipped);
+        match codec.with_strict_ascii(true).decode_reader(archive.as_bytes(), io::sink()) {
+            Err(BabelError::NonPrintableByte { offset, byte }) => assert_eq!((offset, byte), (12, 0x07)),
+            other => panic!("decoded a bell in strict ASCII: {:?}", other),
+        }
+    }
+
+    // Each swapped page still reads back from its own address
+    #[test]
+    fn full_bytes_verification_catches_pages_out_of_order() {
+        let codec = BabelCodec::default().with_progress(false).with_page_length(PageLength::new(20).unwrap());
+        let bytes = random_bytes(250, 40);
+        let text = codec.bytes_to_babel_text(&bytes);
+        let pages: Vec<String> = text.as_bytes().chunks(20).map(|page| String::from_utf8(page.to_vec()).unwrap()).collect();
+        let archive_of = |pages: &[String]| {
+            // Every page still reads back as its own text, so page checks pass
+            let (addresses, stats) = codec.locate_pages(pages, 0).unwrap();
+            assert!(stats.all_passed());
+            codec.bare_archive(addresses, bytes.len() as u64)
+        };
+
+        // A chunking bug that puts the first two pages out of order
+        let mut swapped = pages.clone();
+        swapped.swap(0, 1);
+        match codec.check_bytes(&archive_of(&swapped), &bytes[..]) {
+            Err(BabelError::VerificationFailed(stats)) => assert_eq!(stats.failed_indices, [0, 1]),
+            other => panic!("missed pages out of order: {:?}", other),
+        }
+        assert!(codec.check_bytes(&archive_of(&pages), &bytes[..]).unwrap().all_passed());
+    }
+
+    // Locations, random padding and filler pages all come from the seed
+    #[test]
+    fn seeded_encodes_are_byte_identical() {
+        let codec = BabelCodec::default()
+            .with_page_length(PageLength::new(20).unwrap())
+            .with_pad_strategy(PadStrategy::RandomAlphabet)
+            .with_pad_to_pages(Some(6));
+        let bytes = random_bytes(258, 35);
+        let seeded = |seed| archive_with(&codec.clone().with_seed(Some(seed)), &bytes);
+        assert_eq!(seeded(7), seeded(7));
+
+        let (first, second) = (Archive::read(&seeded(7)[..]).unwrap(), Archive::read(&seeded(8)[..]).unwrap());
+        for (a, b) in first.addresses.iter().chain(&first.filler).zip(second.addresses.iter().chain(&second.filler)) {
+            assert_ne!(a, b);
+        }
+        let mut decoded = Vec::new();
+        codec.decode_archive(&second, &mut decoded).unwrap();
+        assert_eq!(decoded, bytes);
+    }
+
+    #[test]
+    fn empty_and_one_byte_inputs_round_trip() {
+        let codec = BabelCodec::default().with_progress(false);
+        for bytes in [&b""[..], b"x"] {
+            let mut written = Vec::new();
+            assert_eq!(codec.encode_reader(bytes, "bin", &mut written).unwrap(), bytes.len() as u64);
+            let archive = Archive::read(&written[..]).unwrap();
+            assert_eq!(archive.header().size(), bytes.len() as u64);
+            assert_eq!(archive.addresses().len(), bytes.len());
+            let mut decoded = Vec::new();
+            assert_eq!(codec.decode_archive(&archive, &mut decoded).unwrap(), bytes.len() as u64);
+            assert_eq!(decoded, bytes);
+        }
+    }
+
+    // Pages resolved while `codec` encodes, all of them on this thread
+    fn pages_resolved(codec: BabelCodec, bytes: &[u8]) -> usize {
+        let codec = codec.with_min_parallel_pages(usize::MAX).with_page_length(PageLength::new(20).unwrap());
+        let before = GET_PAGE_CALLS.with(|calls| calls.get());
+        archive_with(&codec, bytes);
+        GET_PAGE_CALLS.with(|calls| calls.get()) - before
+    }
+
+    #[test]
+    fn unverified_encodes_never_resolve_a_page() {
+        let bytes = random_bytes(214, 95);
+        assert_eq!(pages_resolved(BabelCodec::default().with_verify_mode(VerifyMode::None), &bytes), 0);
+        assert_eq!(pages_resolved(BabelCodec::default().with_verify_mode(VerifyMode::Full), &bytes), 10);
+    }
+
+    // Page 2 swapped with page 3 fails its checksum before page 3 is generated
+    #[test]
+    fn strict_page_checksums_abort_at_the_first_bad_page() {
+        let codec = BabelCodec::default()
+            .with_page_length(PageLength::new(20).unwrap())
+            .with_page_checksums(true)
+            .with_strict_checksums(true)
+            .with_min_parallel_pages(usize::MAX);
+        let archive = String::from_utf8(archive_with(&codec, &random_bytes(223, 200))).unwrap();
+        let mut lines: Vec<&str> = archive.lines().collect();
+        let first = lines.iter().position(|line| line.contains(':') && !line.contains('=')).unwrap();
+        lines.swap(first + 2, first + 3);
+        let corrupt = lines.join("\n");
+
+        let before = GET_PAGE_CALLS.with(|calls| calls.get());
+        match codec.decode_reader(corrupt.as_bytes(), io::sink()) {
+            Err(BabelError::PageChecksumMismatch { index }) => assert_eq!(index, 2),
+            other => panic!("decoded a swapped page: {:?}", other),
+        }
+        assert_eq!(GET_PAGE_CALLS.with(|calls| calls.get()) - before, 3);
+    }
+
+    // Records the largest buffer it was asked to fill
+    struct WidestRead<R> {
+        inner: R,
+        widest: Arc<AtomicUsize>,
+    }
+
+    impl<R: Read> Read for WidestRead<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.widest.fetch_max(buf.len(), Ordering::Relaxed);
+            self.inner.read(buf)
+        }
+    }
+
+    // Room for two pages of the default length, where the unbounded batch is 64
+    #[test]
+    fn tight_memory_budgets_read_a_couple_of_pages_at_a_time() {
+        let budget = 2 * LENGTH_OF_PAGE * BYTES_PER_PAGE_CHAR;
+        let codec = BabelCodec::default().with_progress(false).with_memory_budget(Some(budget));
+        let bytes = random_bytes(225, 64 * 1024);
+        let widest = Arc::new(AtomicUsize::new(0));
+        let reader = WidestRead { inner: &bytes[..], widest: Arc::clone(&widest) };
+        let mut archive = Vec::new();
+        codec.encode_reader(reader, "bin", &mut archive).unwrap();
+        assert_eq!(widest.load(Ordering::Relaxed), LENGTH_OF_PAGE);
+
+        let mut decoded = Vec::new();
+        codec.decode_reader(&archive[..], &mut decoded).unwrap();
+        assert_eq!(decoded, bytes);
+    }
+
+    #[test]
+    fn memory_budgets_bound_the_threads_and_refuse_held_addresses() {
+        let budget = 2 * LENGTH_OF_PAGE * BYTES_PER_PAGE_CHAR;
+        let codec = BabelCodec::default().with_progress(false).with_threads(Threads::Fixed(8)).with_memory_budget(Some(budget));
+        assert_eq!(codec.threads(), Threads::Fixed(2));
+        assert_eq!(codec.clone().with_memory_budget(None).threads(), Threads::Fixed(8));
+
+        let json = codec.with_format(ArchiveFormat::Json);
+        assert!(matches!(json.encode_reader(&b"held"[..], "bin", io::sink()), Err(BabelError::UnsupportedOptions(_))));
+    }
+
+    #[test]
+    fn bare_address_lists_decode_with_a_hand_built_header() {
+        let codec = BabelCodec::default().with_progress(false).with_page_length(PageLength::new(20).unwrap());
+        let bytes = b"the header was kept elsewhere";
+        let addresses = codec.encode_bytes(bytes).unwrap();
+        assert_eq!(addresses.len(), 3);
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let index = dir.path().join("addresses.txt");
+        let output = dir.path().join("restored.txt");
+        fs::write(&index, format!("{} {}\n{}\n", addresses[0], addresses[1], addresses[2])).unwrap();
+        let header = ArchiveHeader::new("txt", bytes.len() as u64, &Alphabet::default());
+        codec.decode_with_header(&header, index.to_str().unwrap(), output.to_str()).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), bytes);
+    }
+
+    #[test]
+    fn bad_addresses_are_reported_with_their_line() {
+        let codec = BabelCodec::default().with_progress(false).with_page_length(PageLength::new(20).unwrap());
+        let bytes = b"the third page is lost";
+        let addresses = codec.encode_bytes(bytes).unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let index = dir.path().join("addresses.txt");
+        fs::write(&index, format!("{} {}\nABC:9:1:01:001\n", addresses[0], addresses[1])).unwrap();
+        let header = ArchiveHeader::new("txt", bytes.len() as u64, &Alphabet::default());
+        match codec.decode_with_header(&header, index.to_str().unwrap(), dir.path().join("out.txt").to_str()) {
+            Err(BabelError::BadAddress { index: 2, line: Some(2), .. }) => {}
+            other => panic!("expected the third address to be reported on line 2, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn best_effort_decodes_an_archive_missing_its_size() {
+        let codec = BabelCodec::default().with_progress(false).with_page_length(PageLength::new(20).unwrap());
+        let bytes = b"the size line was lost";
+        let archive = String::from_utf8(archive_with(&codec, bytes)).unwrap();
+        let size_line = format!("\n{}\n", bytes.len());
+        assert!(archive.contains(&size_line));
+        let archive = archive.replacen(&size_line, "\n", 1);
+
+        assert!(codec.decode_reader(archive.as_bytes(), io::sink()).is_err());
+        let mut decoded = Vec::new();
+        codec.clone().with_best_effort(true).decode_reader(archive.as_bytes(), &mut decoded).unwrap();
+        assert_eq!(decoded, bytes);
+    }
+
+    // Version 1 had no magic line or header fields: extension, size, addresses
+    #[test]
+    fn version_1_archives_decode_and_migrate() {
+        let codec = BabelCodec::default().with_progress(false);
+        let bytes = b"written before the format had a version";
+        let addresses = codec.encode_bytes(bytes).unwrap();
+        let v1 = format!("txt\n{}\n{}\n", bytes.len(), addresses.join("\n"));
+        let mut decoded = Vec::new();
+        codec.decode_reader(v1.as_bytes(), &mut decoded).unwrap();
+        assert_eq!(decoded, bytes);
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let (old, new) = (dir.path().join("old.babel"), dir.path().join("new.babel"));
+        fs::write(&old, &v1).unwrap();
+        assert_eq!(codec.migrate_archive(old.to_str().unwrap(), new.to_str().unwrap()).unwrap(), 1);
+        let migrated = fs::read_to_string(&new).unwrap();
+        assert!(migrated.starts_with(&format!("babel/{}\ntxt\n{}\n", FORMAT_VERSION, bytes.len())), "{}", migrated);
+        assert!(migrated.ends_with(&format!("{}\n", addresses.join("\n"))));
+        let mut decoded = Vec::new();
+        codec.decode_reader(migrated.as_bytes(), &mut decoded).unwrap();
+        assert_eq!(decoded, bytes);
+    }
+
+    // The size is a u64 in the header whatever the target's pointer width
+    #[test]
+    fn sizes_past_u32_round_trip_through_the_header() {
+        let size = u64::from(u32::MAX) + 12345;
+        let header = ArchiveHeader::new("bin", size, &Alphabet::default());
+        let mut text = Vec::new();
+        Archive::from_addresses(header, "").write(ArchiveFormat::Text, &mut text).unwrap();
+        assert_eq!(Archive::read_header(&text[..]).unwrap().size(), size);
+    }
+}
@@ -0,0 +1,81 @@
+//! Interop with the libraryofbabel.info website's page URLs.
+//!
+//! The site numbers walls, shelves, volumes and pages from 1 where addresses
+//! here count from 0, and writes hexagon names in lowercase. Only the
+//! coordinates are translated: the site derives page text from a hexagon with
+//! its own scheme, so a link shows *a* page at that spot, not our content.
+
+use crate::error::BabelError;
+use crate::library::{format_address, split_address};
+use crate::location::{LibraryGeometry, Location};
+
+const BOOK_URL: &str = "https://libraryofbabel.info/book.cgi";
+
+/// Link to the page at `address` on libraryofbabel.info.
+///
+/// The result looks like `https://libraryofbabel.info/book.cgi?hex-w1-s2-v03:4`,
+/// with the `:` before the page percent-encoded as `%3A`.
+pub fn page_url(address: &str) -> Result<String, BabelError> {
+    let (key, location) = split_address(address)?;
+    if !key.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(BabelError::MalformedAddress(address.to_string()));
+    }
+
+    Ok(format!("{}?{}-w{}-s{}-v{:02}%3A{}",
+        BOOK_URL,
+        key.to_ascii_lowercase(),
+        location.wall + 1,
+        location.shelf + 1,
+        location.volume + 1,
+        location.page + 1))
+}
+
+/// Parse a libraryofbabel.info book URL back into an address; the inverse of [`page_url`].
+pub fn address_from_url(url: &str) -> Result<String, BabelError> {
+    let invalid = || BabelError::InvalidUrl(url.to_string());
+
+    let query = url.strip_prefix(BOOK_URL)
+        .and_then(|rest| rest.strip_prefix('?'))
+        .ok_or_else(invalid)?;
+    let query = query.replace("%3A", ":").replace("%3a", ":");
+
+    let (book, page) = query.split_once(':').ok_or_else(invalid)?;
+    let fields: Vec<&str> = book.split('-').collect();
+    if fields.len() != 4 || fields[0].is_empty() {
+        return Err(invalid());
+    }
+
+    // Site coordinates are 1-based, so zero or a missing prefix is an error
+    let coordinate = |field: &str, prefix: char| -> Result<u32, BabelError> {
+        field.strip_prefix(prefix)
+            .and_then(|n| n.parse::<u32>().ok())
+            .and_then(|n| n.checked_sub(1))
+            .ok_or_else(invalid)
+    };
+
+    let location = Location {
+        wall: coordinate(fields[1], 'w')?,
+        shelf: coordinate(fields[2], 's')?,
+        volume: coordinate(fields[3], 'v')?,
+        page: page.parse::<u32>().ok().and_then(|n| n.checked_sub(1)).ok_or_else(invalid)?,
+    };
+
+    Ok(format_address(&fields[0].to_ascii_uppercase(), &location, &LibraryGeometry::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::BabelCodec;
+
+    #[test]
+    fn urls_parse_back_to_their_address() {
+        let mut addresses = BabelCodec::default().with_seed(Some(210)).encode_bytes(b"a page to link to").unwrap();
+        addresses.extend(["Z09:0:0:00:000".to_string(), "1A:3:4:31:409".to_string()]);
+        for address in addresses {
+            let url = page_url(&address).unwrap();
+            assert_eq!(address_from_url(&url).unwrap(), address, "{}", url);
+        }
+        assert_eq!(page_url("1A:3:4:31:409").unwrap(), "https://libraryofbabel.info/book.cgi?1a-w4-s5-v32%3A410");
+    }
+}
@@ -0,0 +1,69 @@
+use flate2::read::{GzDecoder, GzEncoder};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+use crate::error::BabelError;
+
+/// How the input is compressed before it is spelled onto pages.
+///
+/// Decoding reverses it after the pages are turned back into bytes, so only
+/// the archive's page count changes. Zstandard needs the `zstd` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Bytes go onto pages as they are.
+    #[default]
+    None,
+    /// Gzip at the default level.
+    Gzip,
+    /// Zstandard at the default level.
+    Zstd,
+}
+
+impl Compression {
+    pub(crate) fn is_none(&self) -> bool {
+        *self == Compression::None
+    }
+
+    /// The name written in the header.
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Result<Self, BabelError> {
+        match name {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(BabelError::InvalidHeader(format!("Unknown compression: {}", name))),
+        }
+    }
+
+    // `reader` with its bytes compressed as they are read
+    pub(crate) fn compress<'a, R: Read + 'a>(self, reader: R) -> Result<Box<dyn Read + 'a>, BabelError> {
+        Ok(match self {
+            Compression::None => Box::new(reader),
+            Compression::Gzip => Box::new(GzEncoder::new(reader, flate2::Compression::default())),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Box::new(zstd::stream::read::Encoder::new(reader, 0)?),
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => return Err(BabelError::CompressionUnavailable(self)),
+        })
+    }
+
+    // `reader` with its bytes decompressed as they are read
+    pub(crate) fn decompress<'a, R: Read + 'a>(self, reader: R) -> Result<Box<dyn Read + 'a>, BabelError> {
+        Ok(match self {
+            Compression::None => Box::new(reader),
+            Compression::Gzip => Box::new(GzDecoder::new(reader)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => return Err(BabelError::CompressionUnavailable(self)),
+        })
+    }
+}
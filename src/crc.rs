@@ -0,0 +1,66 @@
+// CRC-32 (IEEE 802.3, reflected, as used by zip and gzip)
+
+use std::cell::Cell;
+use std::io::{self, Read};
+use std::rc::Rc;
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static TABLE: [u32; 256] = make_table();
+
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+// A CRC-32 fed a piece at a time, for bytes that never sit in memory together
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Crc32(u32);
+
+impl Crc32 {
+    pub(crate) fn new() -> Self {
+        Crc32(!0)
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        self.0 = bytes.iter().fold(self.0, |crc, &b| TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8));
+    }
+
+    pub(crate) fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+// Passes reads through, adding every byte to `crc` on the way
+//
+// The running CRC is shared so it can be read back after the reader has been
+// handed on to a compressor that owns it.
+pub(crate) struct CrcReader<R> {
+    pub(crate) inner: R,
+    pub(crate) crc: Rc<Cell<Crc32>>,
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let mut crc = self.crc.get();
+        crc.update(&buf[..n]);
+        self.crc.set(crc);
+        Ok(n)
+    }
+}
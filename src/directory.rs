@@ -0,0 +1,240 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufReader, ErrorKind, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::codec::BabelCodec;
+use crate::error::BabelError;
+use crate::temp_file::TempFile;
+use crate::verify::VerifyStats;
+
+/// One file of a directory archive and where its bytes sit in the decoded stream.
+///
+/// A directory archive holds its files' bytes one after another, in the order
+/// of its entries, and decodes them back into a directory tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    /// Path relative to the archived directory, with `/` between components.
+    pub path: String,
+    /// Where the file's bytes start in the decoded stream.
+    pub offset: u64,
+    /// How many bytes the file holds.
+    pub size: u64,
+}
+
+// Every regular file under `root`, relative to it, walking each directory in name order
+//
+// Symlinks and other special files are skipped with a warning, so a link
+// cannot pull in files from outside the directory or loop back into it.
+fn collect_files(root: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(root.join(relative))?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = relative.join(entry.file_name());
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if kind.is_file() {
+            files.push(path);
+        } else {
+            warn!("skipping {}, which is not a regular file", root.join(&path).display());
+        }
+    }
+    Ok(())
+}
+
+// `path` as written in the manifest, the same on every platform
+fn manifest_path(path: &Path) -> Result<String, BabelError> {
+    let parts = path
+        .components()
+        .map(|part| part.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, format!("{} has a name that is not UTF-8", path.display())))?;
+    Ok(parts.join("/"))
+}
+
+// Where a manifest path lands under `root`, refusing anything that would leave it
+fn entry_path(root: &Path, entry: &ArchiveEntry) -> Result<PathBuf, BabelError> {
+    let relative = Path::new(&entry.path);
+    let safe = !entry.path.is_empty() && relative.components().all(|part| matches!(part, Component::Normal(_)));
+    if !safe {
+        return Err(BabelError::InvalidHeader(format!("File entry escapes the output directory: {}", entry.path)));
+    }
+    Ok(root.join(relative))
+}
+
+// The files of a directory read back to back, each opened only when reached
+//
+// Each file is read for exactly the size recorded in the manifest, so one that
+// changes while it is archived is an error rather than a shifted manifest.
+struct DirectoryReader<'a> {
+    root: &'a Path,
+    files: std::iter::Zip<std::slice::Iter<'a, PathBuf>, std::slice::Iter<'a, ArchiveEntry>>,
+    current: Option<(io::Take<BufReader<fs::File>>, &'a Path)>,
+}
+
+impl Read for DirectoryReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some((reader, path)) = self.current.as_mut() {
+                match reader.read(buf)? {
+                    0 if buf.is_empty() => return Ok(0),
+                    0 if reader.limit() > 0 => {
+                        let message = format!("{} shrank while it was being archived", self.root.join(path).display());
+                        return Err(io::Error::new(ErrorKind::UnexpectedEof, message));
+                    }
+                    0 => self.current = None,
+                    n => return Ok(n),
+                }
+            }
+            match self.files.next() {
+                Some((path, entry)) => {
+                    let file = BufReader::new(fs::File::open(self.root.join(path))?);
+                    self.current = Some((file.take(entry.size), path));
+                }
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+impl BabelCodec {
+    /// Encode every file under the directory at `dir_path` into one archive.
+    ///
+    /// The files' bytes go onto pages back to back, and the header lists each
+    /// file's path, offset and size so decoding rebuilds the tree; see
+    /// [`ArchiveEntry`]. Empty directories, symlinks and file metadata are not
+    /// kept. The archive is written to `output_path`, or beside the directory
+    /// under its name with a `.babel` extension.
+    pub fn encode_directory(&self, dir_path: &str, output_path: Option<&str>) -> Result<VerifyStats, BabelError> {
+        let root = fs::canonicalize(dir_path)?;
+        let output_path = match output_path {
+            Some(path) => path.to_string(),
+            None => root.with_extension("babel").to_string_lossy().to_string(),
+        };
+        // Reading the archive while it is written would never finish cleanly
+        let output_dir = Path::new(&output_path).parent().filter(|dir| !dir.as_os_str().is_empty());
+        if fs::canonicalize(output_dir.unwrap_or(Path::new(".")))?.starts_with(&root) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is inside the directory being archived", output_path),
+            ).into());
+        }
+
+        self.progress(format_args!("Reading directory..."));
+        let mut files = Vec::new();
+        collect_files(&root, Path::new(""), &mut files)?;
+        if files.is_empty() {
+            return Err(io::Error::new(ErrorKind::InvalidInput, format!("{} holds no files to archive", dir_path)).into());
+        }
+        let mut entries = Vec::with_capacity(files.len());
+        let mut offset = 0;
+        for path in &files {
+            let size = fs::metadata(root.join(path))?.len();
+            entries.push(ArchiveEntry { path: manifest_path(path)?, offset, size });
+            offset += size;
+        }
+        self.progress(format_args!("Archiving {} files, {} bytes...", entries.len(), offset));
+
+        let open = || -> Result<Box<dyn Read + '_>, BabelError> {
+            Ok(Box::new(DirectoryReader { root: &root, files: files.iter().zip(entries.iter()), current: None }))
+        };
+        self.encode_to_path(open, &output_path, |encoded| {
+            let mut header = self.new_header("", encoded);
+            header.name = root.file_name().map(|name| name.to_string_lossy().into_owned());
+            header.files = entries.clone();
+            Ok(header)
+        })
+    }
+}
+
+// Split the decoded stream of a directory archive back into its files under `root`
+//
+// The entries must tile the stream exactly, so a doctored manifest cannot
+// drop bytes or write one file's bytes into another.
+pub(crate) fn write_files(bytes: &[u8], entries: &[ArchiveEntry], root: &Path) -> Result<(), BabelError> {
+    let mut offset = 0;
+    for entry in entries {
+        if entry.offset != offset {
+            return Err(BabelError::InvalidHeader(format!(
+                "File entry {} starts at byte {} where the previous file ends at {}", entry.path, entry.offset, offset
+            )));
+        }
+        offset = offset.saturating_add(entry.size);
+    }
+    if offset != bytes.len() as u64 {
+        return Err(BabelError::InvalidHeader(format!(
+            "File entries cover {} bytes but the archive decodes to {}", offset, bytes.len()
+        )));
+    }
+
+    for entry in entries {
+        let path = entry_path(root, entry)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut output = TempFile::create(&path)?;
+        output.write_all(&bytes[entry.offset as usize..(entry.offset + entry.size) as usize])?;
+        output.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::Archive;
+    use crate::page_length::PageLength;
+
+    fn entry(path: &str, offset: u64, size: u64) -> ArchiveEntry {
+        ArchiveEntry { path: path.to_string(), offset, size }
+    }
+
+    #[test]
+    fn nested_directories_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let (tree, archive, output) = (dir.path().join("tree"), dir.path().join("tree.babel"), dir.path().join("out"));
+        fs::create_dir_all(tree.join("a/b")).unwrap();
+        fs::write(tree.join("top.txt"), b"at the top").unwrap();
+        fs::write(tree.join("a/b/deep.bin"), [0u8, 1, 2, 255]).unwrap();
+        fs::write(tree.join("a/empty"), b"").unwrap();
+        let codec = BabelCodec::default().with_progress(false).with_page_length(PageLength::new(40).unwrap());
+        codec.encode_directory(tree.to_str().unwrap(), archive.to_str()).unwrap();
+
+        let header = Archive::open(&archive).unwrap().header().clone();
+        let paths: Vec<&str> = header.files().iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, ["a/b/deep.bin", "a/empty", "top.txt"]);
+
+        codec.decode_file(archive.to_str().unwrap(), output.to_str()).unwrap();
+        assert_eq!(fs::read(output.join("top.txt")).unwrap(), b"at the top");
+        assert_eq!(fs::read(output.join("a/b/deep.bin")).unwrap(), [0u8, 1, 2, 255]);
+        assert_eq!(fs::read(output.join("a/empty")).unwrap(), b"");
+
+        // A stream has nowhere to put the manifest
+        let contents = fs::read(&archive).unwrap();
+        assert!(matches!(codec.decode_reader(&contents[..], io::sink()), Err(BabelError::UnsupportedOptions(_))));
+    }
+
+    #[test]
+    fn entries_leaving_the_output_directory_are_refused() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().join("out");
+        match write_files(b"xy", &[entry("../x", 0, 2)], &root) {
+            Err(BabelError::InvalidHeader(reason)) => assert!(reason.contains("escapes"), "{}", reason),
+            other => panic!("expected an escaping entry to be refused, got {:?}", other),
+        }
+        assert!(!dir.path().join("x").exists());
+    }
+
+    #[test]
+    fn entries_leaving_a_gap_are_refused() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().join("out");
+        match write_files(b"abcd", &[entry("one", 0, 1), entry("two", 2, 2)], &root) {
+            Err(BabelError::InvalidHeader(reason)) => assert!(reason.contains("starts at byte 2"), "{}", reason),
+            other => panic!("expected a gap to be refused, got {:?}", other),
+        }
+        assert!(!root.exists());
+    }
+}
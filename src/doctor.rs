@@ -0,0 +1,64 @@
+use crate::archive::Archive;
+use crate::codec::BabelCodec;
+use crate::error::BabelError;
+use crate::address::Address;
+use crate::library::page_number;
+
+impl BabelCodec {
+    /// Problems that would stop `archive` from decoding with this codec.
+    ///
+    /// Only the header and address syntax are checked; no page is generated
+    /// beyond the header pages of a self-contained archive, so this is cheap
+    /// even for very large archives. An empty list means nothing was found.
+    pub fn diagnose(&self, archive: &Archive) -> Vec<String> {
+        let resolved;
+        let archive = if archive.header.self_contained {
+            match self.resolve_embedded(archive.clone()) {
+                Ok(archive) => {
+                    resolved = archive;
+                    &resolved
+                }
+                Err(e) => return vec![e.to_string()],
+            }
+        } else {
+            archive
+        };
+
+        let codec = match self.for_header(archive.header()) {
+            Ok(codec) => codec,
+            Err(e) => return vec![e.to_string()],
+        };
+        let mut problems = Vec::new();
+        let header = archive.header();
+
+        if let Err(e) = codec.check_alphabet(header) {
+            problems.push(e.to_string());
+        }
+
+        let expected = codec.layout(header).page_count();
+        if archive.addresses().len() != expected {
+            problems.push(format!(
+                "Archive lists {} pages but {} bytes need {}",
+                archive.addresses().len(), header.size(), expected
+            ));
+        }
+
+        let space = codec.space();
+        if let Err(e) = &space {
+            problems.push(e.to_string());
+        }
+        for (index, address) in archive.addresses().iter().enumerate() {
+            let checked = match space {
+                Ok(space) => {
+                    Address::parse_in(address, &space.geometry).and_then(|address| page_number(&address, space)).map(|_| ())
+                }
+                Err(_) => address.parse::<Address>().map(|_| ()),
+            };
+            if let Err(e) = checked {
+                problems.push(BabelError::BadAddress { index, line: None, source: Box::new(e) }.to_string());
+            }
+        }
+
+        problems
+    }
+}
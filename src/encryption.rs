@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::error::BabelError;
+
+// Name written in the header for the one scheme there is
+const SCHEME: &str = "chacha20poly1305";
+const SALT_BYTES: usize = 16;
+const NONCE_BYTES: usize = 12;
+
+/// A passphrase for encrypting or decrypting an archive, kept out of `Debug` output.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+pub(crate) struct Passphrase(pub(crate) String);
+
+impl fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Passphrase(..)")
+    }
+}
+
+// How an archive's bytes were encrypted: ChaCha20-Poly1305 under a key
+// derived from the passphrase with Argon2id
+//
+// The bytes on the pages are the ciphertext with its 16-byte tag, taken after
+// compression, so the recorded size counts them. A new salt and nonce are drawn
+// for every encode, whatever the codec's seed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Encryption {
+    salt: [u8; SALT_BYTES],
+    nonce: [u8; NONCE_BYTES],
+}
+
+impl Encryption {
+    // The header value: the scheme, then the salt and nonce in hex
+    pub(crate) fn to_header_value(&self) -> String {
+        format!("{} {} {}", SCHEME, hex(&self.salt), hex(&self.nonce))
+    }
+
+    pub(crate) fn from_header_value(value: &str) -> Result<Self, BabelError> {
+        let invalid = || BabelError::InvalidHeader(format!("Invalid encryption: {}", value));
+        let mut fields = value.split(' ');
+        if fields.next() != Some(SCHEME) {
+            return Err(BabelError::InvalidHeader(format!("Unsupported encryption: {}", value)));
+        }
+        let salt = fields.next().and_then(unhex).ok_or_else(invalid)?;
+        let nonce = fields.next().and_then(unhex).ok_or_else(invalid)?;
+        if fields.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Encryption { salt, nonce })
+    }
+
+    // Encrypt `plain` under a fresh salt and nonce
+    #[cfg(feature = "encryption")]
+    pub(crate) fn seal(passphrase: &Passphrase, plain: &[u8]) -> Result<(Self, Vec<u8>), BabelError> {
+        use chacha20poly1305::aead::Aead;
+        use rand::RngCore;
+
+        let mut encryption = Encryption { salt: [0; SALT_BYTES], nonce: [0; NONCE_BYTES] };
+        rand::rngs::OsRng.fill_bytes(&mut encryption.salt);
+        rand::rngs::OsRng.fill_bytes(&mut encryption.nonce);
+        let sealed = encryption
+            .cipher(passphrase)?
+            .encrypt(&encryption.nonce.into(), plain)
+            .map_err(|_| BabelError::EncryptionFailed("the cipher refused the input".to_string()))?;
+        Ok((encryption, sealed))
+    }
+
+    // Decrypt `sealed`; a wrong passphrase and corrupt bytes both fail the tag
+    #[cfg(feature = "encryption")]
+    pub(crate) fn open(&self, passphrase: &Passphrase, sealed: &[u8]) -> Result<Vec<u8>, BabelError> {
+        use chacha20poly1305::aead::Aead;
+
+        self.cipher(passphrase)?.decrypt(&self.nonce.into(), sealed).map_err(|_| BabelError::WrongPassphrase)
+    }
+
+    #[cfg(feature = "encryption")]
+    fn cipher(&self, passphrase: &Passphrase) -> Result<chacha20poly1305::ChaCha20Poly1305, BabelError> {
+        use chacha20poly1305::KeyInit;
+
+        let mut key = [0u8; 32];
+        argon2::Argon2::default()
+            .hash_password_into(passphrase.0.as_bytes(), &self.salt, &mut key)
+            .map_err(|e| BabelError::EncryptionFailed(format!("cannot derive a key: {}", e)))?;
+        Ok(chacha20poly1305::ChaCha20Poly1305::new(&key.into()))
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub(crate) fn seal(_: &Passphrase, _: &[u8]) -> Result<(Self, Vec<u8>), BabelError> {
+        Err(BabelError::EncryptionUnavailable)
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub(crate) fn open(&self, _: &Passphrase, _: &[u8]) -> Result<Vec<u8>, BabelError> {
+        Err(BabelError::EncryptionUnavailable)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    if text.len() != N * 2 {
+        return None;
+    }
+    let mut bytes = [0u8; N];
+    for (byte, pair) in bytes.iter_mut().zip(text.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+#[cfg(all(test, feature = "encryption"))]
+mod tests {
+    use crate::codec::BabelCodec;
+    use crate::error::BabelError;
+    use crate::page_length::PageLength;
+
+    #[test]
+    fn only_the_right_passphrase_decrypts() {
+        let codec = BabelCodec::default().with_progress(false).with_page_length(PageLength::new(20).unwrap());
+        let sealed = codec.clone().with_passphrase(Some("correct horse".to_string()));
+        let mut archive = Vec::new();
+        sealed.encode_reader(&b"kept under a passphrase"[..], "txt", &mut archive).unwrap();
+
+        let mut decoded = Vec::new();
+        sealed.decode_reader(&archive[..], &mut decoded).unwrap();
+        assert_eq!(decoded, b"kept under a passphrase");
+        let wrong = codec.clone().with_passphrase(Some("battery staple".to_string()));
+        assert!(matches!(wrong.decode_reader(&archive[..], std::io::sink()), Err(BabelError::WrongPassphrase)));
+        assert!(matches!(codec.decode_reader(&archive[..], std::io::sink()), Err(BabelError::PassphraseRequired)));
+    }
+
+    #[test]
+    fn encrypted_encodes_refuse_to_resume() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let input = dir.path().join("input.txt");
+        std::fs::write(&input, b"sealed").unwrap();
+        let codec = BabelCodec::default().with_progress(false).with_passphrase(Some("pass".to_string())).with_resume(true);
+        match codec.encode_file(input.to_str().unwrap(), None) {
+            Err(BabelError::UnsupportedOptions(reason)) => assert!(reason.contains("resumed"), "{}", reason),
+            other => panic!("resumed an encrypted encode: {:?}", other),
+        }
+    }
+}
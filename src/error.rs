@@ -0,0 +1,241 @@
+use std::fmt;
+use std::io;
+
+use crate::compression::Compression;
+use crate::location::LibraryGeometry;
+use crate::padding::PadStrategy;
+use crate::verify::VerifyStats;
+
+/// Errors produced while encoding or decoding babel archives.
+#[derive(Debug)]
+pub enum BabelError {
+    Io(io::Error),
+    #[cfg(feature = "network")]
+    Http(reqwest::Error),
+    /// The archive header is missing or could not be parsed.
+    InvalidHeader(String),
+    /// The input does not look like a babel archive at all; the reason says why.
+    NotAnArchive(String),
+    /// An address line is not of the form `key:wall:shelf:volume:page`.
+    MalformedAddress(String),
+    /// An address does not have exactly five `:`-separated parts.
+    WrongPartCount { address: String, parts: usize },
+    /// An address's key is not an uppercase base-36 number.
+    InvalidRadix(String),
+    /// An address's wall, shelf, volume or page is beyond the last of the library's geometry.
+    LocationOutOfRange { address: String, geometry: LibraryGeometry },
+    /// An address's key does not leave a number any page of this length spells:
+    /// `negative` when the key is smaller than its location allows, otherwise too large.
+    KeyOutOfRange { address: String, negative: bool },
+    /// A number handed to `to_text` is negative, or too large for any page of this length.
+    PageNumberOutOfRange { negative: bool },
+    /// A URL is not a libraryofbabel.info book link.
+    InvalidUrl(String),
+    /// The page length is zero, too short to align, or too large for the location multiplier.
+    PageLengthOutOfRange(usize),
+    /// The codec was given one page length and the archive records another.
+    PageLengthMismatch { codec: usize, archive: usize },
+    /// A library geometry has a count of zero or could not be parsed.
+    InvalidGeometry(String),
+    /// The codec was given one library geometry and the archive records another.
+    GeometryMismatch { codec: LibraryGeometry, archive: LibraryGeometry },
+    /// Text handed to a search is not exactly one page long.
+    WrongPageLength { expected: usize, found: usize },
+    /// An alphabet was built from an unusable symbol set.
+    InvalidAlphabet(String),
+    /// Text-mode input held a byte that cannot be written on a page.
+    InvalidTextChar { position: u64, byte: u8 },
+    /// The page symbols at `offset` do not spell bytes: one is not in the alphabet,
+    /// or their value passes 255 (or what a base29 block can hold).
+    InvalidBytePair { offset: usize, pair: String },
+    /// Input bytes read at `offset` did not read back from the page text they
+    /// were spelled as, so the alphabet's spelling is not reversible.
+    SpellingMismatch { offset: u64 },
+    /// Page text ended partway through a byte, or a base29 block, at symbol `offset`.
+    UnpairedSymbol { offset: usize },
+    /// A phrase to locate is longer than one page.
+    PhraseTooLong { found: usize, limit: usize },
+    /// Text handed to a search holds a character that no page can spell.
+    InvalidPageChar { position: usize, found: char },
+    /// The padding strategy uses a character that cannot appear on a page.
+    InvalidPadding(PadStrategy),
+    /// The archive or codec asks for a compression this build was compiled without.
+    CompressionUnavailable(Compression),
+    /// The archive or codec asks for encryption and this build was compiled without it.
+    EncryptionUnavailable,
+    /// The archive is encrypted and no passphrase was given.
+    PassphraseRequired,
+    /// Encrypting failed, or no key could be derived from the passphrase.
+    EncryptionFailed(String),
+    /// The passphrase does not decrypt the archive, or its bytes were altered.
+    WrongPassphrase,
+    /// Strict ASCII decoding found a byte that is not printable ASCII.
+    NonPrintableByte { offset: u64, byte: u8 },
+    /// A decoded page does not match the checksum stored for it.
+    PageChecksumMismatch { index: usize },
+    /// The reassembled file does not match the CRC-32 recorded for it.
+    ChecksumMismatch { expected: u32, found: u32 },
+    /// The input needs more pages than the archive was asked to pad to.
+    TooManyPages { pages: usize, limit: usize },
+    /// An archive or address list has fewer pages than its size needs.
+    TooFewPages { pages: usize, needed: usize },
+    /// The codec's options cannot be used together, or not on this archive; the reason says which.
+    UnsupportedOptions(String),
+    /// The archive's recorded size is more bytes than this platform can hold in memory.
+    SizeTooLarge(u64),
+    /// Page `index` of a resumed encode is not the page its checkpoint recorded,
+    /// so the input changed since the checkpoint was written.
+    CheckpointMismatch { index: usize },
+    /// A single address line is larger than the requested split size.
+    SplitSizeTooSmall { limit: u64, line: u64 },
+    /// The address of page `index` names no page; `line` is where it sits in a text archive.
+    BadAddress { index: usize, line: Option<usize>, source: Box<BabelError> },
+    /// Some pages did not resolve back to their content after encoding.
+    VerificationFailed(VerifyStats),
+    /// An archive decoded back to other bytes than were encoded, first differing at `offset`.
+    RoundTripMismatch { offset: u64 },
+    /// The archive was written with a different alphabet than the codec decoding it.
+    AlphabetMismatch { expected: String, found: String },
+}
+
+impl fmt::Display for BabelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BabelError::Io(e) => write!(f, "{}", e),
+            #[cfg(feature = "network")]
+            BabelError::Http(e) => write!(f, "HTTP error: {}", e),
+            BabelError::InvalidHeader(msg) => write!(f, "Invalid header: {}", msg),
+            BabelError::NotAnArchive(reason) => write!(f, "Not a babel archive: {}", reason),
+            BabelError::MalformedAddress(address) => write!(f, "Malformed address: {}", address),
+            BabelError::WrongPartCount { address, parts } => write!(
+                f, "Address {} has {} parts instead of key:wall:shelf:volume:page", address, parts
+            ),
+            BabelError::InvalidRadix(address) => write!(
+                f, "Address {} has a key that is not uppercase base 36 (only 0-9 and A-Z)", address
+            ),
+            BabelError::LocationOutOfRange { address, geometry } => write!(
+                f, "Address {} is outside the library, which has {} walls, {} shelves, {} volumes and {} pages",
+                address, geometry.walls, geometry.shelves, geometry.volumes, geometry.pages
+            ),
+            BabelError::KeyOutOfRange { address, negative: true } => write!(
+                f, "Address {} has a key too small for its location, so no page is there", address
+            ),
+            BabelError::KeyOutOfRange { address, negative: false } => write!(
+                f, "Address {} has a key too large for any page of this length", address
+            ),
+            BabelError::PageNumberOutOfRange { negative: true } => write!(f, "No page spells a negative number"),
+            BabelError::PageNumberOutOfRange { negative: false } => write!(
+                f, "Number is too large for any page of this length"
+            ),
+            BabelError::InvalidUrl(url) => write!(f, "Not a Library of Babel page URL: {}", url),
+            BabelError::PageLengthOutOfRange(length) => write!(
+                f, "Page length {} is out of range (must be between 1 and {})", length, u32::MAX
+            ),
+            BabelError::WrongPageLength { expected, found } => write!(
+                f, "Page text has {} characters where the page length is {}", found, expected
+            ),
+            BabelError::PageLengthMismatch { codec, archive } => write!(
+                f, "Page length mismatch: codec uses {} but archive was encoded with {}", codec, archive
+            ),
+            BabelError::InvalidGeometry(msg) => write!(f, "Invalid library geometry: {}", msg),
+            BabelError::GeometryMismatch { codec, archive } => write!(
+                f, "Library geometry mismatch: codec uses {} but archive was encoded with {}", codec, archive
+            ),
+            BabelError::InvalidAlphabet(msg) => write!(f, "Invalid alphabet: {}", msg),
+            BabelError::InvalidTextChar { position, byte } => write!(
+                f, "Byte {:#04x} at offset {} cannot be written on a page in text mode", byte, position
+            ),
+            BabelError::InvalidBytePair { offset, pair } => write!(
+                f, "Symbols {:?} at offset {} do not spell a byte", pair, offset
+            ),
+            BabelError::SpellingMismatch { offset } => write!(
+                f, "Bytes read at offset {} do not read back from their page text", offset
+            ),
+            BabelError::UnpairedSymbol { offset } => write!(
+                f, "Symbol at offset {} is half a byte with nothing after it", offset
+            ),
+            BabelError::PhraseTooLong { found, limit } => write!(
+                f, "Phrase has {} characters but a page holds only {}", found, limit
+            ),
+            BabelError::InvalidPageChar { position, found } => write!(
+                f, "Character {:?} at position {} is not a page character", found, position
+            ),
+            BabelError::InvalidPadding(pad) => write!(f, "Invalid padding {:?}: not a page character", pad),
+            BabelError::CompressionUnavailable(compression) => write!(
+                f, "{} compression is not available; rebuild with the `{}` feature", compression.name(), compression.name()
+            ),
+            BabelError::EncryptionUnavailable => write!(
+                f, "Encryption is not available; rebuild with the `encryption` feature"
+            ),
+            BabelError::PassphraseRequired => write!(f, "The archive is encrypted; a passphrase is needed to decode it"),
+            BabelError::EncryptionFailed(reason) => write!(f, "Encryption failed: {}", reason),
+            BabelError::WrongPassphrase => write!(
+                f, "Decryption failed: the passphrase is wrong or the archive's bytes were altered"
+            ),
+            BabelError::NonPrintableByte { offset, byte } => write!(
+                f, "Decoded byte {:#04x} at offset {} is not printable ASCII", byte, offset
+            ),
+            BabelError::PageChecksumMismatch { index } => write!(f, "Page {} does not match its checksum", index),
+            BabelError::ChecksumMismatch { expected, found } => write!(
+                f, "Decoded file has CRC-32 {:08x} but the archive records {:08x}", found, expected
+            ),
+            BabelError::TooManyPages { pages, limit } => write!(
+                f, "Input needs {} pages, more than the {} requested", pages, limit
+            ),
+            BabelError::TooFewPages { pages, needed } => write!(
+                f, "Only {} pages for a size that needs {}", pages, needed
+            ),
+            BabelError::UnsupportedOptions(reason) => write!(f, "Unsupported options: {}", reason),
+            BabelError::SizeTooLarge(size) => write!(
+                f, "Archive holds {} bytes, more than this platform can hold in memory", size
+            ),
+            BabelError::CheckpointMismatch { index } => write!(
+                f, "Page {} of the input does not match the checkpoint; delete it to start over", index
+            ),
+            BabelError::SplitSizeTooSmall { limit, line } => write!(
+                f, "Split size of {} bytes cannot hold an address line of {} bytes", limit, line
+            ),
+            BabelError::BadAddress { index, line: Some(line), source } => write!(f, "Page {} (line {}): {}", index, line, source),
+            BabelError::BadAddress { index, line: None, source } => write!(f, "Page {}: {}", index, source),
+            BabelError::VerificationFailed(stats) => write!(f, "Page verification failed: {}", stats),
+            BabelError::RoundTripMismatch { offset } => write!(
+                f, "Archive decodes to other bytes than were encoded, first differing at byte {}", offset
+            ),
+            BabelError::AlphabetMismatch { expected, found } => write!(
+                f,
+                "Alphabet mismatch: codec uses '{}' but archive was encoded with '{}'",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BabelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BabelError::Io(e) => Some(e),
+            #[cfg(feature = "network")]
+            BabelError::Http(e) => Some(e),
+            BabelError::BadAddress { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BabelError {
+    // Errors raised inside a `Read` over decoded pages come back out as themselves
+    fn from(e: io::Error) -> Self {
+        if e.get_ref().is_some_and(|inner| inner.is::<BabelError>()) {
+            *e.into_inner().and_then(|inner| inner.downcast().ok()).expect("checked to be a BabelError")
+        } else {
+            BabelError::Io(e)
+        }
+    }
+}
+
+#[cfg(feature = "network")]
+impl From<reqwest::Error> for BabelError {
+    fn from(e: reqwest::Error) -> Self {
+        BabelError::Http(e)
+    }
+}
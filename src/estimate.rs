@@ -0,0 +1,51 @@
+use crate::address::Address;
+use crate::archive::{Archive, ArchiveFormat, ArchiveHeader};
+use crate::codec::BabelCodec;
+use crate::error::BabelError;
+use crate::location::Location;
+
+// Bytes the Poly1305 tag adds to an encrypted input
+const TAG_BYTES: u64 = 16;
+
+/// What encoding an input would produce, worked out without searching for any page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodeEstimate {
+    /// Pages the input fills, including any filler up to `--pad-to-pages`.
+    pub pages: usize,
+    /// Characters in the longest address a page of this length can have.
+    pub address_length: usize,
+    /// Bytes of a text archive holding that many of the longest addresses.
+    pub archive_bytes: u64,
+}
+
+impl BabelCodec {
+    /// Estimate the archive encoding `size` bytes would give.
+    ///
+    /// Compression is not applied, so for a compressed encode the page count
+    /// is an upper bound. Addresses are all counted at the longest a key can
+    /// be, which most keys miss by a digit or two, and the header pages of a
+    /// self-contained archive are left out.
+    pub fn estimate(&self, size: u64) -> Result<EncodeEstimate, BabelError> {
+        let sealed = if self.passphrase.is_some() { size + TAG_BYTES } else { size };
+        let header = ArchiveHeader::new("", sealed, self.alphabet()).with_payload(self.payload);
+        let pages = self.layout(&header).page_count().max(self.pad_to_pages.unwrap_or(0));
+
+        // The last location of the last page of the space keys the largest number
+        let space = self.space()?;
+        let geometry = space.geometry;
+        let last = Location {
+            wall: geometry.walls - 1,
+            shelf: geometry.shelves - 1,
+            volume: geometry.volumes - 1,
+            page: geometry.pages - 1,
+        };
+        let key = &space.page_count - 1 + last.to_loc_int(&geometry) * &space.loc_mult;
+        let address_length = Address { key, location: last }.to_string_in(&geometry).len();
+
+        let mut header_text = Vec::new();
+        Archive::from_addresses(header, "").write(ArchiveFormat::Text, &mut header_text)?;
+        // One separator, a space or a newline, after every address
+        let archive_bytes = header_text.len() as u64 + pages as u64 * (address_length as u64 + 1);
+        Ok(EncodeEstimate { pages, address_length, archive_bytes })
+    }
+}
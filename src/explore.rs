@@ -0,0 +1,111 @@
+use num_bigint::BigInt;
+use num_traits::Signed;
+
+use crate::address::Address;
+use crate::codec::BabelCodec;
+use crate::error::BabelError;
+use crate::library::{page_number, search, PageSpace, PAD_CHAR};
+use crate::location::{LibraryGeometry, Location};
+
+/// The pages around an address: the rest of its book and the other books on its shelf.
+///
+/// The library is laid out by content. Within a hexagon, consecutive page
+/// numbers (the page text read as a base-29 number) fill each book page by
+/// page, then the books on a shelf, then the shelves and walls. The next page
+/// of a book is therefore the one whose number is one greater. Near the very
+/// start or end of the library some neighbours do not exist and are left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryNeighborhood {
+    /// Where the starting page is.
+    pub location: Location,
+    /// The other pages of the same book, in page order.
+    pub same_book: Vec<String>,
+    /// The first page of each other book on the same shelf, in volume order.
+    pub same_shelf: Vec<String>,
+}
+
+// Position of `location` when the hexagon is read page by page, volume by volume, shelf by shelf
+fn ordinal(location: &Location, geometry: &LibraryGeometry) -> u64 {
+    let shelf = location.wall as u64 * geometry.shelves as u64 + location.shelf as u64;
+    let volume = shelf * geometry.volumes as u64 + location.volume as u64;
+    volume * geometry.pages as u64 + location.page as u64
+}
+
+// The address holding page number `number` at `location`, if any page has that number
+fn address_at(number: &BigInt, location: &Location, space: &PageSpace) -> Option<String> {
+    if number.is_negative() || *number >= space.page_count {
+        return None;
+    }
+    let loc_int = location.to_loc_int(&space.geometry);
+    Some(Address { key: number + loc_int * &space.loc_mult, location: *location }.to_string_in(&space.geometry))
+}
+
+impl BabelCodec {
+    /// The neighbourhood of the page at `address`; see [`LibraryNeighborhood`].
+    ///
+    /// Addresses are computed from the page numbers alone, so no page text is
+    /// generated, and every address returned resolves with this codec.
+    pub fn neighbors(&self, address: &str) -> Result<LibraryNeighborhood, BabelError> {
+        let space = self.space()?;
+        let geometry = space.geometry;
+        let address: Address = address.parse()?;
+        let number = page_number(&address, space)?;
+        let location = address.location;
+
+        // Page number of the first page in this hexagon
+        let base = number - ordinal(&location, &geometry);
+        let at = |location: Location| address_at(&(&base + ordinal(&location, &geometry)), &location, space);
+
+        let same_book = (0..geometry.pages)
+            .filter(|&page| page != location.page)
+            .filter_map(|page| at(Location { page, ..location }))
+            .collect();
+        let same_shelf = (0..geometry.volumes)
+            .filter(|&volume| volume != location.volume)
+            .filter_map(|volume| at(Location { volume, page: 0, ..location }))
+            .collect();
+
+        Ok(LibraryNeighborhood { location, same_book, same_shelf })
+    }
+
+    /// The address of a page that starts with `phrase` and is padded out with periods.
+    ///
+    /// The phrase may only use page characters: lowercase letters, comma,
+    /// space and period. The page is placed like an encoded one, at a random
+    /// location under this codec's bias and seed, so a seeded codec always
+    /// gives the same address.
+    pub fn locate(&self, phrase: &str) -> Result<Address, BabelError> {
+        let space = self.space()?;
+        let found = phrase.chars().count();
+        if found > space.length {
+            return Err(BabelError::PhraseTooLong { found, limit: space.length });
+        }
+        let mut page = String::with_capacity(space.length);
+        page.push_str(phrase);
+        page.extend(std::iter::repeat_n(PAD_CHAR, space.length - found));
+        search(&page, space, self.location_bias, &mut self.page_rng(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page_length::PageLength;
+
+    #[test]
+    fn neighbours_fill_the_rest_of_the_book_and_shelf() {
+        let codec = BabelCodec::default().with_progress(false).with_page_length(PageLength::new(20).unwrap()).with_seed(Some(249));
+        let address = codec.locate("a book of its own").unwrap().to_string();
+        let neighborhood = codec.neighbors(&address).unwrap();
+        let geometry = LibraryGeometry::default();
+        assert_eq!(neighborhood.same_book.len(), geometry.pages as usize - 1);
+        assert_eq!(neighborhood.same_shelf.len(), geometry.volumes as usize - 1);
+        assert!(!neighborhood.same_book.contains(&address));
+
+        for neighbour in neighborhood.same_book.iter().chain(&neighborhood.same_shelf) {
+            let location = neighbour.parse::<Address>().unwrap().location;
+            assert_eq!((location.wall, location.shelf), (neighborhood.location.wall, neighborhood.location.shelf));
+            assert!(codec.get_page_shared(neighbour).is_ok(), "{} does not resolve", neighbour);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::io::Read;
+
+use crate::archive::Archive;
+use crate::codec::BabelCodec;
+use crate::error::BabelError;
+
+impl BabelCodec {
+    /// Byte offset of the first occurrence of `needle` in the file encoded by `archive`.
+    ///
+    /// Pages are decoded one at a time and scanning stops at the first match,
+    /// so the decoded file is never held in memory. The last `needle.len() - 1`
+    /// bytes of each read are kept so matches spanning a page boundary are found.
+    /// Offsets in a compressed archive are into the decompressed file.
+    pub fn find_text<R: Read>(&self, archive: R, needle: &[u8]) -> Result<Option<u64>, BabelError> {
+        if needle.is_empty() {
+            return Ok(Some(0));
+        }
+
+        let archive = Archive::read(archive)?;
+
+        let overlap = needle.len() - 1;
+        let mut window: Vec<u8> = Vec::new();
+        let mut window_start: u64 = 0;
+
+        let mut reader = self.reader(&archive)?;
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            window.extend_from_slice(&buf[..n]);
+            if let Some(pos) = window.windows(needle.len()).position(|w| w == needle) {
+                return Ok(Some(window_start + pos as u64));
+            }
+
+            if window.len() > overlap {
+                let consumed = window.len() - overlap;
+                window.drain(..consumed);
+                window_start += consumed as u64;
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page_length::PageLength;
+
+    // Pages of twenty characters hold ten bytes each
+    const CONTENTS: &[u8] = b"ten bytes.the phrase spans pages.";
+
+    #[test]
+    fn phrases_straddling_a_page_boundary_are_found() {
+        let codec = BabelCodec::default().with_progress(false).with_page_length(PageLength::new(20).unwrap());
+        let mut archive = Vec::new();
+        codec.encode_reader(CONTENTS, "txt", &mut archive).unwrap();
+
+        assert_eq!(codec.find_text(&archive[..], b"bytes.the").unwrap(), Some(4));
+        assert_eq!(codec.find_text(&archive[..], b"phrase spans pages").unwrap(), Some(14));
+        assert_eq!(codec.find_text(&archive[..], b"pages.").unwrap(), Some(27));
+        assert_eq!(codec.find_text(&archive[..], b"not there").unwrap(), None);
+    }
+}
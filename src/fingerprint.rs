@@ -0,0 +1,37 @@
+use crate::codec::BabelCodec;
+use crate::crc::crc32;
+use crate::error::BabelError;
+use crate::library::page_number;
+
+impl BabelCodec {
+    /// A CRC-32 fingerprint of the content of the page at `address`.
+    ///
+    /// The fingerprint is of the page's content, not its key: the location is
+    /// subtracted out of the key first, and the number left is in one-to-one
+    /// correspondence with the page text. Pages with the same content therefore
+    /// share a fingerprint wherever they are stored, and no page text is
+    /// generated. Like any 32-bit checksum, different pages can collide.
+    pub fn page_content_checksum(&self, address: &str) -> Result<u32, BabelError> {
+        let (_, digits) = page_number(&address.parse()?, self.space()?)?.to_bytes_le();
+        Ok(crc32(&digits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page_length::PageLength;
+
+    #[test]
+    fn fingerprints_follow_content_not_location() {
+        let codec = BabelCodec::default().with_progress(false).with_page_length(PageLength::new(20).unwrap());
+        let first = codec.clone().with_seed(Some(1)).encode_bytes(b"same page.").unwrap();
+        let second = codec.clone().with_seed(Some(2)).encode_bytes(b"same page.").unwrap();
+        let other = codec.clone().with_seed(Some(1)).encode_bytes(b"other page").unwrap();
+        assert_ne!(first, second);
+
+        let fingerprint = |addresses: &[String]| codec.page_content_checksum(&addresses[0]).unwrap();
+        assert_eq!(fingerprint(&first), fingerprint(&second));
+        assert_ne!(fingerprint(&first), fingerprint(&other));
+    }
+}
@@ -0,0 +1,114 @@
+use crate::alphabet::{Alphabet, Block};
+use crate::archive::ArchiveHeader;
+use crate::page_length::PageLength;
+use crate::text_mode::PayloadMode;
+
+/// Which bytes of the original file each page of an archive holds.
+///
+/// In byte mode every byte takes two characters, so with an odd page length
+/// one byte straddles each boundary; it is counted on the page holding its
+/// second character, which is where decoding completes it. The base29
+/// alphabet spells three bytes at once, and all three go on the page holding
+/// the block's last character. The last page stops at the original size
+/// whatever padding follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageLayout {
+    size: u64,
+    page_length: u64,
+    block: Block,
+}
+
+impl PageLayout {
+    pub fn new(header: &ArchiveHeader, page_length: PageLength) -> Self {
+        // Custom alphabets all spell bytes in pairs, like the default
+        let block = match header.payload() {
+            PayloadMode::Bytes => Alphabet::built_in(header.alphabet()).unwrap_or_default().block(),
+            PayloadMode::Text => Block::TEXT,
+        };
+        PageLayout {
+            size: header.size(),
+            page_length: page_length.get() as u64,
+            block,
+        }
+    }
+
+    /// Number of pages the original file fills.
+    pub fn page_count(&self) -> usize {
+        self.text_len().div_ceil(self.page_length) as usize
+    }
+
+    /// Characters the whole file is spelled with, before any padding.
+    pub fn text_len(&self) -> u64 {
+        self.block.text_len(self.size)
+    }
+
+    // Characters spelling the bytes before `offset`, which starts a block or is the size
+    pub(crate) fn chars_before(&self, offset: u64) -> u64 {
+        self.block.text_len(offset)
+    }
+
+    // First byte whose last character falls on or after page `index`
+    fn boundary(&self, index: u64) -> u64 {
+        let chars = index.saturating_mul(self.page_length);
+        if chars >= self.text_len() {
+            return self.size;
+        }
+        self.block.bytes_before(chars).min(self.size)
+    }
+
+    /// `(byte_offset, byte_len)` of page `index`, or `None` past the last page.
+    pub fn page(&self, index: usize) -> Option<(u64, u64)> {
+        if index >= self.page_count() {
+            return None;
+        }
+        let start = self.boundary(index as u64);
+        Some((start, self.boundary(index as u64 + 1) - start))
+    }
+
+    /// `(byte_offset, byte_len)` of every page, in order.
+    pub fn pages(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        (0..self.page_count()).filter_map(|index| self.page(index))
+    }
+
+    /// Index of the page holding byte `offset`.
+    pub fn page_of(&self, offset: u64) -> Option<usize> {
+        if offset >= self.size {
+            return None;
+        }
+        let block = offset / self.block.bytes();
+        let end = (block + 1).saturating_mul(self.block.chars()).min(self.text_len());
+        Some(((end - 1) / self.page_length) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(size: u64) -> [ArchiveHeader; 3] {
+        let mut text = ArchiveHeader::new("txt", size, &Alphabet::default());
+        text.payload = PayloadMode::Text;
+        [ArchiveHeader::new("bin", size, &Alphabet::default()), ArchiveHeader::new("bin", size, &Alphabet::base29()), text]
+    }
+
+    #[test]
+    fn pages_cover_the_file_contiguously() {
+        for size in (0..120).chain([1000, 4099]) {
+            for header in headers(size) {
+                for length in [1, 2, 5, 7, 20, 3239] {
+                    let layout = PageLayout::new(&header, PageLength::new(length).unwrap());
+                    let case = format!("{:?} {} {} bytes, pages of {}", header.payload(), header.alphabet(), size, length);
+                    let mut next = 0;
+                    for (index, (offset, len)) in layout.pages().enumerate() {
+                        assert_eq!(offset, next, "{}: page {}", case, index);
+                        assert!((offset..offset + len).all(|byte| layout.page_of(byte) == Some(index)), "{}", case);
+                        next = offset + len;
+                    }
+                    assert_eq!(next, size, "{}", case);
+                    assert_eq!(layout.page_count() as u64, layout.text_len().div_ceil(length as u64), "{}", case);
+                    assert_eq!(layout.page_of(size), None);
+                }
+            }
+        }
+    }
+}
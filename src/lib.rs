@@ -0,0 +1,60 @@
+//! Encode files as Library of Babel page references and decode them back.
+
+mod address;
+mod alphabet;
+mod archive;
+pub mod babel;
+mod byte_iter;
+mod canonical;
+mod checkpoint;
+mod codec;
+mod compression;
+pub mod compat;
+mod crc;
+mod directory;
+mod encryption;
+mod doctor;
+mod error;
+mod estimate;
+mod explore;
+mod find;
+mod fingerprint;
+mod layout;
+mod library;
+mod location;
+mod metadata;
+mod padding;
+mod page_cache;
+mod page_length;
+mod parallel_read;
+mod reader;
+mod reencode;
+mod self_contained;
+mod shuffle;
+mod spool;
+mod temp_file;
+mod text_mode;
+mod verify;
+#[cfg(feature = "bytes")]
+mod buffers;
+#[cfg(feature = "network")]
+mod network;
+
+pub use address::Address;
+pub use alphabet::Alphabet;
+pub use archive::{Archive, ArchiveFormat, ArchiveHeader, FORMAT_VERSION};
+pub use codec::{BabelCodec, EncodedAddresses, Threads};
+pub use compression::Compression;
+pub use directory::ArchiveEntry;
+pub use error::BabelError;
+pub use estimate::EncodeEstimate;
+pub use explore::LibraryNeighborhood;
+pub use layout::PageLayout;
+pub use location::{LibraryGeometry, Location, LocationBias, PAGES, SHELVES, VOLUMES, WALLS};
+pub use padding::PadStrategy;
+pub use page_length::PageLength;
+pub use reader::ArchiveReader;
+pub use text_mode::{OnInvalidChar, PayloadMode};
+pub use verify::{VerifyMode, VerifyStats};
+#[cfg(feature = "network")]
+pub use network::{extension_for_content_type, HttpBody};
@@ -0,0 +1,376 @@
+use log::debug;
+use num_bigint::{BigInt, BigUint, Sign};
+use num_integer::Integer;
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::address::Address;
+use crate::error::BabelError;
+use crate::location::{LibraryGeometry, Location, LocationBias};
+
+/// Characters on a page of the default length.
+pub const LENGTH_OF_PAGE: usize = 3239;
+/// What fills the rest of a partial page under the default padding.
+///
+/// A period is also a page character, so it is only ever trimmed where it
+/// cannot be content: after bytes spelled by an alphabet without it. Base29
+/// spells with it, so its pages, like text-mode pages where periods are real
+/// text, end at the recorded size instead.
+pub const PAD_CHAR: char = '.';
+
+// Characters that can appear on a page, in digit order
+pub(crate) const CHARSET: &str = "abcdefghijklmnopqrstuvwxyz, .";
+
+// Digits at or below which the base conversions work on a number in one piece
+const SPLIT_DIGITS: usize = 256;
+// Digits at or above which the base conversions work on the two halves on separate threads
+const PARALLEL_DIGITS: usize = 1024;
+// Digits of an address key, in digit order
+const KEY_DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+// Calculate powers for location multiplier
+//
+// This is a multi-kilobyte number for real page lengths, so callers compute it
+// once per page length and pass it down rather than redoing it per page.
+fn calculate_loc_mult(length: usize) -> Result<BigInt, BabelError> {
+    // BigInt::pow only takes a u32 exponent
+    let length = u32::try_from(length).map_err(|_| BabelError::PageLengthOutOfRange(length))?;
+    let thirty = BigInt::from(30u32);
+    Ok(thirty.pow(length))
+}
+
+// The number a page spelling `input` holds, or the first character no page can spell
+pub(crate) fn string_to_number(input: &str) -> Result<BigInt, BabelError> {
+    let charset = CHARSET.as_bytes();
+    let digits = input
+        .chars()
+        .enumerate()
+        .map(|(position, c)| {
+            charset.iter().position(|&x| x as char == c).map(|digit| digit as u8)
+                .ok_or(BabelError::InvalidPageChar { position, found: c })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(BigInt::from(read_digits(&digits, 29)))
+}
+
+// A key in uppercase base 36; a negative number has no key and spells as nothing
+pub(crate) fn int_to_base36(x: BigInt) -> String {
+    let Some(x) = x.to_biguint() else {
+        return String::new();
+    };
+    // Room for any number of this many bits, with the leading zeros trimmed after
+    let mut digits = vec![0; (x.bits() as f64 / 36f64.log2()).ceil() as usize + 1];
+    write_digits(&x, 36, &mut digits);
+    let first = digits.iter().position(|&digit| digit != 0).unwrap_or(digits.len() - 1);
+    digits[first..].iter().map(|&digit| KEY_DIGITS[digit as usize] as char).collect()
+}
+
+// `radix^exponent`, computed once per process for the splits of the base conversions
+fn power(radix: u32, exponent: usize) -> Arc<BigUint> {
+    static POWERS: Mutex<BTreeMap<(u32, usize), Arc<BigUint>>> = Mutex::new(BTreeMap::new());
+    if let Some(power) = POWERS.lock().unwrap().get(&(radix, exponent)) {
+        return Arc::clone(power);
+    }
+    let power = Arc::new(BigUint::from(radix).pow(exponent as u32));
+    Arc::clone(POWERS.lock().unwrap().entry((radix, exponent)).or_insert(power))
+}
+
+// The number `digits` spell in `radix`, most significant first
+//
+// Folding in one digit at a time multiplies an ever longer number, which is
+// quadratic in the digit count, so long inputs are split in two, read on
+// their own, in parallel once they are long enough, and joined with one
+// multiplication by a power of the radix.
+fn read_digits(digits: &[u8], radix: u32) -> BigUint {
+    let length = digits.len();
+    if length <= SPLIT_DIGITS {
+        return BigUint::from_radix_be(digits, radix).expect("every digit is below the radix");
+    }
+    let low_length = length / 2;
+    let (high_digits, low_digits) = digits.split_at(length - low_length);
+    let (high, low) = if length >= PARALLEL_DIGITS {
+        rayon::join(|| read_digits(high_digits, radix), || read_digits(low_digits, radix))
+    } else {
+        (read_digits(high_digits, radix), read_digits(low_digits, radix))
+    };
+    high * &*power(radix, low_length) + low
+}
+
+// Fill `digits` with the digits of `x` in `radix`, most significant first and
+// zero-padded on the left; `x` must be below `radix^digits.len()`
+//
+// Repeated division by the radix costs time quadratic in the digit count, so
+// long numbers are split by a power of the radix into a high and a low half
+// that are written on their own, in parallel once they are long enough.
+fn write_digits(x: &BigUint, radix: u32, digits: &mut [u8]) {
+    let length = digits.len();
+    if length <= SPLIT_DIGITS {
+        let value = x.to_radix_be(radix);
+        let start = length - value.len();
+        digits[..start].fill(0);
+        digits[start..].copy_from_slice(&value);
+        return;
+    }
+    let low_length = length / 2;
+    let (high, low) = x.div_rem(&power(radix, low_length));
+    let (high_digits, low_digits) = digits.split_at_mut(length - low_length);
+    if length >= PARALLEL_DIGITS {
+        rayon::join(|| write_digits(&high, radix, high_digits), || write_digits(&low, radix, low_digits));
+    } else {
+        write_digits(&high, radix, high_digits);
+        write_digits(&low, radix, low_digits);
+    }
+}
+
+/// A page length together with its location multiplier `30^length`, and the
+/// geometry that lays locations out.
+#[derive(Debug, Clone)]
+pub(crate) struct PageSpace {
+    pub(crate) length: usize,
+    pub(crate) geometry: LibraryGeometry,
+    pub(crate) loc_mult: BigInt,
+    // `29^length`, one more than the largest number a page can spell
+    pub(crate) page_count: BigInt,
+}
+
+impl PageSpace {
+    pub(crate) fn new(length: usize, geometry: LibraryGeometry) -> Result<Self, BabelError> {
+        geometry.check()?;
+        let loc_mult = calculate_loc_mult(length)?;
+        Ok(PageSpace {
+            length,
+            geometry,
+            page_count: BigInt::from(29u32).pow(length as u32),
+            loc_mult,
+        })
+    }
+
+    // The space for `length` and `geometry`, computed once per process and shared by every codec
+    pub(crate) fn shared(length: usize, geometry: LibraryGeometry) -> Result<Arc<Self>, BabelError> {
+        type Key = (usize, [u32; 4]);
+        static SPACES: Mutex<BTreeMap<Key, Arc<PageSpace>>> = Mutex::new(BTreeMap::new());
+        let key = (length, [geometry.walls, geometry.shelves, geometry.volumes, geometry.pages]);
+        if let Some(space) = SPACES.lock().unwrap().get(&key) {
+            return Ok(Arc::clone(space));
+        }
+        // Computed unlocked so other lengths are not held up; a racing thread's copy is dropped
+        let space = Arc::new(PageSpace::new(length, geometry)?);
+        Ok(Arc::clone(SPACES.lock().unwrap().entry(key).or_insert(space)))
+    }
+}
+
+// The page spelling `x`, left-padded with `a` to the page length
+//
+// A number of 29^length or more, or a negative one, has no page; callers
+// range-check it first, so it is only ever spelled in full, never truncated.
+fn to_text(x: BigInt, space: &PageSpace) -> String {
+    let charset = CHARSET.as_bytes();
+    let digits = match x.to_biguint() {
+        Some(x) if x < *space.page_count.magnitude() => {
+            let mut digits = vec![0; space.length];
+            write_digits(&x, 29, &mut digits);
+            digits
+        }
+        _ => x.magnitude().to_radix_be(29),
+    };
+    digits.into_iter().map(|digit| charset[digit as usize] as char).collect()
+}
+
+// The page spelling `x`, or an error when it is negative or past the last page
+pub(crate) fn number_to_text(x: &BigInt, space: &PageSpace) -> Result<String, BabelError> {
+    let negative = x.sign() == Sign::Minus;
+    if negative || *x >= space.page_count {
+        return Err(BabelError::PageNumberOutOfRange { negative });
+    }
+    Ok(to_text(x.clone(), space))
+}
+
+// Join a base-36 key and its location in the `key:wall:shelf:volume:page` form,
+// each coordinate padded to its width in `geometry`
+pub(crate) fn format_address(key: &str, location: &Location, geometry: &LibraryGeometry) -> String {
+    let [wall, shelf, volume, page] = geometry.field_widths();
+    format!("{}:{:0wall$}:{:0shelf$}:{:0volume$}:{:0page$}", key,
+        location.wall, location.shelf, location.volume, location.page)
+}
+
+// Split an address into its base-36 key and location
+pub(crate) fn split_address(address: &str) -> Result<(&str, Location), BabelError> {
+    let malformed = || BabelError::MalformedAddress(address.to_string());
+    let parts: Vec<&str> = address.split(':').collect();
+    if parts.len() != 5 {
+        return Err(BabelError::WrongPartCount { address: address.to_string(), parts: parts.len() });
+    }
+    if parts[0].is_empty() {
+        return Err(malformed());
+    }
+
+    let location = Location {
+        wall: parts[1].parse().map_err(|_| malformed())?,
+        shelf: parts[2].parse().map_err(|_| malformed())?,
+        volume: parts[3].parse().map_err(|_| malformed())?,
+        page: parts[4].parse().map_err(|_| malformed())?,
+    };
+    Ok((parts[0], location))
+}
+
+// Whether the page at `address` reads back as `original`, padding aside
+//
+// A mismatch is only described at debug level; the caller reports the page.
+pub(crate) fn verify_page(original: &str, address: &Address, space: &PageSpace) -> bool {
+    let retrieved = match get_page(address, space) {
+        Ok(retrieved) => retrieved,
+        Err(e) => {
+            debug!("page at {} cannot be read back: {}", address, e);
+            return false;
+        }
+    };
+    let retrieved = retrieved.trim_end_matches(PAD_CHAR);
+    let original = original.trim_end_matches(PAD_CHAR);
+    if original == retrieved {
+        return true;
+    }
+
+    let first_difference = original.chars().zip(retrieved.chars()).position(|(a, b)| a != b);
+    debug!(
+        "page at {} reads back {} characters where {} were written, first differing at {:?}: \
+         written ...{:?}, read ...{:?}",
+        address,
+        retrieved.len(),
+        original.len(),
+        first_difference,
+        &original[original.len().saturating_sub(10)..],
+        &retrieved[retrieved.len().saturating_sub(10)..],
+    );
+    false
+}
+
+
+// An address that holds `search_str` at a random location
+//
+// Nothing here reads the page back; encode checks whole batches at once in
+// `verify_pages`, so each page is resolved at most once.
+pub(crate) fn search<R: Rng>(search_str: &str, space: &PageSpace, bias: LocationBias, rng: &mut R) -> Result<Address, BabelError> {
+    let found = search_str.chars().count();
+    if found != space.length {
+        return Err(BabelError::WrongPageLength { expected: space.length, found });
+    }
+
+    let location = Location::random(rng, &space.geometry, bias);
+    search_exact(search_str, space, &location)
+}
+
+// The number spelled by the page at `address`, with its location taken out of the key
+//
+// A corrupt key can leave a number that no page spells: a negative one would
+// decode to a page of all `a`s and one too big would spill past the page, so
+// both are errors, as is a location outside the geometry. Parsing the address
+// already ruled out the rest.
+pub(crate) fn page_number(address: &Address, space: &PageSpace) -> Result<BigInt, BabelError> {
+    if !address.location.is_within(&space.geometry) {
+        return Err(BabelError::LocationOutOfRange { address: address.to_string_in(&space.geometry), geometry: space.geometry });
+    }
+    let loc_int = address.location.to_loc_int(&space.geometry);
+    let number = &address.key - (loc_int * &space.loc_mult);
+    let negative = number.sign() == Sign::Minus;
+    if negative || number >= space.page_count {
+        return Err(BabelError::KeyOutOfRange { address: address.to_string_in(&space.geometry), negative });
+    }
+    Ok(number)
+}
+
+// The one address that holds `search_str` at `location`
+pub(crate) fn search_exact(search_str: &str, space: &PageSpace, location: &Location) -> Result<Address, BabelError> {
+    let loc_int = location.to_loc_int(&space.geometry);
+    let search_num = string_to_number(search_str)?;
+    Ok(Address { key: search_num + (loc_int * &space.loc_mult), location: *location })
+}
+
+// Pages resolved by `get_page` on this thread, for tests to count
+#[cfg(test)]
+thread_local! {
+    pub(crate) static GET_PAGE_CALLS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+// The page at `address`, or why no page is there
+pub(crate) fn get_page(address: &Address, space: &PageSpace) -> Result<String, BabelError> {
+    #[cfg(test)]
+    GET_PAGE_CALLS.with(|calls| calls.set(calls.get() + 1));
+    let result = to_text(page_number(address, space)?, space);
+    // page_number keeps the number below 29^length, so this only trips on a bug
+    if result.len() != space.length {
+        return Err(BabelError::KeyOutOfRange { address: address.to_string_in(&space.geometry), negative: false });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_lengths_past_u32_are_refused() {
+        let length = u32::MAX as usize + 1;
+        assert!(matches!(calculate_loc_mult(length), Err(BabelError::PageLengthOutOfRange(l)) if l == length));
+        assert!(matches!(PageSpace::shared(length, LibraryGeometry::default()), Err(BabelError::PageLengthOutOfRange(_))));
+        assert_eq!(calculate_loc_mult(2).unwrap(), BigInt::from(900));
+    }
+
+    #[test]
+    fn page_spaces_are_computed_once() {
+        let geometry = LibraryGeometry::default();
+        let first = PageSpace::shared(206, geometry).unwrap();
+        let second = PageSpace::shared(206, geometry).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first.loc_mult, calculate_loc_mult(206).unwrap());
+
+        let other = PageSpace::shared(206, LibraryGeometry::new(1, 1, 1, 1).unwrap()).unwrap();
+        assert!(!Arc::ptr_eq(&first, &other));
+    }
+
+    #[test]
+    fn keys_past_the_last_page_are_too_large() {
+        let space = PageSpace::new(5, LibraryGeometry::default()).unwrap();
+        let location = Location { wall: 3, shelf: 4, volume: 31, page: 409 };
+        let loc_int = location.to_loc_int(&space.geometry);
+        let address = Address { key: loc_int * &space.loc_mult + &space.page_count, location };
+        assert!(matches!(page_number(&address, &space), Err(BabelError::KeyOutOfRange { negative: false, .. })));
+    }
+
+    // An error quotes the address the way its archive writes it
+    #[test]
+    fn errors_quote_addresses_in_their_geometry() {
+        let geometry = LibraryGeometry::new(20, 20, 200, 2000).unwrap();
+        let space = PageSpace::new(5, geometry).unwrap();
+        let location = Location { wall: 1, shelf: 2, volume: 3, page: 4 };
+        let address = Address { key: location.to_loc_int(&geometry) * &space.loc_mult - 1, location };
+        match page_number(&address, &space) {
+            Err(BabelError::KeyOutOfRange { address: quoted, .. }) => assert!(quoted.ends_with(":01:02:003:0004"), "{}", quoted),
+            other => panic!("resolved a key below its location: {:?}", other),
+        }
+    }
+
+    // The keys either side of both ends of one location's range
+    #[test]
+    fn keys_resolve_only_within_their_location() {
+        let space = PageSpace::new(5, LibraryGeometry::default()).unwrap();
+        let location = Location { wall: 1, shelf: 2, volume: 3, page: 4 };
+        let base = location.to_loc_int(&space.geometry) * &space.loc_mult;
+        let at = |key: BigInt| get_page(&Address { key, location }, &space);
+
+        assert!(matches!(at(&base - 1), Err(BabelError::KeyOutOfRange { negative: true, .. })));
+        assert_eq!(at(base.clone()).unwrap(), "aaaaa");
+        assert_eq!(at(&base + &space.page_count - 1).unwrap(), ".....");
+        assert!(matches!(at(&base + &space.page_count), Err(BabelError::KeyOutOfRange { negative: false, .. })));
+    }
+
+    #[test]
+    fn only_page_characters_spell_a_number() {
+        // `b`, `,`, ` ` and `.` are digits 1, 26, 27 and 28
+        assert_eq!(string_to_number("b, .").unwrap(), BigInt::from(29u32.pow(3) + 26 * 29u32.pow(2) + 27 * 29 + 28));
+        match string_to_number("spaces, commas!") {
+            Err(BabelError::InvalidPageChar { position, found }) => assert_eq!((position, found), (14, '!')),
+            other => panic!("spelled a `!`: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::ToPrimitive;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::BabelError;
+
+/// Walls in a hexagon of libraryofbabel.info.
+pub const WALLS: u32 = 4;
+/// Shelves on each wall.
+pub const SHELVES: u32 = 5;
+/// Volumes on each shelf.
+pub const VOLUMES: u32 = 32;
+/// Pages in each volume.
+pub const PAGES: u32 = 410;
+
+/// How many walls, shelves, volumes and pages each hexagon holds.
+///
+/// The geometry decides how an address key splits into a page number and its
+/// location, so an archive must be decoded with the geometry it was encoded
+/// with. Written as `walls,shelves,volumes,pages`, for example `4,5,32,410`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LibraryGeometry {
+    pub walls: u32,
+    pub shelves: u32,
+    pub volumes: u32,
+    pub pages: u32,
+}
+
+impl Default for LibraryGeometry {
+    /// The layout used by libraryofbabel.info.
+    fn default() -> Self {
+        LibraryGeometry {
+            walls: WALLS,
+            shelves: SHELVES,
+            volumes: VOLUMES,
+            pages: PAGES,
+        }
+    }
+}
+
+impl LibraryGeometry {
+    /// A geometry with the given counts, each of which must be at least one.
+    pub fn new(walls: u32, shelves: u32, volumes: u32, pages: u32) -> Result<Self, BabelError> {
+        let geometry = LibraryGeometry { walls, shelves, volumes, pages };
+        geometry.check()?;
+        Ok(geometry)
+    }
+
+    // A hexagon with no walls, shelves, volumes or pages has no location to draw
+    pub(crate) fn check(&self) -> Result<(), BabelError> {
+        if [self.walls, self.shelves, self.volumes, self.pages].contains(&0) {
+            return Err(BabelError::InvalidGeometry(format!("{} has a count of zero", self)));
+        }
+        Ok(())
+    }
+
+    /// Digits each coordinate takes in an address, as `[wall, shelf, volume, page]`.
+    ///
+    /// Every coordinate is zero-padded to the width of its largest value, so for
+    /// the default geometry addresses always read `key:W:S:VV:PPP`.
+    pub fn field_widths(&self) -> [usize; 4] {
+        [self.walls, self.shelves, self.volumes, self.pages].map(|count| decimal_width(count) as usize)
+    }
+}
+
+impl fmt::Display for LibraryGeometry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.walls, self.shelves, self.volumes, self.pages)
+    }
+}
+
+impl FromStr for LibraryGeometry {
+    type Err = BabelError;
+
+    fn from_str(value: &str) -> Result<Self, BabelError> {
+        let invalid = || BabelError::InvalidGeometry(format!("{} is not four counts like 4,5,32,410", value));
+        let counts = value
+            .split(',')
+            .map(|count| count.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        match counts[..] {
+            [walls, shelves, volumes, pages] => LibraryGeometry::new(walls, shelves, volumes, pages),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// Decimal digits needed to write any index below `count`
+fn decimal_width(count: u32) -> u32 {
+    count.saturating_sub(1).max(1).ilog10() + 1
+}
+
+fn shift(width: u32) -> BigInt {
+    BigInt::from(10u32).pow(width)
+}
+
+/// How random locations are spread over a hexagon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LocationBias {
+    /// Every location equally likely.
+    #[default]
+    Uniform,
+    /// Each coordinate drawn from a triangular distribution peaking at zero,
+    /// so pages gather around wall 0, shelf 0 and the low volumes and pages.
+    /// The mean of each coordinate falls from about half its range to a third.
+    LowNumbered,
+}
+
+// A value below `count`, skewed toward zero by `bias`
+fn draw<R: Rng>(rng: &mut R, count: u32, bias: LocationBias) -> u32 {
+    match bias {
+        LocationBias::Uniform => rng.gen_range(0..count),
+        // The smaller of two uniform draws is `k` with probability falling linearly in `k`
+        LocationBias::LowNumbered => rng.gen_range(0..count).min(rng.gen_range(0..count)),
+    }
+}
+
+/// The coordinates of a page within a hexagon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Location {
+    pub wall: u32,
+    pub shelf: u32,
+    pub volume: u32,
+    pub page: u32,
+}
+
+impl Location {
+    pub(crate) fn random<R: Rng>(rng: &mut R, geometry: &LibraryGeometry, bias: LocationBias) -> Self {
+        Location {
+            wall: draw(rng, geometry.walls, bias),
+            shelf: draw(rng, geometry.shelves, bias),
+            volume: draw(rng, geometry.volumes, bias),
+            page: draw(rng, geometry.pages, bias),
+        }
+    }
+
+    /// Whether every coordinate is below its count in `geometry`.
+    pub fn is_within(&self, geometry: &LibraryGeometry) -> bool {
+        self.wall < geometry.walls
+            && self.shelf < geometry.shelves
+            && self.volume < geometry.volumes
+            && self.page < geometry.pages
+    }
+
+    /// The integer the location contributes to an address key.
+    ///
+    /// Coordinates are laid out as decimal digits, page first and wall last,
+    /// each zero-padded to the width of its largest value in `geometry`. For
+    /// the default geometry that is `PPPVVSW`.
+    pub fn to_loc_int(&self, geometry: &LibraryGeometry) -> BigInt {
+        let mut loc_int = BigInt::from(self.page);
+        loc_int = loc_int * shift(decimal_width(geometry.volumes)) + self.volume;
+        loc_int = loc_int * shift(decimal_width(geometry.shelves)) + self.shelf;
+        loc_int * shift(decimal_width(geometry.walls)) + self.wall
+    }
+
+    /// Split a location integer back into coordinates; the inverse of [`Location::to_loc_int`].
+    ///
+    /// A negative integer, or one with a coordinate outside `geometry`, is no
+    /// location's and is an error rather than being read as some other one.
+    pub fn from_loc_int(loc_int: BigInt, geometry: &LibraryGeometry) -> Result<Location, BabelError> {
+        let out_of_range = || BabelError::LocationOutOfRange { address: loc_int.to_string(), geometry: *geometry };
+        let coordinate = |value: BigInt| value.to_u32().ok_or_else(out_of_range);
+        let (rest, wall) = loc_int.div_rem(&shift(decimal_width(geometry.walls)));
+        let (rest, shelf) = rest.div_rem(&shift(decimal_width(geometry.shelves)));
+        let (page, volume) = rest.div_rem(&shift(decimal_width(geometry.volumes)));
+
+        let location = Location {
+            wall: coordinate(wall)?,
+            shelf: coordinate(shelf)?,
+            volume: coordinate(volume)?,
+            page: coordinate(page)?,
+        };
+        if !location.is_within(geometry) {
+            return Err(out_of_range());
+        }
+        Ok(location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn every_default_location_survives_its_loc_int() {
+        let geometry = LibraryGeometry::default();
+        for wall in 0..WALLS {
+            for shelf in 0..SHELVES {
+                for volume in 0..VOLUMES {
+                    for page in 0..PAGES {
+                        let location = Location { wall, shelf, volume, page };
+                        assert_eq!(Location::from_loc_int(location.to_loc_int(&geometry), &geometry).unwrap(), location);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn field_widths_fit_the_largest_index() {
+        assert_eq!(LibraryGeometry::default().field_widths(), [1, 1, 2, 3]);
+        assert_eq!(LibraryGeometry::new(10, 11, 100, 1).unwrap().field_widths(), [1, 2, 2, 1]);
+        assert_eq!(LibraryGeometry::new(1001, 1000, 2, 70000).unwrap().field_widths(), [4, 3, 1, 5]);
+    }
+
+    #[test]
+    fn corner_locations_survive_wide_fields() {
+        let geometry = LibraryGeometry::new(1001, 1000, 2, 70000).unwrap();
+        for location in [
+            Location { wall: 0, shelf: 0, volume: 0, page: 0 },
+            Location { wall: 1000, shelf: 999, volume: 1, page: 69999 },
+            Location { wall: 9, shelf: 10, volume: 0, page: 100 },
+        ] {
+            assert_eq!(Location::from_loc_int(location.to_loc_int(&geometry), &geometry).unwrap(), location);
+        }
+    }
+
+    #[test]
+    fn loc_ints_of_no_location_are_refused() {
+        let geometry = LibraryGeometry::default();
+        let past_u32 = BigInt::from(u64::from(u32::MAX) + 1) * 10_000;
+        for loc_int in [past_u32, BigInt::from(-1), BigInt::from(4100000), BigInt::from(4)] {
+            match Location::from_loc_int(loc_int.clone(), &geometry) {
+                Err(BabelError::LocationOutOfRange { address, .. }) => assert_eq!(address, loc_int.to_string()),
+                other => panic!("expected {} to be refused, got {:?}", loc_int, other),
+            }
+        }
+    }
+
+    // Uniform pages average 204.5 and low-numbered ones about 136
+    #[test]
+    fn low_numbered_locations_average_lower() {
+        let geometry = LibraryGeometry::default();
+        let mean_page = |bias| {
+            let mut rng = StdRng::seed_from_u64(244);
+            (0..10_000).map(|_| Location::random(&mut rng, &geometry, bias).page as f64).sum::<f64>() / 10_000.0
+        };
+        let uniform = mean_page(LocationBias::Uniform);
+        let low = mean_page(LocationBias::LowNumbered);
+        assert!((195.0..215.0).contains(&uniform), "uniform mean {}", uniform);
+        assert!((126.0..146.0).contains(&low), "low-numbered mean {}", low);
+    }
+}
@@ -1,391 +1,1054 @@
-use iter::IntoParallelRefIterator;
-use iter::ParallelIterator;
-use num_traits::Num;
-use rand::Rng;
-use rayon::*;
-use slice::ParallelSlice;
+mod progress_bar;
+
+use babel_encoding::babel::PAD_CHAR;
+use babel_encoding::{
+    Alphabet, Archive, ArchiveFormat, ArchiveHeader, BabelCodec, BabelError, Compression, LibraryGeometry, LocationBias, OnInvalidChar,
+    PadStrategy, PageLayout, PageLength, PayloadMode, Threads, VerifyMode, FORMAT_VERSION,
+};
+use clap::error::ErrorKind;
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use progress_bar::{PageBar, Started};
+use rand::RngCore;
 use std::env;
 use std::fs;
-use std::io::BufWriter;
+use std::io::{self, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::num::NonZeroUsize;
+use std::ops::Range;
 use std::path::Path;
-use std::io::{Read, Write};
-use num_bigint::BigInt;
-use num_traits::{Zero, ToPrimitive};
-use num_integer::Integer;
-
-const LENGTH_OF_PAGE: usize = 3239;
-const PAD_CHAR: char = '.';
-
-// Calculate powers for location multiplier
-fn calculate_loc_mult(length: u32) -> BigInt {
-    let thirty = BigInt::from(30u32);
-    thirty.pow(length)
-}
-
-fn bytes_to_babel_text(bytes: &[u8]) -> String {
-    // Process conversion in parallel for large inputs
-    if bytes.len() > 1024 {  // Only parallelize for larger inputs
-        bytes.par_iter()
-            .map(|&byte| {
-                let first = byte / 26;
-                let second = byte % 26;
-                format!("{}{}", 
-                    char::from(b'a' + first),
-                    char::from(b'a' + second))
-            })
-            .collect()
-    } else {
-        bytes.iter()
-            .map(|&byte| {
-                let first = byte / 26;
-                let second = byte % 26;
-                format!("{}{}", 
-                    char::from(b'a' + first),
-                    char::from(b'a' + second))
-            })
-            .collect()
-    }
-}
-
-fn babel_text_to_bytes(text: &str) -> Vec<u8> {
-    let text = text.trim_end_matches(PAD_CHAR);
-    let chars: Vec<char> = text.chars().collect();
-    
-    // Process conversion in parallel for large inputs
-    if chars.len() > 2048 {  // Only parallelize for larger inputs
-        chars.par_chunks(2)
-            .filter(|chunk| chunk.len() == 2)
-            .map(|chunk| {
-                let first = (chunk[0] as u8 - b'a') * 26;
-                let second = chunk[1] as u8 - b'a';
-                first + second
-            })
-            .collect()
-    } else {
-        let mut bytes = Vec::with_capacity(chars.len() / 2);
-        for chunk in chars.chunks(2) {
-            if chunk.len() == 2 {
-                let first = (chunk[0] as u8 - b'a') * 26;
-                let second = chunk[1] as u8 - b'a';
-                bytes.push(first + second);
-            }
+use std::process::ExitCode;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Encode files as Library of Babel page addresses and back.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Print each step of the encode or decode as it happens; twice for per-page details
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Hide warnings and the page progress bar, which is otherwise drawn when stderr is a terminal
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Search and decode pages on this many threads; 1 runs everything sequentially
+    #[arg(short, long, global = true, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+    /// Read the passphrase for encrypted archives from this file instead of $BABEL_PASSPHRASE
+    #[arg(long, global = true, value_name = "FILE")]
+    passphrase_file: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encode a file into a .babel archive
+    Encode(EncodeArgs),
+    /// Encode every file under a directory into one .babel archive; decode restores the tree
+    Archive(EncodeArgs),
+    /// Rebuild the original file from an archive
+    Decode {
+        /// Archive to decode, or `-` for stdin
+        input: String,
+        /// File to write, or `-` for stdout (the default when reading stdin)
+        output: Option<String>,
+        /// Output path, as an alternative to the second positional argument
+        #[arg(short = 'o', long = "output", value_name = "FILE", conflicts_with = "output")]
+        output_flag: Option<String>,
+        /// Warn about pages, or a file, that fail their checksum instead of stopping
+        #[arg(long)]
+        keep_going: bool,
+        /// Read the input as a bare address list, taking the header from this archive
+        #[arg(long, value_name = "FILE", conflicts_with = "size")]
+        header: Option<String>,
+        /// Read the input as a bare address list of a file this many bytes long
+        #[arg(long, value_name = "BYTES")]
+        size: Option<u64>,
+        /// Extension recorded for --size
+        #[arg(long, value_name = "EXT", requires = "size", default_value = "")]
+        extension: String,
+        /// Decode an archive whose size line is missing, keeping every page in full
+        #[arg(long)]
+        best_effort: bool,
+        /// Fail at the first decoded byte that is not printable ASCII, a tab or a line break
+        #[arg(long)]
+        strict_ascii: bool,
+        /// Decode only bytes A..B of the file (either end may be left off), to stdout by default
+        #[arg(long, value_name = "A..B", value_parser = parse_range, conflicts_with = "best_effort")]
+        bytes: Option<Range<u64>>,
+        /// Decode only pages A..B, counting from 0, to stdout by default
+        #[arg(long, value_name = "A..B", value_parser = parse_range, conflicts_with_all = ["best_effort", "bytes"])]
+        pages: Option<Range<u64>>,
+    },
+    /// Decode an archive and check it against its header, or against the original file
+    ///
+    /// Exits with 1 if they differ, printing the offset of the first differing byte.
+    Verify {
+        archive: String,
+        original: Option<String>,
+    },
+    /// Print an archive's header
+    Stats {
+        archive: String,
+    },
+    /// Print the byte offset of the first occurrence of some text
+    Find {
+        archive: String,
+        text: String,
+    },
+    /// Print the address of a page that starts with some text, padded out with periods
+    Locate {
+        /// Lowercase letters, commas, spaces and periods, at most one page long
+        phrase: String,
+        /// Draw the location from this seed, so the phrase always gives the same address
+        #[arg(long, value_name = "N")]
+        seed: Option<u64>,
+    },
+    /// Print the text of the page at an address
+    Lookup {
+        address: String,
+        /// Leave off the periods padding out the end of the page
+        #[arg(long, overrides_with = "raw")]
+        trim: bool,
+        /// Print the page in full, padding included (the default; overrides an earlier --trim)
+        #[arg(long, overrides_with = "trim")]
+        raw: bool,
+    },
+    /// Spell a file's bytes as page text in the chosen alphabet, without finding any pages
+    Text {
+        /// File to spell, or `-` for stdin
+        input: String,
+        /// Where to write the text, or `-` for stdout (the default)
+        output: Option<String>,
+        /// How bytes are spelled
+        #[arg(long, value_enum, default_value_t = ByteEncodingArg::Letters26)]
+        byte_encoding: ByteEncodingArg,
+    },
+    /// Turn text from `text` back into the bytes it spells; trailing periods are padding
+    Untext {
+        /// Text to read, or `-` for stdin
+        input: String,
+        /// Where to write the bytes, or `-` for stdout (the default)
+        output: Option<String>,
+        /// How bytes are spelled
+        #[arg(long, value_enum, default_value_t = ByteEncodingArg::Letters26)]
+        byte_encoding: ByteEncodingArg,
+        /// Keep only the first BYTES bytes, for text padded with something other than periods
+        #[arg(long, value_name = "BYTES")]
+        size: Option<u64>,
+    },
+    /// Show the other pages of an address's book and the other books on its shelf
+    Explore {
+        address: String,
+        /// Print every address in full instead of shortening the keys
+        #[arg(long)]
+        full: bool,
+    },
+    /// Rewrite an archive in the current format version without decoding it
+    Migrate {
+        input: String,
+        output: String,
+    },
+    /// Check an archive's header and addresses without decoding any pages
+    Doctor {
+        archive: String,
+    },
+    /// Measure encode and decode throughput on random data in memory
+    Benchmark {
+        /// Bytes of random data to encode
+        #[arg(long, default_value_t = 100_000)]
+        size: usize,
+        /// Untimed rounds run first
+        #[arg(long, default_value_t = 1)]
+        warmup: usize,
+        /// Timed rounds averaged into the result
+        #[arg(long, default_value_t = 3)]
+        iterations: usize,
+        /// Use one thread per physical core instead of per logical CPU
+        #[arg(long)]
+        threads_auto_detect_physical: bool,
+    },
+}
+
+#[derive(Args)]
+struct EncodeArgs {
+    /// File to encode (a directory for `archive`), or `-` for stdin
+    input: String,
+    /// Archive to write, or `-` for stdout (the default when reading stdin)
+    output: Option<String>,
+    /// Output path, as an alternative to the second positional argument
+    #[arg(short = 'o', long = "output", value_name = "FILE", conflicts_with = "output")]
+    output_flag: Option<String>,
+    /// How the archive is laid out
+    #[arg(long, value_enum, conflicts_with_all = ["compact_json", "compress_index", "self_contained"])]
+    format: Option<FormatArg>,
+    /// Write a single JSON object with a base64 address index
+    #[arg(long)]
+    compact_json: bool,
+    /// Deflate the address list, leaving the header readable as text
+    #[arg(short = 'z', long, conflicts_with = "compact_json")]
+    compress_index: bool,
+    /// Write only addresses, spelling the header on the leading pages
+    #[arg(long, conflicts_with_all = ["compact_json", "compress_index", "split_size"])]
+    self_contained: bool,
+    /// Skip the page checks, for trusted bulk encodes
+    #[arg(short = 'n', long)]
+    no_verify: bool,
+    /// Check the whole archive decodes back to the input instead of checking pages
+    #[arg(long, conflicts_with = "no_verify")]
+    verify_bytes: bool,
+    /// Space-separated addresses per line
+    #[arg(short = 'p', long, value_name = "N")]
+    pages_per_line: Option<usize>,
+    /// Record the modification time and unix mode, restored on decode
+    #[arg(short = 'm', long)]
+    preserve_metadata: bool,
+    /// Free-text note stored in the header
+    #[arg(long, value_name = "TEXT")]
+    note: Option<String>,
+    /// Extension recorded in the header when reading stdin or writing stdout
+    #[arg(long, value_name = "EXT")]
+    extension: Option<String>,
+    /// Write the addresses to part files of at most this many bytes
+    #[arg(long, value_name = "BYTES")]
+    split_size: Option<u64>,
+    /// Store a checksum of every page so decoding stops at the first corrupt one
+    #[arg(short = 'c', long)]
+    page_checksums: bool,
+    /// Store a CRC-32 of the whole file, checked once decoding has reassembled it
+    #[arg(long)]
+    checksum: bool,
+    /// Approximate memory, in megabytes, to keep in flight while encoding; sets
+    /// the pages searched at once and the threads searching them, and needs an
+    /// unsplit, unencrypted text archive
+    #[arg(long, value_name = "MB")]
+    memory_budget: Option<usize>,
+    /// Write plain text straight onto pages
+    #[arg(short = 't', long)]
+    text: bool,
+    /// What text mode does with characters that cannot appear on a page
+    #[arg(long, value_enum, default_value_t = InvalidCharArg::Error)]
+    on_invalid_char: InvalidCharArg,
+    /// What fills the rest of the final page
+    #[arg(long, value_enum, default_value_t = PadArg::Repeat)]
+    pad_strategy: PadArg,
+    /// Character `--pad-strategy repeat` fills with instead of a period; any page character
+    #[arg(long, value_name = "C")]
+    pad_char: Option<char>,
+    /// Encrypt the input with the passphrase from --passphrase-file or $BABEL_PASSPHRASE
+    #[arg(long, conflicts_with = "text")]
+    encrypt: bool,
+    /// How bytes are spelled on pages; recorded in the header, so decode needs no flag
+    #[arg(long, value_enum, default_value_t = ByteEncodingArg::Letters26, conflicts_with = "text")]
+    byte_encoding: ByteEncodingArg,
+    /// How page locations are drawn
+    #[arg(long, value_enum, default_value_t = BiasArg::Uniform)]
+    location_bias: BiasArg,
+    /// Compress the input before encoding it; decode decompresses automatically
+    #[arg(long, value_enum, default_value_t = CompressArg::None, conflicts_with = "text")]
+    compress: CompressArg,
+    /// Draw locations from this seed, so the same input always gives the same archive
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+    /// Add filler pages until the archive holds exactly this many
+    #[arg(long, value_name = "N")]
+    pad_to_pages: Option<usize>,
+    /// Read the input through this many file handles at once
+    #[arg(long, value_name = "HANDLES")]
+    parallel_read: Option<usize>,
+    /// Search with one thread per physical core instead of per logical CPU
+    #[arg(long)]
+    threads_auto_detect_physical: bool,
+    /// Write a JSON summary of the encode to this file, or to stderr when no file is given
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = STDIO)]
+    stats_json: Option<String>,
+    /// Characters per page, recorded in the header so decoding follows it
+    #[arg(long, value_name = "CHARS", value_parser = parse_page_length)]
+    page_length: Option<PageLength>,
+    /// Walls, shelves, volumes and pages per hexagon, as `4,5,32,410`; recorded in the header
+    #[arg(long, value_name = "W,S,V,P")]
+    geometry: Option<LibraryGeometry>,
+    /// Print the page count and archive size an encode would give, and write nothing
+    #[arg(long, conflicts_with = "stats_json")]
+    dry_run: bool,
+    /// With --dry-run, search one sample page to estimate how long the encode would take
+    #[arg(long, requires = "dry_run")]
+    time_estimate: bool,
+    /// Checkpoint finished pages to `<output>.partial` and carry on from one left by a killed encode
+    #[arg(long, conflicts_with_all = ["encrypt", "dry_run"])]
+    resume: bool,
+}
+
+// Any length a page can have; `EncodeArgs::codec` checks it against the alphabet's blocks
+fn parse_page_length(value: &str) -> Result<PageLength, String> {
+    let length: usize = value.parse().map_err(|e| format!("{}", e))?;
+    PageLength::new(length).map_err(|e| e.to_string())
+}
+
+// Bytes held by pages `pages`, cut short at the last page
+fn page_bytes(layout: &PageLayout, pages: Range<u64>) -> Range<u64> {
+    let count = layout.page_count() as u64;
+    let start_of = |page: u64| match layout.page(page.min(count) as usize) {
+        Some((offset, _)) => offset,
+        None => layout.pages().last().map_or(0, |(offset, len)| offset + len),
+    };
+    start_of(pages.start)..start_of(pages.end)
+}
+
+// `A..B`, `A..` or `..B`; an open end runs to the start or end of the file
+fn parse_range(value: &str) -> Result<Range<u64>, String> {
+    let (start, end) = value.split_once("..").ok_or_else(|| format!("{} is not a range like 0..4096", value))?;
+    let bound = |text: &str, open: u64| match text {
+        "" => Ok(open),
+        _ => text.parse::<u64>().map_err(|e| format!("{}: {}", text, e)),
+    };
+    let range = bound(start, 0)?..bound(end, u64::MAX)?;
+    if range.start > range.end {
+        return Err(format!("{} ends before it starts", value));
+    }
+    Ok(range)
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ByteEncodingArg {
+    /// Two letters a..z per byte, base 26
+    Letters26,
+    /// Two letters a..p per byte, as hex
+    Hex16,
+    /// Five of the 29 page characters per three bytes; a sixth fewer pages
+    Base29,
+}
+
+impl From<ByteEncodingArg> for Alphabet {
+    fn from(arg: ByteEncodingArg) -> Self {
+        match arg {
+            ByteEncodingArg::Letters26 => Alphabet::letters26(),
+            ByteEncodingArg::Hex16 => Alphabet::hex16(),
+            ByteEncodingArg::Base29 => Alphabet::base29(),
         }
-        bytes
-    }
-}
-
-fn string_to_number(input: &str) -> BigInt {
-    let digits: Vec<char> = "abcdefghijklmnopqrstuvwxyz, .".chars().collect();
-    let base = BigInt::from(29u32);
-    let mut result = BigInt::zero();
-    
-    for c in input.chars() {
-        if let Some(pos) = digits.iter().position(|&x| x == c) {
-            result = result * &base + BigInt::from(pos);
-        }
-    }
-    result
-}
-
-fn int_to_base36(mut x: BigInt) -> String {
-    if x.is_zero() {
-        return "0".to_string();
-    }
-
-    let digits: Vec<char> = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ".chars().collect();
-    let mut result = Vec::new();
-    let thirty_six = BigInt::from(36u32);
-    let zero = BigInt::zero();
-    
-    while x > zero {
-        let (new_x, remainder) = x.div_rem(&thirty_six);
-        result.push(digits[remainder.to_u32().unwrap_or(0) as usize]);
-        x = new_x;
-    }
-    
-    result.into_iter().rev().collect()
-}
-
-fn to_text(mut x: BigInt) -> String {
-    let digits: Vec<char> = "abcdefghijklmnopqrstuvwxyz, .".chars().collect();
-    let mut result = Vec::new();
-    let twenty_nine = BigInt::from(29u32);
-    
-    if x.is_zero() {
-        return "a".to_string();
-    }
-    
-    // Convert number to base-29 digits
-    while x > Zero::zero() {
-        let (new_x, remainder) = x.div_rem(&twenty_nine);
-        result.push(digits[remainder.to_usize().unwrap_or(0)]);
-        x = new_x;
-    }
-    result.reverse();
-    
-    // Convert to string
-    let mut text: String = result.into_iter().collect();
-    
-    // Left-pad with 'a' if we're short
-    if text.len() < LENGTH_OF_PAGE {
-        let padding = "a".repeat(LENGTH_OF_PAGE - text.len());
-        text = format!("{}{}", padding, text);
-    }
-    
-    text
-}
-
-// Verify page retrieval
-fn verify_page(original: &str, address: &str) -> bool {
-    let retrieved = get_page(address);
-    let retrieved = retrieved.trim_end_matches(PAD_CHAR);
-    let original_trimmed = original.trim_end_matches(PAD_CHAR);
-    
-    if original_trimmed.len() != retrieved.len() {
-        println!("Length mismatch after trimming!");
-        println!("Original length: {}", original_trimmed.len());
-        println!("Retrieved length: {}", retrieved.len());
-        println!("Original last 10 chars: {:?}", original_trimmed.chars().rev().take(10).collect::<Vec<_>>());
-        println!("Retrieved last 10 chars: {:?}", retrieved.chars().rev().take(10).collect::<Vec<_>>());
-        
-        // If lengths differ, print the first differing position
-        let orig_chars: Vec<char> = original_trimmed.chars().collect();
-        let retr_chars: Vec<char> = retrieved.chars().collect();
-        for i in 0..std::cmp::min(orig_chars.len(), retr_chars.len()) {
-            if orig_chars[i] != retr_chars[i] {
-                println!("First difference at position {}", i);
-                println!("Original char: {:?}", orig_chars[i]);
-                println!("Retrieved char: {:?}", retr_chars[i]);
-                break;
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PadArg {
+    /// Repeated periods
+    Repeat,
+    /// Random page characters
+    Random,
+    /// The alphabet's zero symbol
+    Zero,
+}
+
+impl From<PadArg> for PadStrategy {
+    fn from(arg: PadArg) -> Self {
+        match arg {
+            PadArg::Repeat => PadStrategy::default(),
+            PadArg::Random => PadStrategy::RandomAlphabet,
+            PadArg::Zero => PadStrategy::Zero,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FormatArg {
+    /// Header lines followed by address lines
+    Text,
+    /// One JSON object with the header fields and an `addresses` array
+    Json,
+    /// One JSON object with a base64 address index (same as -j)
+    CompactJson,
+    /// Text header with a deflated address list (same as -z)
+    CompressedIndex,
+    /// Address lines only (same as --self-contained)
+    SelfContained,
+}
+
+impl From<FormatArg> for ArchiveFormat {
+    fn from(arg: FormatArg) -> Self {
+        match arg {
+            FormatArg::Text => ArchiveFormat::Text,
+            FormatArg::Json => ArchiveFormat::Json,
+            FormatArg::CompactJson => ArchiveFormat::CompactJson,
+            FormatArg::CompressedIndex => ArchiveFormat::CompressedIndex,
+            FormatArg::SelfContained => ArchiveFormat::SelfContained,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CompressArg {
+    None,
+    Gzip,
+    /// Needs the `zstd` feature
+    Zstd,
+}
+
+impl From<CompressArg> for Compression {
+    fn from(arg: CompressArg) -> Self {
+        match arg {
+            CompressArg::None => Compression::None,
+            CompressArg::Gzip => Compression::Gzip,
+            CompressArg::Zstd => Compression::Zstd,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BiasArg {
+    /// Anywhere in the hexagon
+    Uniform,
+    /// Mostly low walls, shelves, volumes and pages
+    Low,
+}
+
+impl From<BiasArg> for LocationBias {
+    fn from(arg: BiasArg) -> Self {
+        match arg {
+            BiasArg::Uniform => LocationBias::Uniform,
+            BiasArg::Low => LocationBias::LowNumbered,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum InvalidCharArg {
+    Error,
+    Skip,
+    Map,
+}
+
+impl From<InvalidCharArg> for OnInvalidChar {
+    fn from(arg: InvalidCharArg) -> Self {
+        match arg {
+            InvalidCharArg::Error => OnInvalidChar::Error,
+            InvalidCharArg::Skip => OnInvalidChar::SkipWithWarning,
+            InvalidCharArg::Map => OnInvalidChar::MapToClosest,
+        }
+    }
+}
+
+impl EncodeArgs {
+    fn pad_strategy(&self) -> PadStrategy {
+        match (self.pad_strategy, self.pad_char) {
+            (PadArg::Repeat, Some(c)) => PadStrategy::Repeat(c),
+            (_, Some(_)) => usage_error("--pad-char only applies to --pad-strategy repeat"),
+            (strategy, None) => strategy.into(),
+        }
+    }
+
+    fn codec(&self, codec: BabelCodec) -> BabelCodec {
+        let mut codec = codec
+            .with_preserve_metadata(self.preserve_metadata)
+            .with_split_size(self.split_size)
+            .with_on_invalid_char(self.on_invalid_char.into())
+            .with_pad_strategy(self.pad_strategy())
+            .with_page_checksums(self.page_checksums)
+            .with_checksum(self.checksum)
+            .with_memory_budget(self.memory_budget.map(|mb| {
+                mb.checked_mul(1024 * 1024).unwrap_or_else(|| usage_error("--memory-budget is too large"))
+            }))
+            .with_parallel_read(self.parallel_read)
+            .with_pad_to_pages(self.pad_to_pages)
+            .with_location_bias(self.location_bias.into())
+            .with_alphabet(self.byte_encoding.into())
+            .with_seed(self.seed)
+            .with_resume(self.resume)
+            .with_compression(self.compress.into());
+        if !self.encrypt {
+            codec = codec.with_passphrase(None);
+        }
+        if let Some(format) = self.format {
+            codec = codec.with_format(format.into());
+        }
+        if self.compact_json {
+            codec = codec.with_format(ArchiveFormat::CompactJson);
+        }
+        if self.compress_index {
+            codec = codec.with_format(ArchiveFormat::CompressedIndex);
+        }
+        if self.self_contained {
+            codec = codec.with_format(ArchiveFormat::SelfContained);
+        }
+        if self.no_verify {
+            codec = codec.with_verify_mode(VerifyMode::None);
+        }
+        if self.verify_bytes {
+            codec = codec.with_verify_mode(VerifyMode::FullBytes);
+        }
+        if let Some(n) = self.pages_per_line {
+            codec = codec.with_pages_per_line(n);
+        }
+        if let Some(note) = &self.note {
+            codec = codec.with_note(note.as_str());
+        }
+        if self.text {
+            codec = codec.with_payload_mode(PayloadMode::Text);
+        }
+        if let Some(length) = self.page_length {
+            // Only whole blocks, so every page holds whole bytes; text mode has one character per byte
+            let alphabet = codec.alphabet();
+            if !self.text && !length.holds_whole_blocks(alphabet) {
+                usage_error(&format!(
+                    "--page-length {} does not hold whole blocks; {} spells bytes in blocks of {} characters",
+                    length.get(), alphabet.id(), alphabet.block_chars(),
+                ));
             }
+            codec = codec.with_page_length(length);
+        }
+        if let Some(geometry) = self.geometry {
+            codec = codec.with_geometry(geometry);
         }
-        return false;
+        codec
+    }
+}
+
+// Only the header is read, so a compressed index is never inflated
+fn print_stats(header: &ArchiveHeader, codec: &BabelCodec) {
+    println!("Format version: {}", header.version());
+    if let Some(name) = header.name() {
+        println!("Name: {}", name);
     }
-    
-    if original_trimmed != retrieved {
-        println!("Content mismatch after trimming!");
-        println!("Original (trimmed) [{} chars]: {}", original_trimmed.len(), original_trimmed);
-        println!("Retrieved [{} chars]: {}", retrieved.len(), retrieved);
-        println!("Address: {}", address);
-        false
+    println!("Extension: {}", header.extension());
+    if header.compression() == Compression::None {
+        println!("Original size: {} bytes", header.size());
     } else {
-        true
-    }
-}
-
-
-fn search(search_str: &str) -> String {
-    assert_eq!(search_str.len(), LENGTH_OF_PAGE, 
-              "Search string must be exactly {} characters", LENGTH_OF_PAGE);
-    
-    let mut rng = rand::thread_rng();
-    let wall = rng.gen_range(0..4).to_string();
-    let shelf = rng.gen_range(0..5).to_string();
-    let volume = format!("{:02}", rng.gen_range(0..32));
-    let page = format!("{:03}", rng.gen_range(0..410));
-
-    let loc_str = format!("{}{}{}{}", page, volume, shelf, wall);
-    let loc_int = BigInt::parse_bytes(loc_str.as_bytes(), 10).unwrap();
-    let loc_mult = calculate_loc_mult(LENGTH_OF_PAGE as u32);
-    
-    let search_num = string_to_number(search_str);
-    let hex_addr = int_to_base36(search_num + (loc_int * loc_mult));
-    let address = format!("{}:{}:{}:{}:{}", hex_addr, wall, shelf, volume, page);
-    
-    // Verify the page can be correctly retrieved
-    if !verify_page(search_str, &address) {
-        panic!("Page verification failed during search!");
-    }
-    
-    address
-}
-
-fn encode_file(input_path: &str, output_path: Option<&str>) -> std::io::Result<()> {
-    println!("Reading input file...");
-    let mut file = fs::File::open(input_path)?;
-    let mut contents = Vec::new();
-    file.read_to_end(&mut contents)?;
-    
-    let extension = Path::new(input_path)
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or("");
-    
-    println!("Converting to babel text...");
-    let babel_text = bytes_to_babel_text(&contents);
-    
-    // Debug: Verify conversion is working
-    println!("Verifying initial conversion...");
-    let test_bytes = babel_text_to_bytes(&babel_text);
-    if test_bytes != contents {
-        panic!("Initial conversion verification failed!");
-    }
-    
-    println!("Splitting into pages...");
-    let chunks: Vec<String> = babel_text
-        .chars()
-        .collect::<Vec<char>>()
-        .chunks(LENGTH_OF_PAGE)
-        .map(|c| {
-            let chunk_str: String = c.iter().collect();
-            if chunk_str.len() < LENGTH_OF_PAGE {
-                format!("{}{}", chunk_str, PAD_CHAR.to_string().repeat(LENGTH_OF_PAGE - chunk_str.len()))
-            } else {
-                chunk_str
-            }
-        })
-        .collect();
-
-    println!("Finding locations for {} pages in parallel...", chunks.len());
-    let locations: Vec<(String, String)> = chunks.par_iter()
-        .map(|chunk| {
-            assert_eq!(chunk.len(), LENGTH_OF_PAGE, 
-                      "Chunk length {} != {}", chunk.len(), LENGTH_OF_PAGE);
-            let location = search(chunk);
-            (chunk.clone(), location)
-        })
-        .collect();
-
-    println!("Verifying all pages in parallel...");
-    let verification_failed = locations.par_iter()
-        .any(|(original, location)| !verify_page(original, location));
-
-    if verification_failed {
-        panic!("Page verification failed!");
-    }
-
-    let output_path = match output_path {
-        Some(path) => path.to_string(),
-        None => {
-            let mut path = Path::new(input_path).to_path_buf();
-            path.set_extension("babel");
-            path.to_string_lossy().to_string()
+        println!("Compressed size: {} bytes", header.size());
+        println!("Compression: {}", header.compression().name());
+    }
+    if header.is_encrypted() {
+        println!("Encrypted: yes (ChaCha20-Poly1305, Argon2id key)");
+    }
+    println!("Pages: {}", codec.layout(header).page_count());
+    if let Some(length) = header.page_length() {
+        println!("Page length: {}", length);
+    }
+    if let Some(geometry) = header.geometry() {
+        println!("Geometry: {} walls, {} shelves, {} volumes, {} pages",
+            geometry.walls, geometry.shelves, geometry.volumes, geometry.pages);
+    }
+    if !header.parts().is_empty() {
+        println!("Parts: {}", header.parts().len());
+    }
+    if !header.files().is_empty() {
+        println!("Files: {}", header.files().len());
+        for file in header.files() {
+            println!("  {} ({} bytes)", file.path, file.size);
         }
-    };
+    }
+    println!("Alphabet: {}", header.alphabet());
+    if header.payload() == PayloadMode::Text {
+        println!("Payload: text");
+    }
+    if let Some(pages) = header.pages() {
+        println!("Real pages: {} (the rest are filler)", pages);
+    }
+    if let Some(crc) = header.crc32() {
+        println!("CRC-32: {:08x}", crc);
+    }
+    if !header.page_crcs().is_empty() {
+        println!("Page checksums: yes");
+    }
+    if let Some(encoder) = header.encoder() {
+        println!("Encoder: {}", encoder);
+    }
+    if let Some(created) = header.created() {
+        println!("Created: {}", created);
+    }
+    if let Some(note) = header.note() {
+        println!("Note: {}", note);
+    }
+    if let Some(modified) = header.modified() {
+        println!("Modified: {}", modified);
+    }
+    if let Some(mode) = header.mode() {
+        println!("Mode: {:o}", mode);
+    }
+}
+
+// Long keys are cut to their first few characters unless `full` is set
+fn short_address(address: &str, full: bool) -> String {
+    const KEY_CHARS: usize = 12;
+    match address.split_once(':') {
+        Some((key, location)) if !full && key.len() > KEY_CHARS => format!("{}...:{}", &key[..KEY_CHARS], location),
+        _ => address.to_string(),
+    }
+}
+
+// Accept the older `--encode input` style by mapping it onto the subcommand
+fn legacy_args() -> Vec<String> {
+    let mut args: Vec<String> = env::args().collect();
+    if let Some(command) = args.get_mut(1) {
+        if let Some(name @ ("encode" | "decode" | "stats" | "find")) = command.strip_prefix("--") {
+            *command = name.to_string();
+        }
+    }
+    args
+}
+
+// Time `iterations` encode/decode round trips of `size` random bytes after `warmup` untimed ones
+fn benchmark(size: usize, warmup: usize, iterations: usize, threads: Threads) -> Result<(), BabelError> {
+    let codec = BabelCodec::new().with_verify_mode(VerifyMode::None).with_threads(threads).with_progress(false);
+    let mut data = vec![0u8; size];
+    rand::thread_rng().fill_bytes(&mut data);
+
+    let mut encode_time = Duration::ZERO;
+    let mut decode_time = Duration::ZERO;
+    let mut pages = 0;
+    for round in 0..warmup + iterations.max(1) {
+        let start = Instant::now();
+        let mut encoded = Vec::new();
+        codec.encode_reader(&data[..], "bin", &mut encoded)?;
+        let encoded_at = Instant::now();
 
-    println!("Writing to {}...", output_path);
-    let output_file = fs::File::create(&output_path)?;
-    let mut writer = BufWriter::new(output_file);
-    
-    writeln!(writer, "{}", extension)?;
-    writeln!(writer, "{}", contents.len())?;
-    
-    for (_, location) in locations {
-        writeln!(writer, "{}", location)?;
-    }
-    
-    writer.flush()?;
-    println!("Encoding complete!");
+        let archive = Archive::read(&encoded[..])?;
+        let mut decoded = Vec::with_capacity(size);
+        codec.decode_archive(&archive, &mut decoded)?;
+        let decoded_at = Instant::now();
+
+        if decoded != data {
+            let offset = data.iter().zip(&decoded).position(|(a, b)| a != b).unwrap_or(size.min(decoded.len()));
+            return Err(BabelError::RoundTripMismatch { offset: offset as u64 });
+        }
+        if round >= warmup {
+            encode_time += encoded_at - start;
+            decode_time += decoded_at - encoded_at;
+            pages += archive.addresses().len();
+        }
+    }
+
+    let megabytes = (size * iterations.max(1)) as f64 / 1_000_000.0;
+    println!("Data: {} bytes, {} timed rounds after {} warm-up", size, iterations.max(1), warmup);
+    println!("Threads: {:?} ({} logical CPUs, {} physical cores)", threads, num_cpus::get(), num_cpus::get_physical());
+    println!("Encode: {:.3} MB/s, {:.1} pages/s searched",
+        megabytes / encode_time.as_secs_f64(), pages as f64 / encode_time.as_secs_f64());
+    println!("Decode: {:.3} MB/s, {:.1} pages/s generated",
+        megabytes / decode_time.as_secs_f64(), pages as f64 / decode_time.as_secs_f64());
     Ok(())
 }
 
-fn decode_file(input_path: &str, output_path: Option<&str>) -> std::io::Result<()> {
-    println!("Reading babel file...");
-    let contents = fs::read_to_string(input_path)?;
-    let mut lines = contents.lines();
-    
-    let extension = lines.next().ok_or_else(|| {
-        std::io::Error::new(std::io::ErrorKind::InvalidData, "File is empty")
-    })?;
-    
-    let original_size = lines.next()
-        .and_then(|s| s.parse::<usize>().ok())
-        .ok_or_else(|| {
-            std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid file size")
-        })?;
-    
-    println!("Decoding {} bytes...", original_size);
-    
-    let locations: Vec<&str> = lines.collect();
-    println!("Found {} pages to decode", locations.len());
-    
-    println!("Decoding pages in parallel...");
-    let decoded_chunks: Vec<String> = locations.par_iter()
-        .map(|&location| {
-            let page_content = get_page(location);
-            if let Some(last_non_period) = page_content.rfind(|c| c != PAD_CHAR) {
-                page_content[..=last_non_period].to_string()
-            } else {
-                String::new()
-            }
-        })
-        .collect();
-    
-    let decoded_text = decoded_chunks.join("");
-    
-    println!("Converting to bytes...");
-    let mut bytes = babel_text_to_bytes(&decoded_text);
-    
-    println!("Original size: {}, Decoded size: {}", original_size, bytes.len());
-    bytes.truncate(original_size);
-    
-    let output_path = match output_path {
-        Some(path) => path.to_string(),
-        None => Path::new(input_path)
-            .with_extension(extension)
-            .to_string_lossy()
-            .to_string()
-    };
+// What `encode --dry-run` prints for an input of `size` bytes
+//
+// Nothing is searched unless `timed`, and then only one page, to time it; the
+// rest would go at the same rate on each of the codec's threads.
+fn dry_run(codec: &BabelCodec, size: u64, timed: bool) -> Result<(), BabelError> {
+    let estimate = codec.estimate(size)?;
+    println!("Input: {} bytes", size);
+    println!("Pages: {}", estimate.pages);
+    println!("Archive size: about {} bytes ({} characters per address at most)", estimate.archive_bytes, estimate.address_length);
+    if !timed {
+        return Ok(());
+    }
+
+    let sample = vec![0u8; codec.alphabet().bytes_before(codec.page_length().get() as u64) as usize];
+    let started = Instant::now();
+    codec
+        .clone()
+        .with_verify_mode(VerifyMode::None)
+        .with_pad_to_pages(None)
+        .with_payload_mode(PayloadMode::Bytes)
+        .encode_bytes(&sample)?;
+    let workers = codec.threads().count().clamp(1, estimate.pages.max(1));
+    let seconds = started.elapsed().as_secs_f64() * estimate.pages as f64 / workers as f64;
+    println!("Time: about {:.1}s on {} thread{}", seconds, workers, if workers == 1 { "" } else { "s" });
+    Ok(())
+}
+
+// The path that stands for stdin or stdout
+const STDIO: &str = "-";
+// Where the passphrase comes from when no --passphrase-file is given
+const PASSPHRASE_VAR: &str = "BABEL_PASSPHRASE";
+
+fn open_input(path: &str) -> Result<Box<dyn Read>, BabelError> {
+    Ok(if path == STDIO {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(fs::File::open(path)?))
+    })
+}
 
-    println!("Writing to {}", output_path);
-    fs::write(output_path, bytes)?;
-    
-    println!("Decoding complete!");
+// Stdout when `path` is `-` or missing
+fn open_output(path: Option<&str>) -> Result<Box<dyn Write>, BabelError> {
+    Ok(match path {
+        None | Some(STDIO) => Box::new(BufWriter::new(io::stdout().lock())),
+        Some(path) => Box::new(BufWriter::new(fs::File::create(path)?)),
+    })
+}
+
+// Report arguments clap cannot check on its own, exiting like any other usage error
+fn usage_error(message: &str) -> ! {
+    Cli::command().error(ErrorKind::ArgumentConflict, message).exit()
+}
+
+// The `--stats-json` summary of an encode that wrote `archive_path`, to `target` or stderr
+//
+// The page count and sizes are read back from the archive's header, so they
+// describe what was written whatever options shaped it.
+fn write_encode_summary(
+    target: &str,
+    codec: &BabelCodec,
+    archive_path: &str,
+    original_size: u64,
+    elapsed: Duration,
+) -> Result<(), BabelError> {
+    let mut header = Archive::read_header(BufReader::new(fs::File::open(archive_path)?))?;
+    if header.is_self_contained() {
+        header = codec.archive_header(&Archive::open(archive_path)?)?;
+    }
+    let layout = codec.layout(&header);
+    let summary = serde_json::json!({
+        "archive": archive_path,
+        "pages": layout.page_count(),
+        "original_size": original_size,
+        "encoded_size": header.size(),
+        "babel_text_length": layout.text_len(),
+        "elapsed_seconds": elapsed.as_secs_f64(),
+        "threads": codec.threads().count(),
+    });
+    if target == STDIO {
+        eprintln!("{}", summary);
+    } else {
+        fs::write(target, format!("{}\n", summary))?;
+    }
     Ok(())
 }
 
-fn get_page(address: &str) -> String {
-    let parts: Vec<&str> = address.split(':').collect();
-    let hex_addr = parts[0];
-    let wall = parts[1];
-    let shelf = parts[2];
-    let volume = format!("{:02}", parts[3]);
-    let page = format!("{:03}", parts[4]);
-
-    let loc_str = format!("{}{}{}{}", page, volume, shelf, wall);
-    let loc_int = BigInt::parse_bytes(loc_str.as_bytes(), 10).unwrap();
-    let loc_mult = calculate_loc_mult(LENGTH_OF_PAGE as u32);
-    
-    let key = BigInt::from_str_radix(hex_addr, 36).unwrap() - (loc_int * loc_mult);
-    let result = to_text(key);
-    
-    assert_eq!(result.len(), LENGTH_OF_PAGE, 
-              "Generated page must be exactly {} characters", LENGTH_OF_PAGE);
-    
-    result
-}
-
-fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() < 3 || args.len() > 5 {
-        println!("Usage:");
-        println!("  Encode: {} --encode <input_file> [output_file]", args[0]);
-        println!("  Decode: {} --decode <input_file> [output_file]", args[0]);
-        return;
-    }
-
-    let command = &args[1];
-    let input_path = &args[2];
-    let output_path = args.get(3).map(|s| s.as_str());
-
-    match command.as_str() {
-        "--encode" => {
-            println!("Starting encoding process...");
-            match encode_file(input_path, output_path) {
-                Ok(_) => println!("File encoded successfully"),
-                Err(e) => eprintln!("Error encoding file: {}", e),
+// `--jobs` wins over a command's own `--threads-auto-detect-physical`
+fn threads(jobs: Option<NonZeroUsize>, physical: bool) -> Threads {
+    match jobs {
+        Some(n) => Threads::Fixed(n.get()),
+        None if physical => Threads::Physical,
+        None => Threads::Logical,
+    }
+}
+
+// The passphrase from `file`, without its final line break, or from the environment
+fn passphrase(file: Option<&str>) -> Result<Option<String>, BabelError> {
+    match file {
+        Some(path) => {
+            let contents = fs::read_to_string(path)?;
+            let line = contents.strip_suffix('\n').unwrap_or(&contents);
+            Ok(Some(line.strip_suffix('\r').unwrap_or(line).to_string()))
+        }
+        None => Ok(env::var(PASSPHRASE_VAR).ok()),
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode, BabelError> {
+    let passphrase = passphrase(cli.passphrase_file.as_deref())?;
+    if let Command::Encode(args) | Command::Archive(args) = &cli.command {
+        if args.encrypt && passphrase.is_none() {
+            usage_error(&format!("--encrypt needs a passphrase from --passphrase-file or ${}", PASSPHRASE_VAR));
+        }
+    }
+    let mut codec = BabelCodec::new()
+        .with_progress(cli.verbose > 0)
+        .with_threads(threads(cli.jobs, false))
+        .with_passphrase(passphrase);
+    if !cli.quiet && io::stderr().is_terminal() {
+        let bar = PAGE_BAR.get_or_init(PageBar::new);
+        codec = codec.with_on_page(move |done| bar.set_done(done));
+    }
+    match cli.command {
+        Command::Encode(args) => {
+            let codec = args.codec(codec.with_threads(threads(cli.jobs, args.threads_auto_detect_physical)));
+            let output = args.output.clone().or(args.output_flag.clone());
+            if args.dry_run {
+                if args.input == STDIO {
+                    usage_error("--dry-run measures the input file, so it cannot read stdin");
+                }
+                dry_run(&codec, fs::metadata(&args.input)?.len(), args.time_estimate)?;
+            } else if args.input == STDIO || output.as_deref() == Some(STDIO) {
+                if args.split_size.is_some() || args.preserve_metadata || args.parallel_read.is_some() {
+                    usage_error("--split-size, --preserve-metadata and --parallel-read need real files");
+                }
+                if args.resume {
+                    usage_error("--resume keeps its checkpoint beside the output file, so it needs real files");
+                }
+                let extension = args.extension.clone().unwrap_or_else(|| {
+                    Path::new(&args.input).extension().and_then(|ext| ext.to_str()).unwrap_or("").to_string()
+                });
+                if args.stats_json.is_some() {
+                    usage_error("--stats-json reads the written archive back, so it needs real files");
+                }
+                let _bar = page_bar("Encoding", None);
+                codec.encode_reader(open_input(&args.input)?, &extension, open_output(output.as_deref())?)?;
+            } else {
+                if args.extension.is_some() {
+                    usage_error("--extension only applies when reading stdin or writing stdout");
+                }
+                // Compressed input has no page count until it is compressed
+                let total = match args.compress {
+                    CompressArg::None => Some(codec.estimate(fs::metadata(&args.input)?.len())?.pages as u64),
+                    _ => None,
+                };
+                let _bar = page_bar("Encoding", total);
+                let started = Instant::now();
+                let stats = codec.encode_file(&args.input, output.as_deref())?;
+                if let Some(target) = &args.stats_json {
+                    let archive = output.unwrap_or_else(|| {
+                        Path::new(&args.input).with_extension("babel").to_string_lossy().to_string()
+                    });
+                    let original_size = fs::metadata(&args.input)?.len();
+                    write_encode_summary(target, &codec, &archive, original_size, started.elapsed())?;
+                }
+                if cli.verbose > 0 {
+                    if stats.total == 0 {
+                        eprintln!("Verification skipped");
+                    } else {
+                        eprintln!("{}", stats);
+                    }
+                }
+            }
+        }
+        Command::Archive(args) => {
+            let codec = args.codec(codec.with_threads(threads(cli.jobs, args.threads_auto_detect_physical)));
+            let output = args.output.clone().or(args.output_flag.clone());
+            if args.input == STDIO || output.as_deref() == Some(STDIO) {
+                usage_error("archive reads a directory and writes a file; stdin and stdout are not supported");
+            }
+            if args.extension.is_some() || args.preserve_metadata || args.parallel_read.is_some() || args.dry_run {
+                usage_error("--extension, --preserve-metadata, --parallel-read and --dry-run only apply to encode");
+            }
+            let _bar = page_bar("Encoding", None);
+            let started = Instant::now();
+            let stats = codec.encode_directory(&args.input, output.as_deref())?;
+            if cli.verbose > 0 && stats.total > 0 {
+                eprintln!("{}", stats);
+            }
+            if let Some(target) = &args.stats_json {
+                let archive = match output {
+                    Some(path) => path,
+                    None => fs::canonicalize(&args.input)?.with_extension("babel").to_string_lossy().to_string(),
+                };
+                let original_size = Archive::read_header(BufReader::new(fs::File::open(&archive)?))?
+                    .files()
+                    .iter()
+                    .map(|file| file.size)
+                    .sum();
+                write_encode_summary(target, &codec, &archive, original_size, started.elapsed())?;
+            }
+        }
+        Command::Decode {
+            input, output, output_flag, keep_going, header, size, extension, best_effort, strict_ascii, bytes, pages,
+        } => {
+            let output = output.or(output_flag);
+            let codec = codec
+                .with_strict_checksums(!keep_going)
+                .with_best_effort(best_effort)
+                .with_strict_ascii(strict_ascii);
+            let _bar = page_bar("Decoding", None);
+            let header = match (header, size) {
+                (Some(path), _) => Some(Archive::open(&path)?.header().clone()),
+                (None, Some(size)) => Some(ArchiveHeader::new(&extension, size, codec.alphabet())),
+                (None, None) => None,
+            };
+            if bytes.is_some() || pages.is_some() {
+                let archive = match header {
+                    Some(header) => {
+                        let mut list = String::new();
+                        open_input(&input)?.read_to_string(&mut list)?;
+                        Archive::from_addresses(header, &list)
+                    }
+                    None if input == STDIO => Archive::read(io::stdin().lock())?,
+                    None => Archive::open(&input)?,
+                };
+                let range = match (bytes, pages) {
+                    (Some(bytes), _) => bytes,
+                    (None, Some(pages)) => {
+                        let header = codec.archive_header(&archive)?;
+                        if header.compression() != Compression::None || header.is_encrypted() {
+                            usage_error("--pages needs an uncompressed, unencrypted archive; use --bytes");
+                        }
+                        page_bytes(&codec.layout(&header), pages)
+                    }
+                    (None, None) => unreachable!(),
+                };
+                codec.decode_range(&archive, range, open_output(output.as_deref())?)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            let streamed = input == STDIO || output.as_deref() == Some(STDIO);
+            match header {
+                Some(header) if streamed => {
+                    let mut list = String::new();
+                    open_input(&input)?.read_to_string(&mut list)?;
+                    codec.decode_archive(&Archive::from_addresses(header, &list), open_output(output.as_deref())?)?;
+                }
+                Some(header) => codec.decode_with_header(&header, &input, output.as_deref())?,
+                None if streamed => {
+                    codec.decode_reader(open_input(&input)?, open_output(output.as_deref())?)?;
+                }
+                None => codec.decode_file(&input, output.as_deref())?,
+            }
+        }
+        Command::Verify { archive, original } => {
+            let archive = Archive::open(&archive)?;
+            let header = codec.archive_header(&archive)?;
+            let size = header.size();
+            let pages = Some(codec.layout(&header).page_count() as u64);
+            if let Some(original) = original {
+                let difference = {
+                    let _bar = page_bar("Decoding", pages);
+                    codec.first_difference(&archive, fs::File::open(&original)?)?
+                };
+                if let Some(offset) = difference {
+                    println!("Decoded contents differ from {} at byte {}", original, offset);
+                    return Ok(ExitCode::FAILURE);
+                }
+                println!("Archive reproduces {} ({} bytes)", original, fs::metadata(&original)?.len());
+                return Ok(ExitCode::SUCCESS);
+            }
+            let decoded = {
+                let _bar = page_bar("Decoding", pages);
+                io::copy(&mut codec.reader(&archive)?, &mut io::sink())?
+            };
+            // The size of a compressed archive counts compressed bytes; the stream's own checksum stands in for it
+            if header.compression() == Compression::None && decoded != size {
+                println!("Decoded {} bytes but the header records {}", decoded, size);
+                return Ok(ExitCode::FAILURE);
+            }
+            println!("Archive decodes to {} bytes", decoded);
+        }
+        Command::Stats { archive } => {
+            let mut header = Archive::read_header(BufReader::new(fs::File::open(&archive)?))?;
+            if header.is_self_contained() {
+                header = codec.archive_header(&Archive::open(&archive)?)?;
+            }
+            print_stats(&header, &codec);
+        }
+        Command::Find { archive, text } => {
+            let archive = BufReader::new(fs::File::open(&archive)?);
+            match codec.find_text(archive, text.as_bytes())? {
+                Some(offset) => println!("Found at byte offset {}", offset),
+                None => println!("Not found"),
+            }
+        }
+        Command::Benchmark { size, warmup, iterations, threads_auto_detect_physical } => {
+            benchmark(size, warmup, iterations, threads(cli.jobs, threads_auto_detect_physical))?
+        }
+        Command::Locate { phrase, seed } => {
+            let codec = codec.with_seed(seed);
+            println!("{}", codec.locate(&phrase)?.to_string_in(&codec.geometry()));
+        }
+        Command::Lookup { address, trim, raw: _ } => {
+            let page = codec.get_page_shared(&address)?;
+            println!("{}", if trim { page.trim_end_matches(PAD_CHAR) } else { &page });
+        }
+        Command::Text { input, output, byte_encoding } => {
+            let mut bytes = Vec::new();
+            open_input(&input)?.read_to_end(&mut bytes)?;
+            let codec = codec.with_alphabet(byte_encoding.into());
+            let mut writer = open_output(output.as_deref())?;
+            writeln!(writer, "{}", codec.bytes_to_babel_text(&bytes))?;
+            writer.flush()?;
+        }
+        Command::Untext { input, output, byte_encoding, size } => {
+            let mut text = String::new();
+            open_input(&input)?.read_to_string(&mut text)?;
+            // The line break `text` ends with is not part of the spelling
+            let mut text = text.trim_end_matches(['\n', '\r']);
+            let alphabet = Alphabet::from(byte_encoding);
+            if let Some(size) = size {
+                let chars = usize::try_from(alphabet.text_len(size)).unwrap_or(usize::MAX);
+                text = match text.char_indices().nth(chars) {
+                    Some((end, _)) => &text[..end],
+                    None if text.chars().count() == chars => text,
+                    None => {
+                        let message = format!("text spells fewer than the {} bytes asked for", size);
+                        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, message).into());
+                    }
+                };
             }
-        },
-        "--decode" => {
-            println!("Starting decoding process...");
-            match decode_file(input_path, output_path) {
-                Ok(_) => println!("File decoded successfully"),
-                Err(e) => eprintln!("Error decoding file: {}", e),
+            let bytes = codec.with_alphabet(alphabet).babel_text_to_bytes(text)?;
+            let mut writer = open_output(output.as_deref())?;
+            writer.write_all(&bytes)?;
+            writer.flush()?;
+        }
+        Command::Explore { address, full } => {
+            let neighborhood = codec.neighbors(&address)?;
+            let location = neighborhood.location;
+            println!("Wall {}, shelf {}, volume {}, page {}",
+                location.wall, location.shelf, location.volume, location.page);
+            println!();
+            println!("Other pages of this book ({}):", neighborhood.same_book.len());
+            for page in &neighborhood.same_book {
+                println!("  {}", short_address(page, full));
+            }
+            println!();
+            println!("Other books on this shelf ({}):", neighborhood.same_shelf.len());
+            for book in &neighborhood.same_shelf {
+                println!("  {}", short_address(book, full));
+            }
+        }
+        Command::Migrate { input, output } => {
+            let version = codec.migrate_archive(&input, &output)?;
+            println!("Migrated {} from version {} to {}", input, version, FORMAT_VERSION);
+        }
+        Command::Doctor { archive } => {
+            let problems = codec.diagnose(&Archive::open(&archive)?);
+            if problems.is_empty() {
+                println!("No problems found");
+            }
+            for problem in &problems {
+                println!("{}", problem);
             }
-        },
-        _ => {
-            println!("Unknown command. Use --encode or --decode");
+            if !problems.is_empty() {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// The page bar, when stderr is a terminal to draw it on
+static PAGE_BAR: OnceLock<PageBar> = OnceLock::new();
+
+// Show the page bar, if there is one, until the guard is dropped
+fn page_bar(action: &'static str, total: Option<u64>) -> Option<Started<'static>> {
+    PAGE_BAR.get().map(|bar| bar.start(action, total))
+}
+
+// Writes the library's log records to stderr, the way the CLI prints its own messages
+//
+// Debug records are only taken from this crate, so `-vv` does not pull in
+// the HTTP stack's. Records are written with the page bar lifted off the terminal.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+            && (metadata.level() <= Level::Info || metadata.target().starts_with(env!("CARGO_CRATE_NAME")))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let print = || match record.level() {
+            Level::Error => eprintln!("Error: {}", record.args()),
+            Level::Warn => eprintln!("Warning: {}", record.args()),
+            Level::Info => eprintln!("{}", record.args()),
+            Level::Debug | Level::Trace => eprintln!("[{}] {}", record.level(), record.args()),
+        };
+        match PAGE_BAR.get() {
+            Some(bar) => bar.suspend(print),
+            None => print(),
         }
     }
-}
\ No newline at end of file
+
+    fn flush(&self) {}
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse_from(legacy_args());
+    log::set_max_level(match (cli.quiet, cli.verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, _) => LevelFilter::Debug,
+    });
+    log::set_logger(&StderrLogger).expect("no logger is set before main");
+    run(cli).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        ExitCode::FAILURE
+    })
+}
@@ -0,0 +1,94 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::archive::ArchiveHeader;
+use crate::error::BabelError;
+
+/// Copy `path`'s modification time and (on unix) permission bits into `header`.
+pub(crate) fn capture(path: &Path, header: &mut ArchiveHeader) -> Result<(), BabelError> {
+    let metadata = fs::metadata(path)?;
+
+    header.modified = metadata.modified().ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since_epoch| since_epoch.as_secs());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        header.mode = Some(metadata.permissions().mode() & 0o7777);
+    }
+
+    Ok(())
+}
+
+/// Restore whatever metadata `header` carries onto the decoded file at `path`.
+///
+/// The mode is ignored on platforms without unix permissions.
+pub(crate) fn apply(path: &Path, header: &ArchiveHeader) -> Result<(), BabelError> {
+    if let Some(modified) = header.modified {
+        let file = fs::File::options().write(true).open(path)?;
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(modified))?;
+    }
+
+    #[cfg(unix)]
+    if let Some(mode) = header.mode {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+
+    Ok(())
+}
+
+/// The current time as an RFC 3339 UTC timestamp.
+pub(crate) fn now_utc() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or(0);
+    utc_timestamp(secs)
+}
+
+/// Format seconds since the unix epoch as `YYYY-MM-DDTHH:MM:SSZ`.
+pub(crate) fn utc_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let time = secs % 86_400;
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, time / 3_600, time % 3_600 / 60, time % 60)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::codec::BabelCodec;
+
+    #[test]
+    fn file_mode_survives_encode_and_decode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        fs::write(path("script.sh"), b"#!/bin/sh\necho hello\n").unwrap();
+        fs::set_permissions(path("script.sh"), fs::Permissions::from_mode(0o751)).unwrap();
+
+        let codec = BabelCodec::default().with_progress(false).with_preserve_metadata(true);
+        codec.encode_file(&path("script.sh"), Some(&path("script.babel"))).unwrap();
+        codec.decode_file(&path("script.babel"), Some(&path("restored.sh"))).unwrap();
+
+        let mode = fs::metadata(path("restored.sh")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o751);
+        assert_eq!(fs::read(path("restored.sh")).unwrap(), fs::read(path("script.sh")).unwrap());
+    }
+}
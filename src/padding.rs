@@ -0,0 +1,93 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::alphabet::Alphabet;
+use crate::library::{CHARSET, PAD_CHAR};
+
+/// What fills the rest of the final page.
+///
+/// Decoding stops at the size recorded in the header, so the padding is never
+/// read back and any strategy decodes to the same bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PadStrategy {
+    /// Repeat one page character; `Repeat('.')` is the default.
+    Repeat(char),
+    /// Random page characters, so the page reads like any other in the library.
+    RandomAlphabet,
+    /// The alphabet's zero symbol, as if the file continued with zero bytes.
+    Zero,
+}
+
+impl Default for PadStrategy {
+    fn default() -> Self {
+        PadStrategy::Repeat(PAD_CHAR)
+    }
+}
+
+impl PadStrategy {
+    // Only page characters can be searched for
+    pub(crate) fn is_valid(&self) -> bool {
+        match self {
+            PadStrategy::Repeat(c) => CHARSET.contains(*c),
+            PadStrategy::RandomAlphabet | PadStrategy::Zero => true,
+        }
+    }
+
+    // Pad `chunk` out to `length` characters
+    pub(crate) fn pad(&self, chunk: &str, length: usize, alphabet: &Alphabet) -> String {
+        self.pad_with(chunk, length, alphabet, &mut rand::thread_rng())
+    }
+
+    // `pad`, drawing random padding from `rng`
+    pub(crate) fn pad_with<R: Rng>(&self, chunk: &str, length: usize, alphabet: &Alphabet, rng: &mut R) -> String {
+        let missing = length.saturating_sub(chunk.len());
+        let mut page = String::with_capacity(length);
+        page.push_str(chunk);
+        match self {
+            PadStrategy::Repeat(c) => page.extend(std::iter::repeat_n(*c, missing)),
+            PadStrategy::Zero => page.extend(std::iter::repeat_n(alphabet.symbol(0), missing)),
+            PadStrategy::RandomAlphabet => {
+                let charset = CHARSET.as_bytes();
+                page.extend((0..missing).map(|_| *charset.choose(rng).unwrap() as char));
+            }
+        }
+        page
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::BabelCodec;
+    use crate::page_length::PageLength;
+    use crate::text_mode::PayloadMode;
+
+    const STRATEGIES: [PadStrategy; 5] =
+        [PadStrategy::Repeat('.'), PadStrategy::Repeat('a'), PadStrategy::Repeat(' '), PadStrategy::RandomAlphabet, PadStrategy::Zero];
+
+    fn round_trip(codec: BabelCodec, bytes: &[u8]) -> Vec<u8> {
+        let codec = codec.with_progress(false).with_page_length(PageLength::new(20).unwrap());
+        let mut archive = Vec::new();
+        codec.encode_reader(bytes, "bin", &mut archive).unwrap();
+        let mut decoded = Vec::new();
+        codec.decode_reader(&archive[..], &mut decoded).unwrap();
+        decoded
+    }
+
+    // Content that reads like the padding, zeros for `Zero` and `a`s for `Repeat('a')`, included
+    #[test]
+    fn every_strategy_decodes_to_the_exact_bytes() {
+        for pad in STRATEGIES {
+            for alphabet in [Alphabet::letters26(), Alphabet::base29()] {
+                for bytes in [&b"seventeen bytes.."[..], b"zeros\0\0", b"\0", b"aaaa", b""] {
+                    let codec = BabelCodec::default().with_pad_strategy(pad).with_alphabet(alphabet.clone());
+                    assert_eq!(round_trip(codec, bytes), bytes, "{:?} {} {:?}", pad, alphabet.id(), bytes);
+                }
+            }
+            for text in [&b"ends in periods.."[..], b"ends in spaces  ", b"aaaa"] {
+                let codec = BabelCodec::default().with_pad_strategy(pad).with_payload_mode(PayloadMode::Text);
+                assert_eq!(round_trip(codec, text), text, "{:?} {:?}", pad, text);
+            }
+        }
+    }
+}
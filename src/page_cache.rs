@@ -0,0 +1,109 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::address::Address;
+use crate::codec::BabelCodec;
+use crate::error::BabelError;
+use crate::library::get_page;
+
+// The most recently used pages, shared by every clone of a codec
+#[derive(Debug, Default)]
+pub(crate) struct PageCache {
+    capacity: usize,
+    pages: HashMap<String, Arc<str>>,
+    // Least recently used first
+    order: VecDeque<String>,
+}
+
+impl PageCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        PageCache { capacity, ..Default::default() }
+    }
+
+    fn get(&mut self, address: &str) -> Option<Arc<str>> {
+        let page = self.pages.get(address)?.clone();
+        self.touch(address);
+        Some(page)
+    }
+
+    fn insert(&mut self, address: &str, page: Arc<str>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.pages.insert(address.to_string(), page).is_some() {
+            self.touch(address);
+            return;
+        }
+        self.order.push_back(address.to_string());
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.pages.remove(&oldest);
+            }
+        }
+    }
+
+    // Move `address` to the most recently used end
+    fn touch(&mut self, address: &str) {
+        if let Some(i) = self.order.iter().position(|a| a == address) {
+            let address = self.order.remove(i).unwrap();
+            self.order.push_back(address);
+        }
+    }
+}
+
+impl BabelCodec {
+    /// Keep the text of the last `pages` pages generated, so repeated lookups
+    /// of the same address share one allocation. Zero, the default, disables
+    /// the cache. Clones of the codec share the cache.
+    pub fn with_page_cache(mut self, pages: usize) -> Self {
+        self.page_cache = Arc::new(Mutex::new(PageCache::new(pages)));
+        self
+    }
+
+    // Pages of another length sit at the same addresses, so start over with an empty cache
+    pub(crate) fn reset_page_cache(&mut self) {
+        let capacity = self.page_cache.lock().unwrap().capacity;
+        self.page_cache = Arc::new(Mutex::new(PageCache::new(capacity)));
+    }
+
+    /// The text of the page at `address`, shared rather than copied.
+    ///
+    /// With a page cache, looking up a cached address returns the same `Arc`
+    /// as before without generating the page again; otherwise every call
+    /// generates a fresh page.
+    pub fn get_page_shared(&self, address: &str) -> Result<Arc<str>, BabelError> {
+        if let Some(page) = self.page_cache.lock().unwrap().get(address) {
+            return Ok(page);
+        }
+
+        // Generated without the lock held, so other threads keep hitting the cache
+        let space = self.space()?;
+        let page: Arc<str> = get_page(&Address::parse_in(address, &space.geometry)?, space)?.into();
+        self.page_cache.lock().unwrap().insert(address, page.clone());
+        Ok(page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::GET_PAGE_CALLS;
+    use crate::page_length::PageLength;
+
+    #[test]
+    fn cached_lookups_share_one_page() {
+        let codec = BabelCodec::default().with_progress(false).with_page_length(PageLength::new(20).unwrap());
+        let addresses = codec.encode_bytes(b"looked up twice, once").unwrap();
+        let cached = codec.clone().with_page_cache(2);
+
+        let before = GET_PAGE_CALLS.with(|calls| calls.get());
+        let first = cached.get_page_shared(&addresses[0]).unwrap();
+        let second = cached.get_page_shared(&addresses[0]).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(GET_PAGE_CALLS.with(|calls| calls.get()) - before, 1);
+
+        let uncached = codec.get_page_shared(&addresses[0]).unwrap();
+        assert!(!Arc::ptr_eq(&uncached, &codec.get_page_shared(&addresses[0]).unwrap()));
+        assert_eq!(uncached, first);
+    }
+}
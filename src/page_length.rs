@@ -0,0 +1,82 @@
+use crate::alphabet::Alphabet;
+use crate::error::BabelError;
+use crate::library::LENGTH_OF_PAGE;
+
+/// Number of characters on a page.
+///
+/// Bytes are spelled in blocks of characters, two per byte in most alphabets
+/// and five per three bytes in base29, so with a length that is not a multiple
+/// of the alphabet's block one block straddles every page boundary. Decoding
+/// copes with that by carrying the unfinished block over, but it means no page
+/// holds whole bytes on its own. [`PageLength::aligned`] rounds down to whole
+/// blocks for callers that want pages to map onto byte ranges exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PageLength(usize);
+
+impl PageLength {
+    /// The libraryofbabel.info page length, 3239 characters.
+    pub const DEFAULT: PageLength = PageLength(LENGTH_OF_PAGE);
+
+    /// Accept any length from 1 up to `u32::MAX`.
+    ///
+    /// Whether pages hold whole blocks depends on the alphabet; see
+    /// [`PageLength::holds_whole_blocks`].
+    pub fn new(length: usize) -> Result<Self, BabelError> {
+        PageLength::recorded(length)
+    }
+
+    // A length read from an archive header
+    pub(crate) fn recorded(length: usize) -> Result<Self, BabelError> {
+        if length == 0 || u32::try_from(length).is_err() {
+            return Err(BabelError::PageLengthOutOfRange(length));
+        }
+        Ok(PageLength(length))
+    }
+
+    /// The largest length not above `length` holding whole blocks of
+    /// `alphabet`, so each page holds whole bytes.
+    pub fn aligned(length: usize, alphabet: &Alphabet) -> Result<Self, BabelError> {
+        let block = alphabet.block_chars() as usize;
+        if length < block || u32::try_from(length).is_err() {
+            return Err(BabelError::PageLengthOutOfRange(length));
+        }
+        Ok(PageLength(length - length % block))
+    }
+
+    pub fn get(self) -> usize {
+        self.0
+    }
+
+    /// Whether pages of this length hold whole blocks of `alphabet`.
+    pub fn holds_whole_blocks(self, alphabet: &Alphabet) -> bool {
+        (self.0 as u64).is_multiple_of(alphabet.block_chars())
+    }
+}
+
+impl Default for PageLength {
+    fn default() -> Self {
+        PageLength::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligned_rounds_down_to_whole_blocks() {
+        let pairs = Alphabet::letters26();
+        assert_eq!(PageLength::aligned(LENGTH_OF_PAGE, &pairs).unwrap().get(), 3238);
+        assert_eq!(PageLength::aligned(3200, &pairs).unwrap().get(), 3200);
+        assert_eq!(PageLength::aligned(3, &pairs).unwrap().get(), 2);
+        assert!(PageLength::aligned(3, &pairs).unwrap().holds_whole_blocks(&pairs));
+        assert!(matches!(PageLength::aligned(1, &pairs), Err(BabelError::PageLengthOutOfRange(1))));
+        assert!(!PageLength::new(LENGTH_OF_PAGE).unwrap().holds_whole_blocks(&pairs));
+
+        let base29 = Alphabet::base29();
+        assert_eq!(PageLength::aligned(LENGTH_OF_PAGE, &base29).unwrap().get(), 3235);
+        assert!(PageLength::new(12).unwrap().holds_whole_blocks(&pairs));
+        assert!(!PageLength::new(12).unwrap().holds_whole_blocks(&base29));
+        assert!(matches!(PageLength::aligned(4, &base29), Err(BabelError::PageLengthOutOfRange(4))));
+    }
+}
@@ -0,0 +1,78 @@
+use rayon::prelude::*;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// A `Read` over a file that fills each buffer through several handles at once.
+///
+/// Every call splits the requested span into one contiguous region per
+/// handle, reads the regions concurrently and returns them stitched back
+/// together in order. Callers therefore see exactly the bytes a sequential
+/// read would give, so page boundaries and conversion are unaffected.
+pub(crate) struct ParallelReader {
+    handles: Vec<fs::File>,
+    offset: u64,
+    len: u64,
+}
+
+impl ParallelReader {
+    pub(crate) fn open<P: AsRef<Path>>(path: P, handles: usize) -> io::Result<Self> {
+        let path = path.as_ref();
+        let handles = (0..handles.max(1))
+            .map(|_| fs::File::open(path))
+            .collect::<io::Result<Vec<_>>>()?;
+        let len = handles[0].metadata()?.len();
+        Ok(ParallelReader { handles, offset: 0, len })
+    }
+}
+
+impl Read for ParallelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let want = buf.len().min((self.len - self.offset) as usize);
+        if want == 0 {
+            return Ok(0);
+        }
+        let region = want.div_ceil(self.handles.len());
+        let offset = self.offset;
+        buf[..want]
+            .par_chunks_mut(region)
+            .zip(self.handles.par_iter_mut())
+            .enumerate()
+            .try_for_each(|(i, (chunk, file))| {
+                file.seek(SeekFrom::Start(offset + (i * region) as u64))?;
+                file.read_exact(chunk)
+            })?;
+        self.offset += want as u64;
+        Ok(want)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Buffer sizes that split unevenly across the handles, and one past the end
+    #[test]
+    fn reads_match_a_sequential_read() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("input.bin");
+        let bytes: Vec<u8> = (0..10_007u32).map(|i| (i * 7919 % 251) as u8).collect();
+        fs::write(&path, &bytes).unwrap();
+
+        for handles in [1, 3, 4, 16] {
+            for buf_len in [1, 7, 1000, 4096, 20_000] {
+                let mut reader = ParallelReader::open(&path, handles).unwrap();
+                let mut read = Vec::new();
+                let mut buf = vec![0u8; buf_len];
+                loop {
+                    let n = reader.read(&mut buf).unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    read.extend_from_slice(&buf[..n]);
+                }
+                assert_eq!(read, bytes, "{} handles, {} byte reads", handles, buf_len);
+            }
+        }
+    }
+}
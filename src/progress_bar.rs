@@ -0,0 +1,55 @@
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+// Pages done out of the total, with rate and ETA once the total is known
+const TEMPLATE: &str = "{msg} [{elapsed_precise}] {wide_bar} {pos}/{len} pages ({per_sec}, ETA {eta})";
+// For a stream of unknown length: no bar or ETA, only the count and rate
+const OPEN_TEMPLATE: &str = "{msg} [{elapsed_precise}] {spinner} {pos} pages ({per_sec})";
+
+// A bar on stderr counting the pages the codec reports through `BabelCodec::with_on_page`
+//
+// It draws nothing until started.
+#[derive(Debug)]
+pub(crate) struct PageBar(ProgressBar);
+
+impl PageBar {
+    pub(crate) fn new() -> Self {
+        PageBar(ProgressBar::hidden())
+    }
+
+    // Start counting from zero towards `total` pages, if it is known, until
+    // the returned guard is dropped
+    pub(crate) fn start(&self, action: &'static str, total: Option<u64>) -> Started<'_> {
+        let bar = &self.0;
+        let template = if total.is_some() { TEMPLATE } else { OPEN_TEMPLATE };
+        bar.set_style(ProgressStyle::with_template(template).expect("progress template is valid"));
+        match total {
+            Some(total) => bar.set_length(total),
+            None => bar.unset_length(),
+        }
+        bar.set_message(action);
+        bar.reset();
+        bar.set_draw_target(ProgressDrawTarget::stderr());
+        Started(self)
+    }
+
+    // Move to `done` pages, the count the codec's page callback is given
+    pub(crate) fn set_done(&self, done: usize) {
+        self.0.set_position(done as u64);
+    }
+
+    // Run `print` with the bar lifted off the terminal, so the line is not drawn over
+    pub(crate) fn suspend<T>(&self, print: impl FnOnce() -> T) -> T {
+        self.0.suspend(print)
+    }
+}
+
+// Clears the bar when dropped, on success or error alike
+pub(crate) struct Started<'a>(&'a PageBar);
+
+impl Drop for Started<'_> {
+    fn drop(&mut self) {
+        let bar = &self.0 .0;
+        bar.finish_and_clear();
+        bar.set_draw_target(ProgressDrawTarget::hidden());
+    }
+}
@@ -0,0 +1,146 @@
+use std::io::{self, Read};
+
+use crate::archive::Archive;
+use crate::codec::{BabelCodec, DecodedChunks};
+use crate::crc::Crc32;
+use crate::error::BabelError;
+
+/// The decoded contents of an archive, produced a page at a time as they are read.
+///
+/// A compressed archive is decompressed on the way out. Convert it with
+/// `try_into()` to decode everything into a `Vec<u8>`.
+pub struct ArchiveReader<'a> {
+    inner: Box<dyn Read + 'a>,
+    size: u64,
+    // What the pages can hold, which unlike `size` a header cannot inflate
+    capacity: usize,
+}
+
+impl ArchiveReader<'_> {
+    /// Size of the original file according to the header; for a compressed
+    /// archive, the size of the compressed bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl Read for ArchiveReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+// The bytes on an archive's pages, before any decompression
+struct PageReader<'a> {
+    chunks: DecodedChunks<'a>,
+    buffer: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for PageReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buffer.len() {
+            match self.chunks.next() {
+                Some(chunk) => {
+                    self.buffer = chunk.map_err(io::Error::other)?;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.buffer.len() - self.pos);
+        buf[..n].copy_from_slice(&self.buffer[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// Checks the decoded bytes against the file's CRC-32 when they run out
+struct CheckedReader<'a> {
+    inner: Box<dyn Read + 'a>,
+    codec: &'a BabelCodec,
+    expected: Option<u32>,
+    crc: Crc32,
+}
+
+impl Read for CheckedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc.update(&buf[..n]);
+        if n == 0 && !buf.is_empty() {
+            if let Some(expected) = self.expected.take() {
+                self.codec.check_crc32(expected, self.crc.finish()).map_err(io::Error::other)?;
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl TryFrom<ArchiveReader<'_>> for Vec<u8> {
+    type Error = BabelError;
+
+    fn try_from(mut reader: ArchiveReader<'_>) -> Result<Self, BabelError> {
+        let mut bytes = Vec::with_capacity(reader.capacity);
+        reader.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl BabelCodec {
+    /// A `Read` over the decoded contents of `archive`.
+    pub fn reader<'a>(&'a self, archive: &'a Archive) -> Result<ArchiveReader<'a>, BabelError> {
+        self.chunk_reader(self.decode_chunks(archive)?)
+    }
+
+    // A `Read` over the bytes `chunks` decodes, decompressed and decrypted
+    pub(crate) fn chunk_reader<'a>(&'a self, chunks: DecodedChunks<'a>) -> Result<ArchiveReader<'a>, BabelError> {
+        let size = chunks.header().size();
+        let capacity = chunks.capacity();
+        let compression = chunks.header().compression();
+        let expected = chunks.header().crc32().filter(|_| !chunks.header().size_unknown);
+        let encrypted = chunks.header().is_encrypted();
+        let header = chunks.header().clone();
+        let mut pages: Box<dyn Read + 'a> = Box::new(PageReader { chunks, buffer: Vec::new(), pos: 0 });
+        // The tag is only checked once every byte is in, so nothing is streamed before then
+        if encrypted {
+            let mut sealed = Vec::new();
+            pages.read_to_end(&mut sealed)?;
+            pages = Box::new(io::Cursor::new(self.decrypt(&header, sealed)?));
+        }
+        let inner = compression.decompress(pages)?;
+        let inner: Box<dyn Read + 'a> = match expected {
+            Some(expected) => Box::new(CheckedReader { inner, codec: self, expected: Some(expected), crc: Crc32::new() }),
+            None => inner,
+        };
+        Ok(ArchiveReader { inner, size, capacity })
+    }
+
+    /// Decode an archive held in memory.
+    pub fn decode_from_slice(&self, archive: &[u8]) -> Result<Vec<u8>, BabelError> {
+        let archive = Archive::read(archive)?;
+        let reader = self.reader(&archive)?;
+        reader.try_into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive_of(bytes: &[u8]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        BabelCodec::default().with_progress(false).encode_reader(bytes, "bin", &mut archive).unwrap();
+        archive
+    }
+
+    #[test]
+    fn try_into_matches_decode_from_slice() {
+        let codec = BabelCodec::default().with_progress(false);
+        let bytes: Vec<u8> = (0..5000u32).map(|i| (i * 7 % 256) as u8).collect();
+        let archive = archive_of(&bytes);
+        let parsed = Archive::read(&archive[..]).unwrap();
+        let decoded: Vec<u8> = codec.reader(&parsed).unwrap().try_into().unwrap();
+        assert_eq!(decoded, codec.decode_from_slice(&archive).unwrap());
+        assert_eq!(decoded, bytes);
+    }
+}
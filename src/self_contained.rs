@@ -0,0 +1,171 @@
+use crate::alphabet::Alphabet;
+use crate::archive::{Archive, ArchiveHeader, FORMAT_VERSION};
+use crate::codec::BabelCodec;
+use crate::crc::crc32;
+use crate::error::BabelError;
+use crate::library::get_page;
+use crate::location::LibraryGeometry;
+use crate::padding::PadStrategy;
+use crate::page_length::PageLength;
+
+// Bytes of the little-endian length before the header JSON, and of the CRC-32 after it
+const LENGTH_BYTES: usize = 4;
+const CRC_BYTES: usize = 4;
+
+impl BabelCodec {
+    // Put pages holding `archive`'s header in front of its addresses.
+    //
+    // The header is stored as its JSON length, the JSON and a CRC-32 of the
+    // JSON, spelled like any other bytes and padded out to whole pages so the
+    // payload still starts on a page boundary. Header pages always have the
+    // default length, alphabet and geometry, since what the payload uses is inside them.
+    pub(crate) fn embed_header(&self, archive: &mut Archive) -> Result<(), BabelError> {
+        if self.page_length() != PageLength::DEFAULT
+            || *self.alphabet() != Alphabet::default()
+            || self.geometry() != LibraryGeometry::default()
+        {
+            return self.header_codec().embed_header(archive);
+        }
+        let header = ArchiveHeader { version: FORMAT_VERSION, ..archive.header.clone() };
+        let json = serde_json::to_vec(&header).map_err(|e| BabelError::InvalidHeader(e.to_string()))?;
+
+        let mut bytes = (json.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&json);
+        bytes.extend_from_slice(&crc32(&json).to_le_bytes());
+
+        let page_length = self.page_length().get();
+        let chunks: Vec<String> = self
+            .bytes_to_babel_text(&bytes)
+            .as_bytes()
+            .chunks(page_length)
+            .map(|chunk| PadStrategy::default().pad(std::str::from_utf8(chunk).unwrap(), page_length, self.alphabet()))
+            .collect();
+        let (mut addresses, stats) = self.locate_pages(&chunks, 0)?;
+        if !stats.all_passed() {
+            return Err(BabelError::VerificationFailed(stats));
+        }
+
+        addresses.append(&mut archive.addresses);
+        archive.addresses = addresses;
+        // Read back the same way as a parsed self-contained archive
+        archive.header.self_contained = true;
+        Ok(())
+    }
+
+    // The header stored in the leading pages of a self-contained archive,
+    // and how many pages it takes; `None` for any other archive
+    pub(crate) fn embedded_header(&self, archive: &Archive) -> Result<Option<(ArchiveHeader, usize)>, BabelError> {
+        if !archive.header.self_contained {
+            return Ok(None);
+        }
+        if self.page_length() != PageLength::DEFAULT
+            || *self.alphabet() != Alphabet::default()
+            || self.geometry() != LibraryGeometry::default()
+        {
+            return self.header_codec().embedded_header(archive);
+        }
+        let space = self.space()?;
+        let missing = || {
+            BabelError::InvalidHeader(
+                "Self-contained archive is missing its header pages; \
+                 a bare address list decodes with --header or --size"
+                    .to_string(),
+            )
+        };
+
+        let mut text = String::new();
+        let mut pages = 0;
+        let mut read_bytes = |count: usize, text: &mut String| -> Result<Vec<u8>, BabelError> {
+            while text.len() < count * 2 {
+                let address = archive.addresses.get(pages).ok_or_else(missing)?;
+                text.push_str(&get_page(&address.parse()?, space)?);
+                pages += 1;
+            }
+            self.babel_text_to_bytes(&text[..count * 2])
+        };
+
+        let length = read_bytes(LENGTH_BYTES, &mut text)?;
+        let json_len = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+        let bytes = read_bytes(LENGTH_BYTES + json_len + CRC_BYTES, &mut text)?;
+
+        let json = &bytes[LENGTH_BYTES..LENGTH_BYTES + json_len];
+        let crc = u32::from_le_bytes(bytes[LENGTH_BYTES + json_len..].try_into().unwrap());
+        if crc32(json) != crc {
+            return Err(BabelError::InvalidHeader("Embedded header does not match its checksum".to_string()));
+        }
+        let header = serde_json::from_slice(json).map_err(|e| BabelError::InvalidHeader(e.to_string()))?;
+        Ok(Some((header, pages)))
+    }
+
+    // This codec at the default page length and alphabet, for reading and writing header pages
+    fn header_codec(&self) -> BabelCodec {
+        self.clone()
+            .with_alphabet(Alphabet::default())
+            .with_byte_aligned_pages(false)
+            .with_page_length(PageLength::DEFAULT)
+            .with_geometry(LibraryGeometry::default())
+    }
+
+    /// The header of `archive`, read from its leading pages if it is self-contained.
+    pub fn archive_header(&self, archive: &Archive) -> Result<ArchiveHeader, BabelError> {
+        Ok(match self.embedded_header(archive)? {
+            Some((header, _)) => header,
+            None => archive.header.clone(),
+        })
+    }
+
+    // `archive` with an embedded header moved out of its pages and into the header
+    pub(crate) fn resolve_embedded(&self, mut archive: Archive) -> Result<Archive, BabelError> {
+        if let Some((header, pages)) = self.embedded_header(&archive)? {
+            archive.header = header;
+            archive.addresses.drain(..pages);
+            archive.separate_filler();
+        }
+        Ok(archive)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Address;
+    use crate::archive::ArchiveFormat;
+    use std::fs;
+
+    #[test]
+    fn self_contained_archives_are_only_addresses() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let (input, archive, output) = (dir.path().join("notes.txt"), dir.path().join("notes.babel"), dir.path().join("out.txt"));
+        let bytes = b"nothing but addresses on disk, the header hidden in pages of its own";
+        fs::write(&input, bytes).unwrap();
+        let codec = BabelCodec::default()
+            .with_progress(false)
+            .with_page_length(PageLength::new(40).unwrap())
+            .with_format(ArchiveFormat::SelfContained)
+            .with_note("in the pages");
+        codec.encode_file(input.to_str().unwrap(), archive.to_str()).unwrap();
+
+        let written = fs::read_to_string(&archive).unwrap();
+        assert!(!written.contains('='), "{}", written);
+        for line in written.lines() {
+            assert!(line.parse::<Address>().is_ok(), "not an address: {}", line);
+        }
+        let header = codec.archive_header(&Archive::open(&archive).unwrap()).unwrap();
+        assert_eq!((header.extension(), header.note()), ("txt", Some("in the pages")));
+
+        BabelCodec::default().with_progress(false).decode_file(archive.to_str().unwrap(), output.to_str()).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), bytes);
+    }
+
+    // Refused before the input is opened, so a missing one is never noticed
+    #[test]
+    fn splitting_is_refused_before_any_page_is_searched() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let codec = BabelCodec::default().with_progress(false).with_format(ArchiveFormat::SelfContained).with_split_size(Some(100));
+        let missing = dir.path().join("missing.txt");
+        match codec.encode_file(missing.to_str().unwrap(), dir.path().join("out.babel").to_str()) {
+            Err(BabelError::UnsupportedOptions(reason)) => assert!(reason.contains("split"), "{}", reason),
+            other => panic!("expected the split to be refused, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use rand::seq::SliceRandom;
+use rayon::prelude::*;
+use std::borrow::Cow;
+
+use crate::archive::Archive;
+use crate::codec::BabelCodec;
+use crate::error::BabelError;
+use crate::library::{get_page, search_exact, split_address};
+use crate::location::Location;
+
+impl BabelCodec {
+    /// A copy of `archive` with its existing locations dealt out to different pages.
+    ///
+    /// Every page keeps its content and the archive uses exactly the same
+    /// locations as before, so its location footprint is unchanged; only which
+    /// page sits at which location is permuted. Each new address is found with
+    /// the page length the archive records, or the codec's if it records none.
+    /// A seeded codec deals the same permutation every time.
+    pub fn shuffle_locations(&self, archive: &Archive) -> Result<Archive, BabelError> {
+        if let Cow::Owned(codec) = self.for_header(&archive.header)? {
+            return codec.shuffle_locations(archive);
+        }
+        let space = self.space()?;
+        // The header pages of a self-contained archive have to stay in front
+        let header_pages = self.embedded_header(archive)?.map_or(0, |(_, pages)| pages);
+        let (header, pages) = archive.addresses().split_at(header_pages);
+
+        let mut locations = pages
+            .iter()
+            .map(|address| split_address(address).map(|(_, location)| location))
+            .collect::<Result<Vec<Location>, _>>()?;
+
+        let original = locations.clone();
+        locations.shuffle(&mut self.rng_for(0, 2));
+        // A shuffle can land back on the identity; any rotation moves at least
+        // one page as long as two locations differ
+        if locations == original {
+            locations.rotate_left(1);
+        }
+
+        let readdress = |(address, location): (&String, &Location)| {
+            Ok(search_exact(&get_page(&address.parse()?, space)?, space, location)?.to_string_in(&space.geometry))
+        };
+        let readdressed: Vec<String> = if pages.len() >= self.min_parallel_pages {
+            self.in_pool(|| pages.par_iter().zip(&locations).map(readdress).collect::<Result<_, BabelError>>())?
+        } else {
+            pages.iter().zip(&locations).map(readdress).collect::<Result<_, BabelError>>()?
+        };
+
+        Ok(Archive { addresses: [header, &readdressed].concat(), ..archive.clone() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page_length::PageLength;
+
+    fn locations(archive: &Archive) -> Vec<(u32, u32, u32, u32)> {
+        archive
+            .addresses()
+            .iter()
+            .map(|address| {
+                let (_, l) = split_address(address).unwrap();
+                (l.wall, l.shelf, l.volume, l.page)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shuffling_permutes_locations_and_keeps_the_bytes() {
+        let codec = BabelCodec::default().with_progress(false).with_seed(Some(234)).with_page_length(PageLength::new(20).unwrap());
+        let bytes = b"six pages of ten bytes each, for dealing out.";
+        let mut encoded = Vec::new();
+        codec.encode_reader(&bytes[..], "txt", &mut encoded).unwrap();
+        let archive = Archive::read(&encoded[..]).unwrap();
+        let shuffled = codec.shuffle_locations(&archive).unwrap();
+
+        let (mut before, mut after) = (locations(&archive), locations(&shuffled));
+        assert_ne!(before, after);
+        before.sort_unstable();
+        after.sort_unstable();
+        assert_eq!(before, after);
+
+        let mut decoded = Vec::new();
+        codec.decode_archive(&shuffled, &mut decoded).unwrap();
+        assert_eq!(decoded, bytes);
+
+        assert_eq!(codec.shuffle_locations(&archive).unwrap(), shuffled);
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::archive::ADDRESS_DELIMITER;
+use crate::temp_file::TempFile;
+
+/// Address lines written to disk as each batch is located, so an encode
+/// never holds more than a batch of addresses in memory.
+///
+/// The header of a text archive needs the final size and page checksums, so
+/// it can only be written once the input runs dry; the spooled lines are
+/// then copied in after it. The spool lives at `<path>.tmp` and is removed
+/// when dropped.
+pub(crate) struct AddressSpool {
+    file: BufWriter<TempFile>,
+    pages_per_line: usize,
+    line: Vec<String>,
+}
+
+impl AddressSpool {
+    pub(crate) fn create(path: &Path, pages_per_line: usize) -> io::Result<Self> {
+        Ok(AddressSpool {
+            file: BufWriter::new(TempFile::create(path)?),
+            pages_per_line: pages_per_line.max(1),
+            line: Vec::new(),
+        })
+    }
+
+    pub(crate) fn push(&mut self, addresses: Vec<String>) -> io::Result<()> {
+        for address in addresses {
+            self.line.push(address);
+            if self.line.len() == self.pages_per_line {
+                self.write_line()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_line(&mut self) -> io::Result<()> {
+        writeln!(self.file, "{}", self.line.join(&ADDRESS_DELIMITER.to_string()))?;
+        self.line.clear();
+        Ok(())
+    }
+
+    // Finish any partial last line and copy every line to `writer`
+    pub(crate) fn copy_to<W: Write>(mut self, writer: &mut W) -> io::Result<u64> {
+        if !self.line.is_empty() {
+            self.write_line()?;
+        }
+        let mut temp = self.file.into_inner().map_err(|e| e.into_error())?;
+        let mut lines = temp.read_back()?;
+        io::copy(&mut lines, writer)
+    }
+}
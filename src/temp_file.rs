@@ -0,0 +1,111 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// An output file written under a temporary name and moved into place on success.
+///
+/// Until [`TempFile::commit`] is called the data lives at `<path>.tmp`, which
+/// is removed when the guard is dropped. An error part way through a write
+/// therefore never leaves a truncated file at the real path, and a process
+/// killed before dropping the guard leaves only the `.tmp` file behind.
+pub(crate) struct TempFile {
+    path: PathBuf,
+    temp_path: PathBuf,
+    file: Option<fs::File>,
+}
+
+impl TempFile {
+    pub(crate) fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut temp_name = path.file_name().map(OsString::from).unwrap_or_default();
+        temp_name.push(".tmp");
+        let temp_path = path.with_file_name(temp_name);
+        let file = fs::File::create(&temp_path)?;
+        Ok(TempFile { path, temp_path, file: Some(file) })
+    }
+
+    /// Flush the data to disk and rename it to the real path.
+    pub(crate) fn commit(mut self) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        fs::rename(&self.temp_path, &self.path)
+    }
+
+    // Flush what was written and open it again for reading
+    pub(crate) fn read_back(&mut self) -> io::Result<fs::File> {
+        self.file().flush()?;
+        fs::File::open(&self.temp_path)
+    }
+
+    fn file(&mut self) -> &mut fs::File {
+        self.file.as_mut().expect("temp file used after commit")
+    }
+}
+
+impl Write for TempFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file().flush()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // Committed guards have already been renamed away
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::BabelCodec;
+    use crate::error::BabelError;
+    use crate::page_length::PageLength;
+    use crate::text_mode::PayloadMode;
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+        names.sort();
+        names
+    }
+
+    // The archive and its address spool are both half written when the input turns bad
+    #[test]
+    fn a_failed_encode_leaves_no_output_behind() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let input = dir.path().join("input.txt");
+        let mut text = "plain page text ".repeat(200).into_bytes();
+        text.extend_from_slice(b"BUT THEN CAPITALS");
+        fs::write(&input, text).unwrap();
+        let output = dir.path().join("input.babel");
+
+        let codec = BabelCodec::default()
+            .with_progress(false)
+            .with_payload_mode(PayloadMode::Text)
+            .with_page_length(PageLength::new(20).unwrap());
+        let result = codec.encode_file(input.to_str().unwrap(), output.to_str());
+        assert!(matches!(result, Err(BabelError::InvalidTextChar { position: 3200, .. })), "{:?}", result);
+        assert_eq!(names(dir.path()), ["input.txt"]);
+    }
+
+    #[test]
+    fn a_failed_write_leaves_no_output_behind() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let input = dir.path().join("input.txt");
+        fs::write(&input, b"never written").unwrap();
+        let output = dir.path().join("input.babel");
+        // The address spool cannot be created over a directory
+        fs::create_dir(dir.path().join("input.babel.addresses.tmp")).unwrap();
+
+        assert!(BabelCodec::default().with_progress(false).encode_file(input.to_str().unwrap(), output.to_str()).is_err());
+        assert_eq!(names(dir.path()), ["input.babel.addresses.tmp", "input.txt"]);
+    }
+}
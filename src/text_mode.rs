@@ -0,0 +1,105 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::error::BabelError;
+use crate::library::CHARSET;
+
+/// What the characters on a page stand for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadMode {
+    /// Any bytes, spelled in blocks of alphabet symbols: two per byte, or
+    /// five per three bytes in base29.
+    #[default]
+    Bytes,
+    /// Plain text written onto pages as-is, one character per byte. Only the
+    /// page characters (`a`-`z`, space, comma and period) can be stored.
+    Text,
+}
+
+impl PayloadMode {
+    pub(crate) fn is_bytes(&self) -> bool {
+        *self == PayloadMode::Bytes
+    }
+}
+
+/// How text mode treats input characters that cannot appear on a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnInvalidChar {
+    /// Stop encoding at the first such character.
+    #[default]
+    Error,
+    /// Drop the character and warn with its byte offset.
+    SkipWithWarning,
+    /// Lowercase letters and fold whitespace and punctuation onto the nearest
+    /// page character; anything left over is skipped with a warning.
+    MapToClosest,
+}
+
+fn closest_page_char(byte: u8) -> Option<u8> {
+    match byte {
+        b'A'..=b'Z' => Some(byte.to_ascii_lowercase()),
+        b'\t' | b'\n' | b'\r' | 0x0b | 0x0c => Some(b' '),
+        b'!' | b'?' => Some(b'.'),
+        b';' | b':' => Some(b','),
+        _ => None,
+    }
+}
+
+// Turn text-mode input into page characters, `offset` being the stream position of `bytes[0]`
+pub(crate) fn to_page_text(bytes: &[u8], offset: u64, policy: OnInvalidChar) -> Result<String, BabelError> {
+    map_page_text(bytes, offset, policy, true)
+}
+
+// `to_page_text` without the warning, for re-reading input that was already encoded
+pub(crate) fn map_page_text(bytes: &[u8], offset: u64, policy: OnInvalidChar, warn: bool) -> Result<String, BabelError> {
+    let mut text = String::with_capacity(bytes.len());
+    let mut skipped = Vec::new();
+
+    for (i, &byte) in bytes.iter().enumerate() {
+        let position = offset + i as u64;
+        if CHARSET.as_bytes().contains(&byte) {
+            text.push(byte as char);
+            continue;
+        }
+        match policy {
+            OnInvalidChar::Error => return Err(BabelError::InvalidTextChar { position, byte }),
+            OnInvalidChar::SkipWithWarning => skipped.push(position),
+            OnInvalidChar::MapToClosest => match closest_page_char(byte) {
+                Some(c) => text.push(c as char),
+                None => skipped.push(position),
+            },
+        }
+    }
+
+    if warn && !skipped.is_empty() {
+        warn!("skipped {} characters not allowed on a page at byte offsets {:?}", skipped.len(), skipped);
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An uppercase letter at offset 10 and a digit at offset 12
+    const INPUT: &[u8] = b"the page, Ax7 ends.";
+
+    #[test]
+    fn error_stops_at_the_first_invalid_char() {
+        match to_page_text(INPUT, 100, OnInvalidChar::Error) {
+            Err(BabelError::InvalidTextChar { position, byte }) => assert_eq!((position, byte), (110, b'A')),
+            other => panic!("expected an invalid character, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn skip_drops_the_letter_and_the_digit() {
+        assert_eq!(to_page_text(INPUT, 0, OnInvalidChar::SkipWithWarning).unwrap(), "the page, x ends.");
+    }
+
+    #[test]
+    fn map_lowercases_the_letter_and_skips_the_digit() {
+        assert_eq!(to_page_text(INPUT, 0, OnInvalidChar::MapToClosest).unwrap(), "the page, ax ends.");
+    }
+}
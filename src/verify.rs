@@ -0,0 +1,213 @@
+use rayon::prelude::*;
+use std::fmt;
+use std::io::{BufReader, Read};
+
+use crate::address::Address;
+use crate::archive::Archive;
+use crate::codec::BabelCodec;
+use crate::error::BabelError;
+use crate::library::{verify_page, PageSpace};
+use crate::text_mode::{self, PayloadMode};
+
+/// How much checking an encode does that each address reproduces its page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VerifyMode {
+    /// Read each page back from its address once its batch is located.
+    #[default]
+    Full,
+    /// Trust the arithmetic and never resolve a page during encode.
+    None,
+    /// Skip the page checks and instead decode the finished archive back to
+    /// bytes, comparing it with the input.
+    ///
+    /// This catches mistakes in chunking, padding and truncation that no
+    /// single page can show, at the cost of generating every page again.
+    FullBytes,
+}
+
+/// Outcome of checking that each page's address resolves back to its content.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyStats {
+    pub total: usize,
+    pub passed: usize,
+    /// Indices of the pages that failed, in page order.
+    pub failed_indices: Vec<usize>,
+}
+
+impl VerifyStats {
+    pub fn all_passed(&self) -> bool {
+        self.failed_indices.is_empty()
+    }
+
+    // Fold in the stats of a later batch of pages
+    pub(crate) fn merge(&mut self, other: VerifyStats) {
+        self.total += other.total;
+        self.passed += other.passed;
+        self.failed_indices.extend(other.failed_indices);
+    }
+}
+
+impl fmt::Display for VerifyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{} pages verified", self.passed, self.total)?;
+        if !self.all_passed() {
+            write!(f, ", failed pages: {:?}", self.failed_indices)?;
+        }
+        Ok(())
+    }
+}
+
+// Check each page against its address, numbering pages from `first_index`
+pub(crate) fn verify_pages(
+    pages: &[String],
+    addresses: &[Address],
+    space: &PageSpace,
+    first_index: usize,
+    parallel: bool,
+) -> VerifyStats {
+    let check = |(i, (page, address)): (usize, (&String, &Address))| {
+        (!verify_page(page, address, space)).then_some(first_index + i)
+    };
+
+    let failed_indices: Vec<usize> = if parallel {
+        pages.par_iter().zip(addresses).enumerate().filter_map(check).collect()
+    } else {
+        pages.iter().zip(addresses).enumerate().filter_map(check).collect()
+    };
+
+    VerifyStats {
+        total: pages.len(),
+        passed: pages.len() - failed_indices.len(),
+        failed_indices,
+    }
+}
+
+impl BabelCodec {
+    // Decode `archive` and fail each page whose bytes differ from `original`
+    //
+    // Text-mode input is mapped with the codec's invalid-character policy
+    // first, and compressed input is compressed the same way, so only what
+    // the encode meant to put on the pages is compared.
+    pub(crate) fn verify_bytes<R: Read>(&self, archive: &Archive, original: R) -> Result<VerifyStats, BabelError> {
+        let chunks = self.decode_chunks(archive)?;
+        let payload = chunks.header().payload();
+        let mut original = chunks.header().compression().compress(original)?;
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut expected = Vec::new();
+        let mut read = 0u64;
+        let mut exhausted = false;
+
+        let mut fill = |expected: &mut Vec<u8>, exhausted: &mut bool| -> Result<(), BabelError> {
+            let n = original.read(&mut buf)?;
+            *exhausted = n == 0;
+            match payload {
+                PayloadMode::Bytes => expected.extend_from_slice(&buf[..n]),
+                PayloadMode::Text => expected.extend_from_slice(
+                    text_mode::map_page_text(&buf[..n], read, self.on_invalid_char, false)?.as_bytes(),
+                ),
+            }
+            read += n as u64;
+            Ok(())
+        };
+
+        let mut stats = VerifyStats::default();
+        for (index, chunk) in chunks.enumerate() {
+            let chunk = chunk?;
+            while expected.len() < chunk.len() && !exhausted {
+                fill(&mut expected, &mut exhausted)?;
+            }
+            let take = chunk.len().min(expected.len());
+            stats.total += 1;
+            if expected.drain(..take).eq(chunk.iter().copied()) {
+                stats.passed += 1;
+            } else {
+                stats.failed_indices.push(index);
+            }
+        }
+
+        // Input past the last page was lost by the encode
+        while expected.is_empty() && !exhausted {
+            fill(&mut expected, &mut exhausted)?;
+        }
+        if !expected.is_empty() {
+            let last = stats.total.saturating_sub(1);
+            if stats.failed_indices.last() != Some(&last) {
+                stats.passed = stats.passed.saturating_sub(1);
+                stats.failed_indices.push(last);
+            }
+            stats.total = stats.total.max(1);
+        }
+        Ok(stats)
+    }
+
+    /// Offset of the first byte where `archive` decodes to something other
+    /// than `original`, or `None` when the two are identical.
+    ///
+    /// Pages are decoded and compared one at a time, so neither side is held
+    /// in memory whole. When one is a prefix of the other the offset is the
+    /// length of the shorter. A compressed archive is compared after
+    /// decompressing it.
+    pub fn first_difference<R: Read>(&self, archive: &Archive, original: R) -> Result<Option<u64>, BabelError> {
+        let mut decoded = self.reader(archive)?;
+        let mut original = BufReader::new(original);
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut offset = 0u64;
+        loop {
+            let n = decoded.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            let chunk = &buf[..n];
+            let mut expected = Vec::with_capacity(n);
+            original.by_ref().take(n as u64).read_to_end(&mut expected)?;
+            if let Some(i) = chunk.iter().zip(&expected).position(|(decoded, byte)| decoded != byte) {
+                return Ok(Some(offset + i as u64));
+            }
+            if expected.len() < chunk.len() {
+                return Ok(Some(offset + expected.len() as u64));
+            }
+            offset += chunk.len() as u64;
+        }
+        // Input past the end of the archive was never encoded
+        let mut byte = [0u8; 1];
+        Ok((original.read(&mut byte)? > 0).then_some(offset))
+    }
+
+    // `verify_bytes`, failing unless every page matched
+    pub(crate) fn check_bytes<R: Read>(&self, archive: &Archive, original: R) -> Result<VerifyStats, BabelError> {
+        let stats = self.verify_bytes(archive, original)?;
+        if !stats.all_passed() {
+            return Err(BabelError::VerificationFailed(stats));
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::search_exact;
+    use crate::location::{LibraryGeometry, Location};
+
+    #[test]
+    fn every_failing_page_is_listed() {
+        let space = PageSpace::new(20, LibraryGeometry::default()).unwrap();
+        let mut pages: Vec<String> = ["first", "second", "third", "fourth", "fifth"]
+            .iter()
+            .map(|word| format!("{:a<20}", word))
+            .collect();
+        let addresses: Vec<Address> = pages
+            .iter()
+            .enumerate()
+            .map(|(i, page)| search_exact(page, &space, &Location { wall: 0, shelf: 1, volume: 2, page: i as u32 }).unwrap())
+            .collect();
+        pages[1] = format!("{:a<20}", "not the second");
+        pages[3] = format!("{:a<20}", "not the fourth");
+
+        for parallel in [false, true] {
+            let stats = verify_pages(&pages, &addresses, &space, 10, parallel);
+            assert_eq!(stats.failed_indices, [11, 13]);
+            assert_eq!((stats.total, stats.passed), (5, 3));
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use assert_cmd::Command;
+use babel_encoding::{Alphabet, BabelCodec};
+use predicates::prelude::*;
+use tempfile::TempDir;
+
+const CONTENTS: &[u8] = b"Every book in the library, and this file besides.\n";
+
+fn babel() -> Command {
+    Command::cargo_bin("babel-encoding").unwrap()
+}
+
+// A scratch directory holding `input.txt`
+fn scratch() -> (TempDir, PathBuf) {
+    let dir = TempDir::new().unwrap();
+    let input = dir.path().join("input.txt");
+    fs::write(&input, CONTENTS).unwrap();
+    (dir, input)
+}
+
+fn encode(input: &Path, archive: &Path) {
+    babel().arg("encode").arg(input).arg(archive).assert().success();
+}
+
+#[test]
+fn encode_then_decode_round_trips() {
+    let (dir, input) = scratch();
+    let archive = dir.path().join("input.babel");
+    let output = dir.path().join("output.txt");
+    encode(&input, &archive);
+    assert!(fs::read_to_string(&archive).unwrap().starts_with("babel/2\n"));
+
+    babel().arg("decode").arg(&archive).arg(&output).assert().success();
+    assert_eq!(fs::read(&output).unwrap(), CONTENTS);
+}
+
+#[test]
+fn empty_and_one_byte_files_round_trip() {
+    let dir = TempDir::new().unwrap();
+    for (name, contents) in [("empty.txt", &b""[..]), ("one.txt", b"1")] {
+        let input = dir.path().join(name);
+        let archive = input.with_extension("babel");
+        let output = input.with_extension("out");
+        fs::write(&input, contents).unwrap();
+        encode(&input, &archive);
+        babel().arg("stats").arg(&archive)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(format!("Original size: {} bytes", contents.len())));
+
+        babel().arg("decode").arg(&archive).arg(&output).assert().success();
+        assert_eq!(fs::read(&output).unwrap(), contents);
+    }
+}
+
+#[test]
+fn encode_defaults_to_a_babel_file_beside_the_input() {
+    let (dir, input) = scratch();
+    babel().arg("encode").arg(&input).assert().success();
+    assert!(dir.path().join("input.babel").exists());
+}
+
+#[test]
+fn stdin_and_stdout_round_trip() {
+    let archive = babel().args(["encode", "-", "--extension", "txt"]).write_stdin(CONTENTS).assert().success();
+    let archive = archive.get_output().stdout.clone();
+    babel().args(["decode", "-"]).write_stdin(archive).assert().success().stdout(CONTENTS);
+}
+
+#[test]
+fn verify_compares_with_the_original() {
+    let (dir, input) = scratch();
+    let archive = dir.path().join("input.babel");
+    encode(&input, &archive);
+    babel().arg("verify").arg(&archive).arg(&input)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Archive reproduces"));
+
+    let other = dir.path().join("other.txt");
+    fs::write(&other, b"Every book in the library, and that file besides.\n").unwrap();
+    babel().arg("verify").arg(&archive).arg(&other)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("differ").and(predicate::str::contains("at byte 33")));
+}
+
+#[test]
+fn stats_prints_the_header() {
+    let (dir, input) = scratch();
+    let archive = dir.path().join("input.babel");
+    babel().arg("encode").arg(&input).arg(&archive).args(["--note", "a test"]).assert().success();
+    babel().arg("stats").arg(&archive)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("Original size: {} bytes", CONTENTS.len())))
+        .stdout(predicate::str::contains("Pages: 1"))
+        .stdout(predicate::str::contains("a test"));
+}
+
+// Base29 spells three bytes in five characters, not six
+#[test]
+fn stats_json_counts_the_text_each_encoding_spells() {
+    let dir = TempDir::new().unwrap();
+    let input = dir.path().join("input.bin");
+    fs::write(&input, [7u8; 300]).unwrap();
+    for (encoding, text_length) in [("letters26", 600), ("base29", 500)] {
+        let archive = dir.path().join(format!("{}.babel", encoding));
+        let stats = dir.path().join(format!("{}.json", encoding));
+        babel().arg("encode").arg(&input).arg(&archive)
+            .args(["--byte-encoding", encoding, "--page-length", "100", "--stats-json"])
+            .arg(&stats)
+            .assert()
+            .success();
+        let summary: serde_json::Value = serde_json::from_str(&fs::read_to_string(&stats).unwrap()).unwrap();
+        assert_eq!(summary["babel_text_length"], text_length, "{}", encoding);
+        assert_eq!(summary["pages"], text_length / 100, "{}", encoding);
+        assert_eq!(summary["original_size"], 300, "{}", encoding);
+    }
+}
+
+#[test]
+fn text_and_untext_round_trip_every_byte_encoding() {
+    let bytes: Vec<u8> = (0..=255).chain([0, 255, 1]).collect();
+    for encoding in ["letters26", "hex16", "base29"] {
+        for len in [0, 1, 3, 6, bytes.len()] {
+            let spelled = babel().args(["text", "-", "--byte-encoding", encoding]).write_stdin(&bytes[..len]).assert().success();
+            let text = String::from_utf8(spelled.get_output().stdout.clone()).unwrap();
+            babel().args(["untext", "-", "--byte-encoding", encoding])
+                .write_stdin(text.clone())
+                .assert()
+                .success()
+                .stdout(bytes[..len].to_vec());
+
+            // Padding that is not periods is cut off at the size
+            let padded = format!("{}zzzz\n", text.trim_end());
+            babel().args(["untext", "-", "--byte-encoding", encoding, "--size", &len.to_string()])
+                .write_stdin(padded)
+                .assert()
+                .success()
+                .stdout(bytes[..len].to_vec());
+        }
+    }
+}
+
+#[test]
+fn doctor_passes_a_good_archive_and_flags_a_bad_address() {
+    let (dir, input) = scratch();
+    let archive = dir.path().join("input.babel");
+    encode(&input, &archive);
+    babel().arg("doctor").arg(&archive).assert().success().stdout("No problems found\n");
+
+    let text = fs::read_to_string(&archive).unwrap();
+    let address = text.lines().last().unwrap();
+    fs::write(&archive, text.replace(address, "NOT-AN-ADDRESS")).unwrap();
+    babel().arg("doctor").arg(&archive).assert().failure().stdout(predicate::str::is_empty().not());
+}
+
+// Coordinates past a byte or two are packed wider, not truncated
+#[test]
+fn compact_json_round_trips_a_large_geometry() {
+    let (dir, input) = scratch();
+    let archive = dir.path().join("input.babel");
+    let output = dir.path().join("output.txt");
+    babel().arg("encode").arg(&input).arg(&archive)
+        .args(["--compact-json", "--geometry", "300,5,70000,410"])
+        .assert()
+        .success();
+    assert!(fs::read_to_string(&archive).unwrap().contains("index_base64"));
+
+    babel().arg("decode").arg(&archive).arg(&output).assert().success();
+    assert_eq!(fs::read(&output).unwrap(), CONTENTS);
+}
+
+#[test]
+fn split_parts_stay_under_the_limit_and_reassemble() {
+    let (dir, input) = scratch();
+    let archive = dir.path().join("out.babel");
+    let output = dir.path().join("output.txt");
+    babel().arg("encode").arg(&input).arg(&archive)
+        .args(["--page-length", "10", "--split-size", "100"])
+        .assert()
+        .success();
+
+    let parts: Vec<PathBuf> = (1..).map(|i| dir.path().join(format!("out.part{:03}.babel", i))).take_while(|part| part.exists()).collect();
+    assert!(parts.len() > 2, "{} parts", parts.len());
+    for part in &parts {
+        assert!(fs::metadata(part).unwrap().len() <= 100, "{}", part.display());
+    }
+
+    babel().arg("decode").arg(&archive).arg(&output).assert().success();
+    assert_eq!(fs::read(&output).unwrap(), CONTENTS);
+}
+
+#[test]
+fn single_job_encodes_decode_back() {
+    let (dir, input) = scratch();
+    let archive = dir.path().join("input.babel");
+    let output = dir.path().join("output.txt");
+    babel().args(["encode", "--jobs", "1", "--page-length", "10"]).arg(&input).arg(&archive).assert().success();
+    babel().arg("decode").arg(&archive).arg(&output).args(["--jobs", "2"]).assert().success();
+    assert_eq!(fs::read(&output).unwrap(), CONTENTS);
+
+    // `-j` is --jobs, so the input stays the input
+    babel().args(["encode", "-j", "4"]).arg(&input).arg(&archive).assert().success();
+    assert_eq!(fs::read(&input).unwrap(), CONTENTS);
+    assert!(fs::read_to_string(&archive).unwrap().starts_with("babel/"));
+
+    babel().args(["encode", "--jobs", "0"]).arg(&input).arg(&archive).assert().code(2);
+}
+
+#[test]
+fn page_lengths_are_checked_against_the_alphabets_block() {
+    let (dir, input) = scratch();
+    let archive = dir.path().join("input.babel");
+    let output = dir.path().join("output.txt");
+    let text = dir.path().join("text.txt");
+    fs::write(&text, "an odd page length is fine for text, one character a byte").unwrap();
+    babel().args(["encode", "--text", "--page-length", "11"]).arg(&text).arg(&archive).assert().success();
+    babel().arg("decode").arg(&archive).arg(&output).assert().success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&text).unwrap());
+
+    babel().args(["encode", "--byte-encoding", "base29", "--page-length", "15"]).arg(&input).arg(&archive).assert().success();
+    babel().arg("decode").arg(&archive).arg(&output).assert().success();
+    assert_eq!(fs::read(&output).unwrap(), CONTENTS);
+
+    babel().args(["encode", "--byte-encoding", "base29", "--page-length", "12"]).arg(&input).arg(&archive).assert().code(2);
+    babel().args(["encode", "--page-length", "11"]).arg(&input).arg(&archive).assert().code(2);
+}
+
+// Real content ending in periods must not be taken for the padding
+#[test]
+fn pad_chars_leave_trailing_periods_alone() {
+    let dir = TempDir::new().unwrap();
+    // 840 is `..` in the last two base29 digits
+    let spelled = BabelCodec::default().with_alphabet(Alphabet::base29()).bytes_to_babel_text(&[0x00, 0x03, 0x48]);
+    assert!(spelled.ends_with(".."), "{}", spelled);
+    let cases: [(&[u8], &[&str]); 3] = [
+        (b"ends in periods...", &["--text"]),
+        (b"\x00\x03\x48", &["--byte-encoding", "base29"]),
+        (b"\x00\x03\x48\x00\x03\x48", &["--byte-encoding", "base29", "--page-length", "15"]),
+    ];
+    for (contents, flags) in cases {
+        for pad in [None, Some("x"), Some(".")] {
+            let input = dir.path().join("input.txt");
+            let archive = dir.path().join("input.babel");
+            let output = dir.path().join("output.txt");
+            fs::write(&input, contents).unwrap();
+            let mut command = babel();
+            command.arg("encode").arg(&input).arg(&archive).args(flags);
+            if let Some(pad) = pad {
+                command.args(["--pad-char", pad]);
+            }
+            command.assert().success();
+            babel().arg("decode").arg(&archive).arg(&output).assert().success();
+            assert_eq!(fs::read(&output).unwrap(), contents, "{:?} {:?}", flags, pad);
+        }
+    }
+
+    babel().arg("encode").arg(dir.path().join("input.txt")).args(["--pad-strategy", "zero", "--pad-char", "x"]).assert().code(2);
+}
+
+#[test]
+fn benchmark_reports_throughput() {
+    let run = babel().args(["benchmark", "--size", "4000", "--warmup", "0", "--iterations", "1"]).assert().success();
+    let stdout = String::from_utf8(run.get_output().stdout.clone()).unwrap();
+    for label in ["Encode: ", "Decode: "] {
+        let line = stdout.lines().find(|line| line.starts_with(label)).unwrap_or_else(|| panic!("no {:?} in {}", label, stdout));
+        let rate: f64 = line[label.len()..].split(' ').next().unwrap().parse().unwrap();
+        assert!(rate > 0.0, "{}", line);
+    }
+}
+
+// Stats must not inflate the index, so a scrambled one only stops decode
+#[test]
+fn stats_reads_a_compressed_index_header_alone() {
+    let (dir, input) = scratch();
+    let archive = dir.path().join("input.babel");
+    let output = dir.path().join("output.txt");
+    babel().arg("encode").arg(&input).arg(&archive).arg("--compress-index").assert().success();
+    babel().arg("decode").arg(&archive).arg(&output).assert().success();
+    assert_eq!(fs::read(&output).unwrap(), CONTENTS);
+
+    let mut bytes = fs::read(&archive).unwrap();
+    let key = b"\nindex_length=";
+    let header_end = bytes.windows(key.len()).position(|w| w == key).unwrap() + key.len();
+    let index_start = header_end + bytes[header_end..].iter().position(|&b| b == b'\n').unwrap() + 1;
+    bytes[index_start..].fill(0xff);
+    fs::write(&archive, bytes).unwrap();
+
+    babel().arg("stats").arg(&archive)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("Original size: {} bytes", CONTENTS.len())));
+    babel().arg("decode").arg(&archive).arg(&output).assert().failure();
+}
+
+#[test]
+fn legacy_flags_run_the_subcommands() {
+    let (dir, input) = scratch();
+    let archive = dir.path().join("input.babel");
+    let output = dir.path().join("output.txt");
+    babel().arg("--encode").arg(&input).arg(&archive).assert().success();
+    babel().arg("--decode").arg(&archive).arg(&output).assert().success();
+    assert_eq!(fs::read(&output).unwrap(), CONTENTS);
+
+    babel().arg("--stats").arg(&archive).assert().success().stdout(predicate::str::contains("Pages: 1"));
+    babel().arg("--find").arg(&archive).arg("this file")
+        .assert()
+        .success()
+        .stdout("Found at byte offset 31\n");
+}
+
+#[test]
+fn unknown_flags_are_usage_errors() {
+    babel().args(["encode", "input.txt", "--no-such-flag"]).assert().code(2);
+    babel().assert().code(2);
+}
+
+#[test]
+fn dry_run_times_a_page_only_when_asked() {
+    let (dir, input) = scratch();
+    babel().arg("encode").arg(&input).arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Pages: 1").and(predicate::str::contains("Time:").not()));
+    babel().arg("encode").arg(&input).arg("--dry-run").arg("--time-estimate")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Time: about"));
+    babel().arg("encode").arg(&input).arg("--time-estimate").assert().failure();
+    assert!(!dir.path().join("input.babel").exists());
+}